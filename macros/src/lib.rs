@@ -0,0 +1,158 @@
+//! Test attribute macro for `testcontainers-rs`.
+//!
+//! Use it through the `macros` feature of `testcontainers-rs` as `#[testcontainers_rs::test]`.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Error, Expr, FnArg, ItemFn, Pat, Type};
+
+/// Runs an async test with started containers injected as parameters.
+///
+/// Every parameter must be a container or an image annotated with the image to use:
+///
+/// ```ignore
+/// #[testcontainers_rs::test]
+/// async fn ping(
+///     #[image(DockerImage::new("redis"))] redis: Container<Client>,
+///     #[image(DockerImage::new("redis").with_tag("7"))] next: DockerImage,
+/// ) {
+///     // ...
+/// }
+/// ```
+///
+/// The containers are created with a client obtained from
+/// `testcontainers_rs::testing::Connect`, started and waited for before the test
+/// body runs.
+/// They are removed after the test, also when it panics, and their logs are
+/// printed when the test fails.
+/// Images are passed in as is, so the test can start them itself.
+#[proc_macro_attribute]
+pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
+    let func = parse_macro_input!(item as ItemFn);
+    match expand(args.into(), func) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(args: proc_macro2::TokenStream, func: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    if !args.is_empty() {
+        return Err(Error::new_spanned(
+            args,
+            "#[testcontainers_rs::test] does not take arguments",
+        ));
+    }
+    if func.sig.asyncness.is_none() {
+        return Err(Error::new_spanned(
+            func.sig.fn_token,
+            "#[testcontainers_rs::test] requires an async fn",
+        ));
+    }
+
+    let mut containers = Vec::new();
+    let mut images = Vec::new();
+    for input in &func.sig.inputs {
+        let input = match input {
+            FnArg::Typed(input) => input,
+            FnArg::Receiver(receiver) => {
+                return Err(Error::new_spanned(receiver, "tests cannot take self"));
+            }
+        };
+        let ident = match &*input.pat {
+            Pat::Ident(pat) => pat.ident.clone(),
+            pat => {
+                return Err(Error::new_spanned(
+                    pat,
+                    "container and image parameters must be plain identifiers",
+                ))
+            }
+        };
+        let image = input
+            .attrs
+            .iter()
+            .find(|attr| attr.path().is_ident("image"))
+            .ok_or_else(|| {
+                Error::new_spanned(
+                    input,
+                    "container and image parameters require an #[image(...)] attribute",
+                )
+            })?
+            .parse_args::<Expr>()?;
+        match Param::of(&input.ty)? {
+            Param::Container => {
+                containers.push((input.pat.clone(), ident, input.ty.clone(), image))
+            }
+            Param::Image => images.push((input.pat.clone(), input.ty.clone(), image)),
+        }
+    }
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = func;
+    let name = &sig.ident;
+    let output = &sig.output;
+    let generics = &sig.generics;
+    if !generics.params.is_empty() {
+        return Err(Error::new_spanned(generics, "tests cannot be generic"));
+    }
+
+    let images = images.iter().map(|(pat, ty, image)| {
+        quote! {
+            let #pat: #ty = ::core::convert::Into::into(#image);
+        }
+    });
+    let starts = containers.iter().map(|(pat, _, ty, image)| {
+        quote! {
+            let #pat: #ty = ::testcontainers_rs::testing::start(#image).await;
+        }
+    });
+    let watched = containers.iter().map(|(_, ident, _, _)| {
+        quote! { ::testcontainers_rs::testing::Watched::new(&#ident) }
+    });
+    let watched_ident = format_ident!("__testcontainers_watched", span = Span::mixed_site());
+
+    Ok(quote! {
+        #[::core::prelude::v1::test]
+        #(#attrs)*
+        #vis fn #name() #output {
+            ::testcontainers_rs::testing::block_on(async move {
+                #(#images)*
+                #(#starts)*
+                let #watched_ident = ::std::vec![#(#watched),*];
+                ::testcontainers_rs::testing::run(#watched_ident, async move #block).await
+            })
+        }
+    })
+}
+
+/// The kind of an injected parameter, told apart by the name of its type.
+enum Param {
+    /// A container that is started before the test body runs.
+    Container,
+    /// An image that is passed in unstarted.
+    Image,
+}
+
+impl Param {
+    fn of(ty: &Type) -> syn::Result<Self> {
+        let name = match ty {
+            Type::Path(path) => path.path.segments.last().map(|segment| &segment.ident),
+            _ => None,
+        };
+        match name {
+            Some(name) if name == "Container" => Ok(Self::Container),
+            Some(name) if name == "DockerImage" => Ok(Self::Image),
+            _ => Err(Error::new_spanned(
+                ty,
+                format!(
+                    "unsupported parameter type `{}`, expected `Container<_>` or `DockerImage`",
+                    quote!(#ty).to_string().replace(' ', "")
+                ),
+            )),
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Blocking wrappers around the async client and container API.
+//!
+//! The blocking [`Client`] owns a small tokio runtime that drives all docker
+//! requests, so containers can be used from plain `#[test]` functions.
+//! Like other blocking facades, these types must not be used from within an
+//! async runtime, since blocking on the owned runtime would panic there.
+
+use super::client::{bollard, DockerClient};
+use super::container::ExitStatus;
+use super::exec::ExecResult;
+use super::image::{DockerImage, IntoImage};
+use super::logs::{LogConsumer, LogFollower, LogFrame, LogStream, WaitError};
+use super::ports::Ports;
+use super::run::Output;
+use super::stats::{PeakUsage, Sample};
+use ::bollard::models::ContainerInspectResponse;
+use futures::StreamExt;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fmt, io, net};
+use tokio::runtime::Runtime;
+
+fn runtime() -> io::Result<Runtime> {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("testcontainers-blocking")
+        .enable_all()
+        .build()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to build runtime")]
+    Runtime(#[source] io::Error),
+
+    #[error(transparent)]
+    Client(#[from] bollard::Error),
+}
+
+/// A blocking docker client.
+pub struct Client<C = bollard::Client>
+where
+    C: DockerClient,
+{
+    inner: C,
+    runtime: Arc<Runtime>,
+}
+
+impl<C> fmt::Debug for Client<C>
+where
+    C: DockerClient + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl Client<bollard::Client> {
+    /// Connects to the local docker daemon.
+    pub fn new() -> Result<Self, Error> {
+        let runtime = runtime().map_err(Error::Runtime)?;
+        let inner = runtime.block_on(bollard::Client::new())?;
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+}
+
+impl<C> Client<C>
+where
+    C: DockerClient,
+{
+    /// Wraps an async client.
+    ///
+    /// The client should not be bound to another runtime, as its requests
+    /// are driven by the runtime owned by the blocking client.
+    pub fn from_async(inner: C) -> io::Result<Self> {
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime()?),
+        })
+    }
+
+    /// Returns the wrapped async client.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn native(&self) -> &C::Client {
+        self.inner.native()
+    }
+
+    /// Creates a container for the image.
+    pub fn create<I: IntoImage<C> + Send>(&self, image: I) -> Result<Container<C>, crate::Error> {
+        let inner = self.runtime.block_on(self.inner.create(image))?;
+        Ok(Container {
+            inner: Some(inner),
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// Runs a container until it exits and returns its exit status and output.
+    ///
+    /// A container that does not exit in time fails the [`Wait`](crate::error::Operation::Wait)
+    /// operation with a [`Timeout`](crate::error::ErrorKind::Timeout).
+    pub fn run_to_completion<I: IntoImage<C> + Send>(
+        &self,
+        image: I,
+    ) -> Result<Output, crate::Error> {
+        Ok(self.runtime.block_on(self.inner.run_to_completion(image))?)
+    }
+}
+
+/// A blocking iterator over the log lines of a container.
+pub struct LogIter<'a> {
+    stream: LogStream<'a>,
+    runtime: &'a Runtime,
+}
+
+impl<'a> fmt::Debug for LogIter<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogIter").finish()
+    }
+}
+
+impl<'a> LogIter<'a> {
+    /// Blocks until a line containing `message` is logged.
+    pub fn wait_for_message(self, message: &str) -> Result<(), WaitError> {
+        self.runtime.block_on(self.stream.wait_for_message(message))
+    }
+}
+
+impl<'a> Iterator for LogIter<'a> {
+    type Item = Result<String, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime.block_on(self.stream.next())
+    }
+}
+
+/// A blocking handle to a docker container.
+///
+/// The container is removed when dropped.
+pub struct Container<C = bollard::Client>
+where
+    C: DockerClient,
+{
+    /// Only `None` while the container is being dropped or removed.
+    inner: Option<super::Container<C>>,
+    runtime: Arc<Runtime>,
+}
+
+impl<C> fmt::Debug for Container<C>
+where
+    C: DockerClient,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Container")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<C> Container<C>
+where
+    C: DockerClient,
+{
+    /// Returns the wrapped async container.
+    pub fn inner(&self) -> &super::Container<C> {
+        self.inner
+            .as_ref()
+            .expect("container is only taken when dropped")
+    }
+
+    /// Returns the id of this container.
+    pub fn id(&self) -> &str {
+        self.inner().id()
+    }
+
+    /// Returns the image this container was created from.
+    pub fn image(&self) -> &DockerImage<C> {
+        self.inner().image()
+    }
+
+    /// Starts the container.
+    pub fn start(&self) -> Result<(), crate::Error> {
+        self.runtime.block_on(self.inner().start())
+    }
+
+    /// Stops the container
+    pub fn stop(&self) -> Result<(), crate::Error> {
+        self.runtime.block_on(self.inner().stop())
+    }
+
+    /// Stops the container, killing it if it does not exit within `timeout`.
+    pub fn stop_with_timeout(&self, timeout: Duration) -> Result<(), crate::Error> {
+        self.runtime
+            .block_on(self.inner().stop_with_timeout(timeout))
+    }
+
+    /// Freezes all processes of the container.
+    pub fn pause(&self) -> Result<(), crate::Error> {
+        self.runtime.block_on(self.inner().pause())
+    }
+
+    /// Resumes the processes of a paused container.
+    pub fn unpause(&self) -> Result<(), crate::Error> {
+        self.runtime.block_on(self.inner().unpause())
+    }
+
+    /// Restarts the container.
+    pub fn restart(&self) -> Result<(), crate::Error> {
+        self.runtime.block_on(self.inner().restart())
+    }
+
+    /// Sends a signal such as `SIGKILL` to the main process of the container.
+    pub fn kill(&self, signal: &str) -> Result<(), crate::Error> {
+        self.runtime.block_on(self.inner().kill(signal))
+    }
+
+    /// Waits until the container exits.
+    pub fn wait(&self) -> Result<ExitStatus, crate::Error> {
+        self.runtime.block_on(self.inner().wait())
+    }
+
+    /// Creates an image from the current filesystem of the container.
+    pub fn commit(&self, reference: &str) -> Result<DockerImage, crate::Error> {
+        self.runtime.block_on(self.inner().commit(reference))
+    }
+
+    /// Removes the container
+    pub fn rm(mut self) -> Result<(), crate::Error> {
+        let inner = self
+            .inner
+            .take()
+            .expect("container is only taken when dropped");
+        self.runtime.block_on(inner.rm())
+    }
+
+    /// Waits until all ready conditions of the image are met.
+    pub fn wait_until_ready(&self) -> Result<(), crate::Error> {
+        self.runtime.block_on(self.inner().wait_until_ready())
+    }
+
+    /// Returns low-level information about the container
+    pub fn inspect(&self) -> Result<ContainerInspectResponse, crate::Error> {
+        self.runtime.block_on(self.inner().inspect())
+    }
+
+    /// Executes a command inside the running container and collects its output
+    pub fn exec<I>(&self, cmd: I) -> Result<ExecResult, crate::Error>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.runtime.block_on(self.inner().exec(cmd))
+    }
+
+    /// Follows the stdout logs of the container
+    pub fn stdout_logs(&self) -> LogIter<'_> {
+        LogIter {
+            stream: self.inner().stdout_logs(),
+            runtime: &self.runtime,
+        }
+    }
+
+    /// Follows the stderr logs of the container
+    pub fn stderr_logs(&self) -> LogIter<'_> {
+        LogIter {
+            stream: self.inner().stderr_logs(),
+            runtime: &self.runtime,
+        }
+    }
+
+    /// Returns all stdout and stderr frames logged so far, in the order they were logged.
+    pub fn logs_snapshot(&self) -> Result<Vec<LogFrame>, io::Error> {
+        self.runtime.block_on(self.inner().logs_snapshot())
+    }
+
+    /// Forwards stdout and stderr of the container to `consumer` from a background task.
+    pub fn follow_logs<L: LogConsumer>(&self, consumer: L) -> LogFollower {
+        let _guard = self.runtime.enter();
+        self.inner().follow_logs(consumer)
+    }
+
+    /// Returns the current resource usage of the container.
+    pub fn stats_snapshot(&self) -> Result<Sample, crate::Error> {
+        self.runtime.block_on(self.inner().stats_snapshot())
+    }
+
+    /// Records the peak resource usage of the container from a background task.
+    pub fn track_peak_usage(&self) -> PeakUsage {
+        let _guard = self.runtime.enter();
+        self.inner().track_peak_usage()
+    }
+
+    /// Gets the host IP address of the container
+    pub fn host(&self) -> Result<net::IpAddr, crate::Error> {
+        self.runtime.block_on(self.inner().host())
+    }
+
+    /// Gets all port mappings of the container
+    pub fn ports(&self) -> Result<Ports, crate::Error> {
+        self.runtime.block_on(self.inner().ports())
+    }
+
+    /// Get the mapped host IPv4 port for the given internal port
+    pub fn mapped_port_ipv4(&self, internal_port: u16) -> Result<Option<u16>, crate::Error> {
+        self.runtime
+            .block_on(self.inner().mapped_port_ipv4(internal_port))
+    }
+
+    /// Get the mapped host IPv6 port for the given internal port
+    pub fn mapped_port_ipv6(&self, internal_port: u16) -> Result<Option<u16>, crate::Error> {
+        self.runtime
+            .block_on(self.inner().mapped_port_ipv6(internal_port))
+    }
+}
+
+impl<C> Drop for Container<C>
+where
+    C: DockerClient,
+{
+    fn drop(&mut self) {
+        // the async container removes itself on drop, which needs the runtime
+        // to drive the request
+        let _guard = self.runtime.enter();
+        self.inner.take();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::Client;
+    use crate::client::mock::{MockClient, Status};
+    use crate::{DockerImage, WaitFor};
+    use color_eyre::eyre;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn blocking_container_lifecycle() -> eyre::Result<()> {
+        let mock = MockClient::new().with_stdout("redis", ["Ready to accept connections"]);
+        let client = Client::from_async(mock.clone())?;
+        let image = DockerImage::new("redis")
+            .with_mapped_port(6379, 6379)
+            .with_wait_for(WaitFor::message_on_stdout("Ready"));
+
+        let container = client.create(image)?;
+        container.start()?;
+        container.wait_until_ready()?;
+        assert_eq!(container.mapped_port_ipv4(6379)?, Some(6379));
+        assert!(container.exec(["redis-cli", "ping"])?.success());
+
+        let lines: Vec<String> = container.stdout_logs().collect::<Result<_, _>>()?;
+        assert_eq!(lines, vec!["Ready to accept connections".to_string()]);
+
+        let id = container.id().to_string();
+        assert_eq!(mock.status(&id), Some(Status::Running));
+        drop(container);
+        assert_eq!(mock.status(&id), None);
+        Ok(())
+    }
+}
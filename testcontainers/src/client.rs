@@ -1,41 +1,209 @@
-use super::container::Container;
-use super::image::DockerImage;
-use super::logs::LogStream;
+use super::container::{Container, ExitStatus};
+use super::error::{Classify, Error, Operation};
+use super::exec::ExecResult;
+use super::hooks::{ContainerHook, HookError, Hooks};
+use super::host::{self, HostRoute};
+use super::image::{DockerImage, IntoImage};
+use super::logs::{FrameStream, LogOptions, LogStream};
+use super::mounts;
+use super::network::Network;
 use super::ports::Ports;
+use super::reuse;
+use super::run::{self, Output};
+use super::stats::{Sample, StatsStream};
+use super::trace;
+use super::volume::{Volume, VolumeConfig};
+use ::bollard::models::ContainerInspectResponse;
 use async_trait::async_trait;
 use std::net;
+use std::time::Duration;
+
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "record")]
+pub mod record;
 
 #[async_trait]
 pub trait DockerClient
 where
-    Self: Sized,
+    Self: Sized + Clone + Send + Sync + 'static,
 {
     type Client;
-    type Error: std::error::Error;
+    type Error: std::error::Error + From<HookError> + Classify + Send + Sync + 'static;
 
     fn native(&self) -> &Self::Client;
     fn stdout_logs(&self, id: &str) -> LogStream<'_>;
     fn stderr_logs(&self, id: &str) -> LogStream<'_>;
 
-    async fn create<I: Into<DockerImage> + Send>(
-        &self,
-        image: I,
-    ) -> Result<Container<Self>, Self::Error>;
+    /// Retrieves the logs selected by `options` as tagged frames.
+    fn logs(&self, id: &str, options: LogOptions) -> FrameStream<'_>;
+
+    /// Creates a container for the image, running its create hooks.
+    async fn create<I: IntoImage<Self> + Send>(&self, image: I) -> Result<Container<Self>, Error> {
+        let (mut image, hooks) = image.into_image().split_hooks();
+        let descriptor = image.descriptor();
+        let name = image.container_name.clone();
+        let span = trace::span!(
+            "testcontainers.create",
+            image = %image.descriptor(),
+            container.id = tracing::field::Empty
+        );
+        let (id, forwarders, temp_dirs) = span
+            .run(async {
+                Hooks::run_pre_create(&mut image)
+                    .map_err(|err| Error::new(Operation::Create, Self::Error::from(err)))?;
+                let forwarders = host::expose(self, &mut image).await?;
+                let temp_dirs = mounts::create_temp_dirs(&mut image)
+                    .map_err(|err| Error::other(Operation::Create, err))?;
+                let id = self
+                    .create_container(&image)
+                    .await
+                    .map_err(|err| Error::new(Operation::Create, err))?;
+                Ok::<_, Error>((id, forwarders, temp_dirs))
+            })
+            .await
+            .map_err(|err| err.with_name(name).with_image(descriptor))?;
+        span.record_id(&id);
+        let image = image.with_container_hooks(hooks);
+        let container = Container::new(id, self.clone(), image)
+            .await
+            .with_forwarders(forwarders)
+            .with_temp_dirs(temp_dirs);
+        container
+            .run_hooks(ContainerHook::PostCreate)
+            .await
+            .map_err(|err| container.error(Operation::Create, Self::Error::from(err)))?;
+        Ok(container)
+    }
+
+    /// Creates a container for the image and returns its id.
+    async fn create_container(&self, image: &DockerImage) -> Result<String, Self::Error>;
+
+    /// Returns whether containers may be reused across test runs, see [`crate::reuse`].
+    fn reuse_enabled(&self) -> bool {
+        reuse::enabled()
+    }
+
+    /// Returns a client with connections of its own.
+    ///
+    /// Pooled connections are driven by the runtime that opened them, so a
+    /// client used from another runtime, e.g. while the test runtime is blocked
+    /// in a panic, needs fresh ones. Clients without connections return a clone.
+    fn detached(&self) -> Result<Self, Self::Error> {
+        Ok(self.clone())
+    }
 
     async fn host(&self, id: &str) -> Result<net::IpAddr, Self::Error>;
     async fn ports(&self, id: &str) -> Result<Ports, Self::Error>;
     async fn rm(&self, id: &str) -> Result<(), Self::Error>;
     async fn stop(&self, id: &str) -> Result<(), Self::Error>;
     async fn start(&self, id: &str) -> Result<(), Self::Error>;
+    async fn inspect(&self, id: &str) -> Result<ContainerInspectResponse, Self::Error>;
+    async fn exec(&self, id: &str, cmd: Vec<String>) -> Result<ExecResult, Self::Error>;
+
+    /// Stops the container, killing it if it does not exit within `timeout`.
+    ///
+    /// The docker API takes whole seconds, so `timeout` is rounded up.
+    async fn stop_with_timeout(&self, id: &str, timeout: Duration) -> Result<(), Self::Error>;
+    async fn pause(&self, id: &str) -> Result<(), Self::Error>;
+    async fn unpause(&self, id: &str) -> Result<(), Self::Error>;
+
+    /// Restarts the container, using the daemon default stop timeout if `timeout` is `None`.
+    ///
+    /// The docker API takes whole seconds, so `timeout` is rounded up.
+    async fn restart(&self, id: &str, timeout: Option<Duration>) -> Result<(), Self::Error>;
+
+    /// Sends a signal such as `SIGKILL` to the main process of the container.
+    async fn kill(&self, id: &str, signal: &str) -> Result<(), Self::Error>;
+
+    /// Runs a container until it exits and returns its exit status and output.
+    ///
+    /// The container is removed afterwards, see [`crate::run`].
+    async fn run_to_completion<I: IntoImage<Self> + Send>(
+        &self,
+        image: I,
+    ) -> Result<Output, run::Error> {
+        run::run(self, image.into_image()).await
+    }
+
+    /// Waits until the container is not running and returns how it exited.
+    async fn wait(&self, id: &str) -> Result<ExitStatus, Self::Error>;
+
+    /// Streams resource usage samples of a running container, about one per second.
+    fn stats(&self, id: &str) -> StatsStream<'_, Self::Error>;
+
+    /// Returns a single resource usage sample.
+    async fn stats_snapshot(&self, id: &str) -> Result<Sample, Self::Error>;
+
+    /// Creates a volume and returns a handle that removes it when dropped.
+    ///
+    /// An existing volume with the same name is used as is and kept when the
+    /// handle is dropped.
+    async fn volume<V: Into<VolumeConfig> + Send>(
+        &self,
+        volume: V,
+    ) -> Result<Volume<Self>, Self::Error> {
+        let config = volume.into();
+        let created = self.create_volume(&config).await?;
+        let volume = Volume::new(config.name, self.clone());
+        Ok(if created { volume } else { volume.keep() })
+    }
+
+    /// Creates a volume unless one with the same name exists, returning whether it was created.
+    async fn create_volume(&self, config: &VolumeConfig) -> Result<bool, Self::Error>;
+    async fn rm_volume(&self, name: &str) -> Result<(), Self::Error>;
+
+    /// Extracts a tar archive into the root of a volume.
+    async fn populate_volume(&self, name: &str, archive: Vec<u8>) -> Result<(), Self::Error>;
 
-    // async fn inspect(&self, id: &str) -> ContainerInspectResponse;
+    /// Creates the image `repo:tag` from the current filesystem of a container.
+    async fn commit(&self, id: &str, repo: &str, tag: &str) -> Result<(), Self::Error>;
+
+    /// Removes an image, even if stopped containers still use it.
+    async fn rm_image(&self, image: &str) -> Result<(), Self::Error>;
+
+    /// Creates a network and returns a handle that removes it when dropped.
+    ///
+    /// An existing network with the same name is used as is and kept when the
+    /// handle is dropped.
+    async fn network(&self, name: &str) -> Result<Network<Self>, Self::Error> {
+        let created = self.create_network(name).await?;
+        let network = Network::new(name.to_string(), self.clone());
+        Ok(if created { network } else { network.keep() })
+    }
+
+    /// Creates a network unless one with the same name exists, returning whether it was created.
+    async fn create_network(&self, name: &str) -> Result<bool, Self::Error>;
+    async fn rm_network(&self, name: &str) -> Result<(), Self::Error>;
+
+    /// Connects a container to a network, reachable by `aliases` within it.
+    async fn connect_network(
+        &self,
+        network: &str,
+        id: &str,
+        aliases: Vec<String>,
+    ) -> Result<(), Self::Error>;
+
+    /// Disconnects a container from a network.
+    async fn disconnect_network(&self, network: &str, id: &str) -> Result<(), Self::Error>;
+
+    /// Returns how containers reach the machine running the tests.
+    async fn host_route(&self) -> Result<HostRoute, Self::Error>;
 }
 
 pub mod bollard {
-    use super::{Container, DockerClient, DockerImage, LogStream, Ports};
+    use super::{
+        Classify, ContainerInspectResponse, DockerClient, DockerImage, Duration, ExecResult,
+        ExitStatus, FrameStream, HookError, HostRoute, LogOptions, LogStream, Operation, Ports,
+        Sample, StatsStream, VolumeConfig,
+    };
+    use crate::error::ErrorKind;
+    use crate::image::PullPolicy;
+    use crate::logs::{frames, LogFrame, Output};
+    use crate::mounts::Mount;
+    use crate::{reuse, trace};
     use async_trait::async_trait;
     use bollard::container::LogsOptions;
-    use color_eyre::eyre;
     use futures::{StreamExt, TryStreamExt};
     use std::sync::Arc;
     use std::{fmt, io, net};
@@ -56,6 +224,166 @@ pub mod bollard {
 
         #[error(transparent)]
         Bollard(#[from] bollard::errors::Error),
+
+        #[error("failed to pull image {image}")]
+        Pull {
+            image: String,
+            #[source]
+            source: bollard::errors::Error,
+        },
+
+        #[error("no stats for container {id}")]
+        MissingStats { id: String },
+
+        #[error("failed to find the address of the host on the route to the daemon")]
+        HostRoute(#[source] io::Error),
+
+        #[error(transparent)]
+        Hook(#[from] HookError),
+    }
+
+    impl Classify for Error {
+        fn kind(&self) -> ErrorKind {
+            match self {
+                Self::Bollard(err) | Self::Pull { source: err, .. } => classify(err),
+                Self::Connection(_) => ErrorKind::Unavailable,
+                // the container is not running
+                Self::MissingHost | Self::MissingStats { .. } => ErrorKind::Conflict,
+                Self::ParseAddr { .. } | Self::HostRoute(_) | Self::Hook(_) => ErrorKind::Other,
+            }
+        }
+
+        fn operation(&self) -> Option<Operation> {
+            matches!(self, Self::Pull { .. }).then_some(Operation::Pull)
+        }
+    }
+
+    /// Classifies an error of the docker API by its status code or transport failure.
+    fn classify(err: &bollard::errors::Error) -> ErrorKind {
+        use bollard::errors::Error as Bollard;
+
+        match err {
+            Bollard::DockerResponseServerError { status_code, .. } => match status_code {
+                404 => ErrorKind::NotFound,
+                // 304 is returned e.g. when starting a running container
+                304 | 409 => ErrorKind::Conflict,
+                408 => ErrorKind::Timeout,
+                429 | 502 | 503 | 504 => ErrorKind::Unavailable,
+                _ => ErrorKind::Other,
+            },
+            Bollard::RequestTimeoutError => ErrorKind::Timeout,
+            Bollard::IOError { err } if err.kind() == io::ErrorKind::TimedOut => ErrorKind::Timeout,
+            Bollard::HyperResponseError { err } if err.is_timeout() => ErrorKind::Timeout,
+            Bollard::IOError { .. } | Bollard::HyperResponseError { .. } => ErrorKind::Unavailable,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Returns the address of a daemon on another machine, reached over TCP.
+    fn remote_daemon() -> Option<net::SocketAddr> {
+        use std::net::ToSocketAddrs;
+
+        let host = std::env::var("DOCKER_HOST").ok()?;
+        let authority = ["tcp://", "http://", "https://"]
+            .iter()
+            .find_map(|scheme| host.strip_prefix(scheme))?
+            .split('/')
+            .next()?;
+        let addr = match authority.to_socket_addrs() {
+            Ok(mut addrs) => addrs.next()?,
+            Err(_) => (authority, 2375).to_socket_addrs().ok()?.next()?,
+        };
+        (!addr.ip().is_loopback()).then_some(addr)
+    }
+
+    /// Returns the endpoint settings of a container in one of its networks.
+    fn endpoint_settings(image: &DockerImage, network: &str) -> bollard::models::EndpointSettings {
+        bollard::models::EndpointSettings {
+            aliases: image.network_aliases.get(network).cloned(),
+            ..Default::default()
+        }
+    }
+
+    /// Builds the docker container configuration for an image.
+    pub(crate) fn container_config(image: &DockerImage) -> bollard::container::Config<String> {
+        use bollard::container::{Config, NetworkingConfig};
+        use bollard::models::{HostConfig, PortBinding};
+        use std::collections::HashMap;
+
+        let mut exposed_ports: HashMap<String, HashMap<(), ()>> = HashMap::new();
+        // let mut exposed_ports = HashMap::new();
+        let mut port_bindings = HashMap::new();
+        for port in &image.exposed_ports {
+            let proto_port = format!("{}/tcp", port.container);
+            exposed_ports.insert(proto_port.clone(), HashMap::new());
+            port_bindings.insert(
+                proto_port,
+                None::<Vec<PortBinding>>,
+                // Some(vec![PortBinding {
+                //     host_ip: Some(String::from("127.0.0.1")),
+                //     host_port: Some(port.host.to_string()),
+                // }]),
+            );
+        }
+
+        // let exposed_ports: HashMap<String, Option<Vec<HashMap<(), ()>> =
+        //     HashMap::from_iter(vec![("80".to_string(), HashMap::new())]);
+
+        let mut host_config = HostConfig {
+            shm_size: image.shm_size,
+            // port_bindings: Some(port_bindings),
+            ..Default::default()
+        };
+        image.resources.apply(&mut host_config);
+        image.security.apply(&mut host_config);
+        Mount::apply(&image.mounts, &mut host_config);
+        host_config.privileged = image.privileged.then_some(true);
+        host_config.extra_hosts =
+            (!image.extra_hosts.is_empty()).then(|| image.extra_hosts.clone());
+        host_config.network_mode = image
+            .network_mode
+            .clone()
+            .or_else(|| image.networks.first().cloned());
+
+        // further networks are connected after the container is created
+        let networking_config = image.networks.first().map(|network| NetworkingConfig {
+            endpoints_config: [(network.clone(), endpoint_settings(image, network))].into(),
+        });
+
+        // let exposed_ports: HashMap<String, HashMap<(), ()>> =
+        //     HashMap::from_iter(vec![("80".to_string(), HashMap::new())]);
+
+        let config: Config<String> = Config {
+            image: Some(image.descriptor()),
+            cmd: Some(image.cmd.clone()),
+            exposed_ports: Some(exposed_ports),
+            // env: Some(image.env),
+            // volumes: Some(image.volumes),
+            entrypoint: Some(image.entrypoint.clone()),
+            host_config: Some(host_config),
+            labels: Some(image.labels.clone().into_iter().collect()),
+            user: image.security.user.clone(),
+            networking_config,
+            ..Default::default()
+        };
+
+        // // create network and add it to container creation
+        // if let Some(network) = image.network() {
+        //     config.host_config = config.host_config.map(|mut host_config| {
+        //         host_config.network_mode = Some(network.to_string());
+        //         host_config
+        //     });
+        //     // if self.create_network_if_not_exists(network).await {
+        //     //     let mut guard = self
+        //     //         .inner
+        //     //         .created_networks
+        //     //         .write()
+        //     //         .expect("'failed to lock RwLock'");
+        //     //     guard.push(network.clone());
+        //     // }
+        // }
+
+        config
     }
 
     #[derive(Clone)]
@@ -80,17 +408,38 @@ pub mod bollard {
             Ok(Self { inner, id })
         }
 
-        fn logs(&self, id: &str, options: LogsOptions<String>) -> LogStream<'_> {
+        fn log_lines(&self, id: &str, options: LogsOptions<String>) -> LogStream<'_> {
             let stream = self
                 .inner
-                .logs(&id, Some(options))
-                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+                .logs(id, Some(options))
+                .map_err(io::Error::other)
                 .map(|chunk| {
                     let bytes = chunk?.into_bytes();
                     Ok(String::from_utf8_lossy(bytes.as_ref()).to_string())
                 });
             LogStream::new(stream)
         }
+
+        /// Returns the id of a running container labeled with the given configuration hash.
+        async fn find_reusable(&self, hash: &str) -> Result<Option<String>, Error> {
+            use bollard::container::ListContainersOptions;
+            use std::collections::HashMap;
+
+            let mut filters = HashMap::new();
+            filters.insert(
+                "label".to_string(),
+                vec![format!("{}={}", reuse::HASH_LABEL, hash)],
+            );
+            filters.insert("status".to_string(), vec!["running".to_string()]);
+            let containers = self
+                .inner
+                .list_containers(Some(ListContainersOptions {
+                    filters,
+                    ..Default::default()
+                }))
+                .await?;
+            Ok(containers.into_iter().find_map(|container| container.id))
+        }
     }
 
     // impl std::ops::Deref for Client {
@@ -112,78 +461,23 @@ pub mod bollard {
         type Client = bollard::Docker;
         type Error = Error;
 
-        async fn create<I: Into<DockerImage> + Send>(
-            &self,
-            image: I,
-        ) -> Result<Container<Self>, Self::Error> {
-            use bollard::container::{Config, CreateContainerOptions};
-            use bollard::models::{HostConfig, PortBinding};
-            use std::collections::HashMap;
+        /// Pulls the image and creates a container for it.
+        async fn create_container(&self, image: &DockerImage) -> Result<String, Self::Error> {
+            use bollard::container::CreateContainerOptions;
 
-            let image = image.into();
-
-            let volumes: HashMap<String, HashMap<(), ()>> = image
-                .volumes
-                .iter()
-                .map(|(orig, dest)| (format!("{}:{}", orig, dest), HashMap::new()))
-                .collect();
-
-            let mut exposed_ports: HashMap<String, HashMap<(), ()>> = HashMap::new();
-            // let mut exposed_ports = HashMap::new();
-            let mut port_bindings = HashMap::new();
-            for port in &image.exposed_ports {
-                let proto_port = format!("{}/tcp", port.container);
-                exposed_ports.insert(proto_port.clone(), HashMap::new());
-                port_bindings.insert(
-                    proto_port,
-                    None::<Vec<PortBinding>>,
-                    // Some(vec![PortBinding {
-                    //     host_ip: Some(String::from("127.0.0.1")),
-                    //     host_port: Some(port.host.to_string()),
-                    // }]),
-                );
+            let mut config = container_config(image);
+            if image.reuse && self.reuse_enabled() {
+                let hash = reuse::image_hash(image);
+                if let Some(id) = self.find_reusable(&hash).await? {
+                    log::debug!("reusing docker container {} for {}", id, image.descriptor());
+                    return Ok(id);
+                }
+                config
+                    .labels
+                    .get_or_insert_with(Default::default)
+                    .insert(reuse::HASH_LABEL.to_string(), hash);
             }
 
-            // let exposed_ports: HashMap<String, Option<Vec<HashMap<(), ()>> =
-            //     HashMap::from_iter(vec![("80".to_string(), HashMap::new())]);
-
-            let mut host_config = HostConfig {
-                shm_size: image.shm_size,
-                // port_bindings: Some(port_bindings),
-                ..Default::default()
-            };
-
-            // let exposed_ports: HashMap<String, HashMap<(), ()>> =
-            //     HashMap::from_iter(vec![("80".to_string(), HashMap::new())]);
-
-            let mut config: Config<String> = Config {
-                image: Some(image.descriptor()),
-                cmd: Some(image.cmd.clone()),
-                exposed_ports: Some(exposed_ports),
-                // env: Some(image.env),
-                // volumes: Some(image.volumes),
-                entrypoint: Some(image.entrypoint.clone()),
-                volumes: Some(volumes),
-                host_config: Some(host_config),
-                ..Default::default()
-            };
-
-            // // create network and add it to container creation
-            // if let Some(network) = image.network() {
-            //     config.host_config = config.host_config.map(|mut host_config| {
-            //         host_config.network_mode = Some(network.to_string());
-            //         host_config
-            //     });
-            //     // if self.create_network_if_not_exists(network).await {
-            //     //     let mut guard = self
-            //     //         .inner
-            //     //         .created_networks
-            //     //         .write()
-            //     //         .expect("'failed to lock RwLock'");
-            //     //     guard.push(network.clone());
-            //     // }
-            // }
-
             let create_options: Option<CreateContainerOptions<String>> = image
                 .container_name
                 .as_ref()
@@ -191,22 +485,49 @@ pub mod bollard {
                     name: name.to_owned(),
                 });
 
-            // pull the image first
             use bollard::image::CreateImageOptions;
-            let pull_options = Some(CreateImageOptions {
-                from_image: image.descriptor(),
-                ..Default::default()
-            });
-            let mut pulling = self.inner.create_image(pull_options, None, None);
-            log::debug!("Pulling docker container {}", image.descriptor());
-            while let Some(result) = pulling.next().await {
-                if let Err(err) = result {
-                    return Err(err.into());
-                }
+            let pull = match image.pull_policy {
+                PullPolicy::Always => true,
+                PullPolicy::Missing => match self.inner.inspect_image(&image.descriptor()).await {
+                    Ok(_) => false,
+                    Err(bollard::errors::Error::DockerResponseServerError {
+                        status_code: 404,
+                        ..
+                    }) => true,
+                    Err(err) => return Err(err.into()),
+                },
+            };
+            if pull {
+                let pull_options = Some(CreateImageOptions {
+                    from_image: image.descriptor(),
+                    ..Default::default()
+                });
+                let mut pulling = self.inner.create_image(pull_options, None, None);
+                log::debug!("Pulling docker container {}", image.descriptor());
+                let span = trace::span!("testcontainers.pull", image = %image.descriptor());
+                span.run(async {
+                    while let Some(result) = pulling.next().await {
+                        result.map_err(|source| Error::Pull {
+                            image: image.descriptor(),
+                            source,
+                        })?;
+                    }
+                    Ok::<_, Error>(())
+                })
+                .await?;
+                log::debug!("Pulled docker container {}", image.descriptor());
             }
-            log::debug!("Pulled docker container {}", image.descriptor());
 
             let container = self.inner.create_container(create_options, config).await?;
+            for network in image.networks.iter().skip(1) {
+                use bollard::network::ConnectNetworkOptions;
+
+                let options = ConnectNetworkOptions {
+                    container: container.id.as_str(),
+                    endpoint_config: endpoint_settings(image, network),
+                };
+                self.inner.connect_network(network, options).await?;
+            }
             // match container {
             //         // Ok(container) => container.id,
             //         Err(bollard::errors::Error::DockerResponseServerError {
@@ -271,15 +592,24 @@ pub mod bollard {
 
             // let container_id = created_container.id;
             // let container = Container::new(container_id, self.clone(), image).await;
-            Ok(Container::new(container.id, self.clone(), image).await)
+            Ok(container.id)
         }
 
         fn native(&self) -> &Self::Client {
             &self.inner
         }
 
+        fn detached(&self) -> Result<Self, Self::Error> {
+            let client =
+                bollard::Docker::connect_with_local_defaults().map_err(Error::Connection)?;
+            Ok(Self {
+                inner: Arc::new(client),
+                id: self.id.clone(),
+            })
+        }
+
         fn stdout_logs(&self, id: &str) -> LogStream<'_> {
-            self.logs(
+            self.log_lines(
                 id,
                 LogsOptions {
                     follow: true,
@@ -291,7 +621,7 @@ pub mod bollard {
         }
 
         fn stderr_logs(&self, id: &str) -> LogStream<'_> {
-            self.logs(
+            self.log_lines(
                 id,
                 LogsOptions {
                     follow: true,
@@ -302,11 +632,62 @@ pub mod bollard {
             )
         }
 
+        fn logs(&self, id: &str, options: LogOptions) -> FrameStream<'_> {
+            use bollard::container::LogOutput;
+
+            let timestamps = options.timestamps;
+            let options = LogsOptions {
+                follow: options.follow,
+                stdout: options.stdout,
+                stderr: options.stderr,
+                since: options.since.map(frames::unix_seconds).unwrap_or_default(),
+                until: options.until.map(frames::unix_seconds).unwrap_or_default(),
+                timestamps,
+                tail: options
+                    .tail
+                    .map_or_else(|| "all".to_string(), |tail| tail.to_string()),
+            };
+            self.inner
+                .logs(id, Some(options))
+                .filter_map(move |output| async move {
+                    let (output, bytes) = match output {
+                        Ok(LogOutput::StdOut { message } | LogOutput::Console { message }) => {
+                            (Output::Stdout, message)
+                        }
+                        Ok(LogOutput::StdErr { message }) => (Output::Stderr, message),
+                        Ok(LogOutput::StdIn { .. }) => return None,
+                        Err(err) => return Some(Err(io::Error::other(err))),
+                    };
+                    Some(if timestamps {
+                        LogFrame::with_timestamp(output, &bytes)
+                    } else {
+                        Ok(LogFrame {
+                            output,
+                            timestamp: None,
+                            bytes: bytes.to_vec(),
+                        })
+                    })
+                })
+                .boxed()
+        }
+
         async fn host(&self, id: &str) -> Result<net::IpAddr, Self::Error> {
             let inspect = self.inner.inspect_container(id, None).await?;
-            let addr = inspect
-                .network_settings
-                .and_then(|network| network.ip_address)
+            let settings = inspect.network_settings.unwrap_or_default();
+            // containers on user-defined networks only have addresses in these
+            // networks, which change when they are reconnected
+            let addr = settings
+                .ip_address
+                .filter(|addr| !addr.is_empty())
+                .or_else(|| {
+                    settings
+                        .networks
+                        .unwrap_or_default()
+                        .into_iter()
+                        .collect::<std::collections::BTreeMap<_, _>>()
+                        .into_values()
+                        .find_map(|endpoint| endpoint.ip_address.filter(|addr| !addr.is_empty()))
+                })
                 .ok_or(Self::Error::MissingHost)?;
             addr.parse()
                 .map_err(|err| Self::Error::ParseAddr { addr, source: err })
@@ -325,9 +706,234 @@ pub mod bollard {
             // .unwrap_or_default()
         }
 
-        // async fn inspect(&self, id: &str) -> Result<ContainerInspectResponse, Self::Error> {
-        //     Ok(self.inner.inspect_container(id, None).await?)
-        // }
+        async fn inspect(&self, id: &str) -> Result<ContainerInspectResponse, Self::Error> {
+            Ok(self.inner.inspect_container(id, None).await?)
+        }
+
+        fn stats(&self, id: &str) -> StatsStream<'_, Self::Error> {
+            let options = bollard::container::StatsOptions {
+                stream: true,
+                one_shot: false,
+            };
+            self.inner
+                .stats(id, Some(options))
+                .map_ok(|stats| Sample::from(&stats))
+                .map_err(Error::from)
+                .boxed()
+        }
+
+        async fn stats_snapshot(&self, id: &str) -> Result<Sample, Self::Error> {
+            // without one_shot, docker waits for a second sample to compute the cpu usage
+            let options = bollard::container::StatsOptions {
+                stream: false,
+                one_shot: false,
+            };
+            let stats = self
+                .inner
+                .stats(id, Some(options))
+                .try_next()
+                .await?
+                .ok_or_else(|| Error::MissingStats { id: id.to_string() })?;
+            Ok(Sample::from(&stats))
+        }
+
+        async fn create_volume(&self, config: &VolumeConfig) -> Result<bool, Self::Error> {
+            use bollard::volume::CreateVolumeOptions;
+
+            // creating a volume that exists succeeds, so it is looked up first
+            match self.inner.inspect_volume(&config.name).await {
+                Ok(_) => {
+                    log::debug!("using existing docker volume {}", config.name);
+                    return Ok(false);
+                }
+                Err(bollard::errors::Error::DockerResponseServerError {
+                    status_code: 404, ..
+                }) => {}
+                Err(err) => return Err(err.into()),
+            }
+            let options = CreateVolumeOptions {
+                name: config.name.clone(),
+                driver: config.driver.clone().unwrap_or_else(|| "local".to_string()),
+                driver_opts: config.driver_options.clone().into_iter().collect(),
+                labels: config.labels.clone().into_iter().collect(),
+            };
+            self.inner.create_volume(options).await?;
+            Ok(true)
+        }
+
+        async fn rm_volume(&self, name: &str) -> Result<(), Self::Error> {
+            self.inner.remove_volume(name, None).await?;
+            Ok(())
+        }
+
+        /// Uploads the archive into a created but never started helper container
+        /// that mounts the volume.
+        async fn populate_volume(&self, name: &str, archive: Vec<u8>) -> Result<(), Self::Error> {
+            use bollard::container::UploadToContainerOptions;
+
+            let helper = DockerImage::new("busybox").with_mount(Mount::volume(name, "/volume"));
+            let id = self.create_container(&helper).await?;
+            let options = UploadToContainerOptions {
+                path: "/volume".to_string(),
+                ..Default::default()
+            };
+            let uploaded = self
+                .inner
+                .upload_to_container(&id, Some(options), archive.into())
+                .await;
+            let removed = self.rm(&id).await;
+            uploaded?;
+            removed
+        }
+
+        async fn commit(&self, id: &str, repo: &str, tag: &str) -> Result<(), Self::Error> {
+            use bollard::container::Config;
+            use bollard::image::CommitContainerOptions;
+
+            let options = CommitContainerOptions {
+                container: id,
+                repo,
+                tag,
+                pause: true,
+                ..Default::default()
+            };
+            self.inner
+                .commit_container(options, Config::<String>::default())
+                .await?;
+            Ok(())
+        }
+
+        async fn rm_image(&self, image: &str) -> Result<(), Self::Error> {
+            use bollard::image::RemoveImageOptions;
+
+            let options = RemoveImageOptions {
+                force: true,
+                ..Default::default()
+            };
+            self.inner.remove_image(image, Some(options), None).await?;
+            Ok(())
+        }
+
+        async fn create_network(&self, name: &str) -> Result<bool, Self::Error> {
+            use bollard::network::CreateNetworkOptions;
+
+            let options = CreateNetworkOptions {
+                name,
+                check_duplicate: true,
+                driver: "bridge",
+                labels: [(crate::network::LABEL, "true")].into(),
+                ..Default::default()
+            };
+            match self.inner.create_network(options).await {
+                Ok(_) => Ok(true),
+                Err(bollard::errors::Error::DockerResponseServerError {
+                    status_code: 409, ..
+                }) => {
+                    log::debug!("using existing docker network {}", name);
+                    Ok(false)
+                }
+                Err(err) => Err(err.into()),
+            }
+        }
+
+        async fn rm_network(&self, name: &str) -> Result<(), Self::Error> {
+            self.inner.remove_network(name).await?;
+            Ok(())
+        }
+
+        async fn connect_network(
+            &self,
+            network: &str,
+            id: &str,
+            aliases: Vec<String>,
+        ) -> Result<(), Self::Error> {
+            use bollard::network::ConnectNetworkOptions;
+
+            let options = ConnectNetworkOptions {
+                container: id,
+                endpoint_config: bollard::models::EndpointSettings {
+                    aliases: (!aliases.is_empty()).then_some(aliases),
+                    ..Default::default()
+                },
+            };
+            self.inner.connect_network(network, options).await?;
+            Ok(())
+        }
+
+        async fn disconnect_network(&self, network: &str, id: &str) -> Result<(), Self::Error> {
+            use bollard::network::DisconnectNetworkOptions;
+
+            let options = DisconnectNetworkOptions {
+                container: id,
+                force: true,
+            };
+            self.inner.disconnect_network(network, options).await?;
+            Ok(())
+        }
+
+        /// Uses the `host-gateway` for a local daemon, and the local address of
+        /// the route to a remote daemon otherwise.
+        async fn host_route(&self) -> Result<HostRoute, Self::Error> {
+            if let Some(daemon) = remote_daemon() {
+                let unspecified: net::IpAddr = match daemon {
+                    net::SocketAddr::V4(_) => net::Ipv4Addr::UNSPECIFIED.into(),
+                    net::SocketAddr::V6(_) => net::Ipv6Addr::UNSPECIFIED.into(),
+                };
+                // connecting a UDP socket only selects the route, nothing is sent
+                let socket = net::UdpSocket::bind((unspecified, 0)).map_err(Error::HostRoute)?;
+                socket.connect(daemon).map_err(Error::HostRoute)?;
+                let addr = socket.local_addr().map_err(Error::HostRoute)?;
+                return Ok(HostRoute::remote(addr.ip()));
+            }
+            let gateway = self
+                .inner
+                .inspect_network::<String>("bridge", None)
+                .await
+                .ok()
+                .and_then(|network| network.ipam?.config)
+                .and_then(|configs| configs.into_iter().find_map(|config| config.gateway))
+                .and_then(|gateway| gateway.parse().ok());
+            Ok(HostRoute::local(gateway))
+        }
+
+        async fn exec(&self, id: &str, cmd: Vec<String>) -> Result<ExecResult, Self::Error> {
+            use bollard::container::LogOutput;
+            use bollard::exec::{CreateExecOptions, StartExecResults};
+
+            let exec = self
+                .inner
+                .create_exec(
+                    id,
+                    CreateExecOptions {
+                        cmd: Some(cmd),
+                        attach_stdout: Some(true),
+                        attach_stderr: Some(true),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+
+            let mut stdout = Vec::new();
+            let mut stderr = Vec::new();
+            if let StartExecResults::Attached { mut output, .. } =
+                self.inner.start_exec(&exec.id, None).await?
+            {
+                while let Some(chunk) = output.next().await {
+                    match chunk? {
+                        LogOutput::StdOut { message } => stdout.extend_from_slice(&message),
+                        LogOutput::StdErr { message } => stderr.extend_from_slice(&message),
+                        LogOutput::StdIn { .. } | LogOutput::Console { .. } => {}
+                    }
+                }
+            }
+
+            let inspect = self.inner.inspect_exec(&exec.id).await?;
+            Ok(ExecResult {
+                exit_code: inspect.exit_code,
+                stdout: String::from_utf8_lossy(&stdout).to_string(),
+                stderr: String::from_utf8_lossy(&stderr).to_string(),
+            })
+        }
 
         async fn rm(&self, id: &str) -> Result<(), Self::Error> {
             Ok(self
@@ -350,13 +956,74 @@ pub mod bollard {
         async fn start(&self, id: &str) -> Result<(), Self::Error> {
             Ok(self.inner.start_container::<String>(id, None).await?)
         }
+
+        async fn stop_with_timeout(&self, id: &str, timeout: Duration) -> Result<(), Self::Error> {
+            let options = bollard::container::StopContainerOptions {
+                t: whole_seconds(timeout) as i64,
+            };
+            Ok(self.inner.stop_container(id, Some(options)).await?)
+        }
+
+        async fn pause(&self, id: &str) -> Result<(), Self::Error> {
+            Ok(self.inner.pause_container(id).await?)
+        }
+
+        async fn unpause(&self, id: &str) -> Result<(), Self::Error> {
+            Ok(self.inner.unpause_container(id).await?)
+        }
+
+        async fn restart(&self, id: &str, timeout: Option<Duration>) -> Result<(), Self::Error> {
+            let options = timeout.map(|timeout| bollard::container::RestartContainerOptions {
+                t: whole_seconds(timeout) as isize,
+            });
+            Ok(self.inner.restart_container(id, options).await?)
+        }
+
+        async fn kill(&self, id: &str, signal: &str) -> Result<(), Self::Error> {
+            let options = bollard::container::KillContainerOptions { signal };
+            Ok(self.inner.kill_container(id, Some(options)).await?)
+        }
+
+        async fn wait(&self, id: &str) -> Result<ExitStatus, Self::Error> {
+            let options = bollard::container::WaitContainerOptions {
+                condition: "not-running",
+            };
+            let response = self
+                .inner
+                .wait_container(id, Some(options))
+                .try_next()
+                .await?;
+            let state = self.inner.inspect_container(id, None).await?.state;
+            let code = response
+                .map(|response| response.status_code)
+                .or_else(|| state.as_ref().and_then(|state| state.exit_code))
+                .unwrap_or_default();
+            Ok(ExitStatus {
+                code,
+                oom_killed: state.and_then(|state| state.oom_killed).unwrap_or(false),
+            })
+        }
+    }
+
+    /// Rounds a timeout up to whole seconds, so that sub-second timeouts do not kill immediately.
+    fn whole_seconds(timeout: Duration) -> u64 {
+        timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0)
     }
 
     #[cfg(test)]
     mod tests {
-        use super::{Client, DockerClient};
+        use super::{whole_seconds, Client, DockerClient};
         use color_eyre::eyre;
-        use pretty_assertions::{assert_eq, assert_ne};
+        use pretty_assertions::assert_eq;
+        use std::time::Duration;
+
+        #[test]
+        fn rounds_timeouts_up_to_whole_seconds() {
+            assert_eq!(whole_seconds(Duration::ZERO), 0);
+            assert_eq!(whole_seconds(Duration::from_millis(500)), 1);
+            assert_eq!(whole_seconds(Duration::from_secs(2)), 2);
+            assert_eq!(whole_seconds(Duration::from_millis(2001)), 3);
+        }
 
         #[tokio::test(flavor = "multi_thread")]
         async fn get_native_client() -> eyre::Result<()> {
@@ -369,7 +1036,7 @@ pub mod bollard {
 
         #[tokio::test(flavor = "multi_thread")]
         async fn expose_all_ports_by_default() -> eyre::Result<()> {
-            let client = Client::new().await?;
+            let _client = Client::new().await?;
             // let docker = Http::new();
             // let image = HelloWorld::default();
             // let container = client.run(image).await?;
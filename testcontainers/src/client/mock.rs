@@ -0,0 +1,1410 @@
+//! In-memory [`DockerClient`] that simulates containers without a docker daemon.
+//!
+//! The [`MockClient`] keeps a small state machine per container
+//! (created, running, exited), serves scripted log output, port mappings and
+//! resource usage samples and can be told to fail the next call of any [`Operation`].
+
+use super::{
+    ContainerInspectResponse, DockerClient, DockerImage, Duration, ExecResult, ExitStatus,
+    FrameStream, HookError, HostRoute, LogOptions, LogStream, Ports, Sample, StatsStream,
+    VolumeConfig,
+};
+use crate::error::{Classify, ErrorKind};
+use crate::logs::{LogFrame, Output};
+use crate::{reuse, rt};
+use async_trait::async_trait;
+use bollard::models::{
+    ContainerState, ContainerStateStatusEnum, EndpointSettings, Health, HealthStatusEnum,
+    NetworkSettings, PortBinding, PortMap,
+};
+use futures::StreamExt;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use std::{fmt, io, net};
+
+/// The first host port handed out for exposed ports without a fixed host port.
+const FIRST_EPHEMERAL_PORT: u16 = 49153;
+
+/// The first address handed out to containers in networks, `172.18.0.2`.
+const FIRST_ADDRESS: u32 = 0xac12_0002;
+
+/// Operations of the [`DockerClient`] trait that can be made to fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Create,
+    Start,
+    Stop,
+    Remove,
+    Host,
+    Ports,
+    Logs,
+    Inspect,
+    Exec,
+    Stats,
+    Volume,
+    Pause,
+    Unpause,
+    Restart,
+    Kill,
+    Wait,
+    Commit,
+    RemoveImage,
+    Network,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Create => "create",
+            Self::Start => "start",
+            Self::Stop => "stop",
+            Self::Remove => "remove",
+            Self::Host => "host",
+            Self::Ports => "ports",
+            Self::Logs => "logs",
+            Self::Inspect => "inspect",
+            Self::Exec => "exec",
+            Self::Stats => "stats",
+            Self::Volume => "volume",
+            Self::Pause => "pause",
+            Self::Unpause => "unpause",
+            Self::Restart => "restart",
+            Self::Kill => "kill",
+            Self::Wait => "wait",
+            Self::Commit => "commit",
+            Self::RemoveImage => "remove image",
+            Self::Network => "network",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The simulated state of a mock container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Created,
+    Running,
+    Paused,
+    Exited,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("no such container: {id}")]
+    NotFound { id: String },
+
+    #[error("missing host")]
+    MissingHost,
+
+    #[error("no such volume: {name}")]
+    VolumeNotFound { name: String },
+
+    #[error("no such image: {image}")]
+    ImageNotFound { image: String },
+
+    #[error("no such network: {name}")]
+    NetworkNotFound { name: String },
+
+    #[error("network {name} has active endpoints")]
+    NetworkInUse { name: String },
+
+    #[error("container {id} is already connected to network {network}")]
+    AlreadyConnected { id: String, network: String },
+
+    #[error("container {id} is not connected to network {network}")]
+    NotConnected { id: String, network: String },
+
+    #[error("container {id} is not running")]
+    NotRunning { id: String },
+
+    #[error("container {id} is not paused")]
+    NotPaused { id: String },
+
+    #[error("invalid signal: {signal}")]
+    InvalidSignal { signal: String },
+
+    #[error("injected {operation} failure: {message}")]
+    Injected {
+        operation: Operation,
+        message: String,
+    },
+
+    #[error(transparent)]
+    Hook(#[from] HookError),
+}
+
+impl Classify for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::NotFound { .. }
+            | Self::VolumeNotFound { .. }
+            | Self::ImageNotFound { .. }
+            | Self::NetworkNotFound { .. }
+            | Self::NotConnected { .. } => ErrorKind::NotFound,
+            Self::MissingHost
+            | Self::NetworkInUse { .. }
+            | Self::AlreadyConnected { .. }
+            | Self::NotRunning { .. }
+            | Self::NotPaused { .. } => ErrorKind::Conflict,
+            // injected failures simulate an unavailable daemon
+            Self::Injected { .. } => ErrorKind::Unavailable,
+            Self::InvalidSignal { .. } | Self::Hook(_) => ErrorKind::Other,
+        }
+    }
+}
+
+/// Returns the number of a signal given as e.g. `SIGKILL`, `KILL` or `9`.
+fn signal_number(signal: &str) -> Option<i64> {
+    let name = signal.trim_start_matches("SIG");
+    let number = match name {
+        "HUP" => 1,
+        "INT" => 2,
+        "QUIT" => 3,
+        "ABRT" => 6,
+        "KILL" => 9,
+        "USR1" => 10,
+        "USR2" => 12,
+        "TERM" => 15,
+        _ => name
+            .parse()
+            .ok()
+            .filter(|number| (1..=64).contains(number))?,
+    };
+    Some(number)
+}
+
+/// Scripted output of containers created from an image.
+#[derive(Debug, Clone, Default)]
+struct Script {
+    stdout: Vec<String>,
+    stderr: Vec<String>,
+    /// Exit code of containers that exit as soon as they are started.
+    exit_code: Option<i64>,
+    /// Time between creating a container and returning its id.
+    create_delay: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct MockContainer {
+    image: String,
+    status: Status,
+    /// Configuration hash of reusable containers.
+    hash: Option<String>,
+    ports: Vec<(u16, u16)>,
+    health: Option<HealthStatusEnum>,
+    logs: Vec<MockLine>,
+    stats: Vec<Sample>,
+    exit: Option<ExitStatus>,
+    networks: Vec<MockEndpoint>,
+    /// Whether the container is on the default bridge, since it was created without networks.
+    bridged: bool,
+    /// Scripted exit code, the container exits as soon as it is started.
+    exit_code: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+struct MockEndpoint {
+    network: String,
+    address: net::Ipv4Addr,
+    aliases: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct MockLine {
+    output: Output,
+    timestamp: SystemTime,
+    line: String,
+}
+
+impl MockContainer {
+    /// Returns whether the container has running or paused processes.
+    fn is_up(&self) -> bool {
+        matches!(self.status, Status::Running | Status::Paused)
+    }
+
+    /// Returns whether the container is connected to any network.
+    fn is_attached(&self) -> bool {
+        self.bridged || !self.networks.is_empty()
+    }
+
+    fn is_connected(&self, network: &str) -> bool {
+        self.networks
+            .iter()
+            .any(|endpoint| endpoint.network == network)
+    }
+
+    fn start(&mut self) {
+        self.status = Status::Running;
+        self.exit = None;
+        if let Some(code) = self.exit_code {
+            self.exit(code, false);
+        }
+    }
+
+    fn exit(&mut self, code: i64, oom_killed: bool) {
+        self.status = Status::Exited;
+        self.exit = Some(ExitStatus { code, oom_killed });
+    }
+
+    fn log(&mut self, output: Output, line: String) {
+        self.logs.push(MockLine {
+            output,
+            timestamp: SystemTime::now(),
+            line,
+        });
+    }
+
+    fn port_map(&self) -> PortMap {
+        self.ports
+            .iter()
+            .map(|(internal, host)| {
+                let binding = PortBinding {
+                    host_ip: Some("0.0.0.0".to_string()),
+                    host_port: Some(host.to_string()),
+                };
+                (format!("{}/tcp", internal), Some(vec![binding]))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct MockVolume {
+    config: VolumeConfig,
+    archives: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    next_id: usize,
+    next_port: u16,
+    containers: HashMap<String, MockContainer>,
+    volumes: HashMap<String, MockVolume>,
+    /// Committed images by reference, with the id of the container they were committed from.
+    images: HashMap<String, String>,
+    networks: BTreeSet<String>,
+    next_address: u32,
+    /// Executed commands with the id of their container, in order.
+    execs: Vec<(String, Vec<String>)>,
+    failures: HashMap<Operation, VecDeque<String>>,
+}
+
+impl State {
+    fn fail(&mut self, operation: Operation) -> Result<(), Error> {
+        match self
+            .failures
+            .get_mut(&operation)
+            .and_then(VecDeque::pop_front)
+        {
+            Some(message) => Err(Error::Injected { operation, message }),
+            None => Ok(()),
+        }
+    }
+
+    /// Connects a container to a network with a new address.
+    fn connect(&mut self, id: &str, network: &str, aliases: Vec<String>) -> Result<(), Error> {
+        if !self.networks.contains(network) {
+            return Err(Error::NetworkNotFound {
+                name: network.to_string(),
+            });
+        }
+        let address = net::Ipv4Addr::from(FIRST_ADDRESS + self.next_address);
+        self.next_address += 1;
+        let container = self.container(id)?;
+        if container.is_connected(network) {
+            return Err(Error::AlreadyConnected {
+                id: id.to_string(),
+                network: network.to_string(),
+            });
+        }
+        container.networks.push(MockEndpoint {
+            network: network.to_string(),
+            address,
+            aliases,
+        });
+        Ok(())
+    }
+
+    /// Returns the id of a running container with the given configuration hash.
+    fn reusable(&self, hash: Option<&str>) -> Option<String> {
+        let hash = hash?;
+        self.containers
+            .iter()
+            .find(|(_, container)| {
+                container.status == Status::Running && container.hash.as_deref() == Some(hash)
+            })
+            .map(|(id, _)| id.clone())
+    }
+
+    fn container(&mut self, id: &str) -> Result<&mut MockContainer, Error> {
+        self.containers
+            .get_mut(id)
+            .ok_or_else(|| Error::NotFound { id: id.to_string() })
+    }
+}
+
+/// A [`DockerClient`] backed by an in-memory container state machine.
+///
+/// Clones share the same state, so a test can keep a handle to the client
+/// to script logs, inject failures and inspect containers while the code
+/// under test owns another one.
+#[derive(Clone)]
+pub struct MockClient {
+    state: Arc<Mutex<State>>,
+    host: net::IpAddr,
+    host_route: HostRoute,
+    scripts: Arc<HashMap<String, Script>>,
+    execs: Arc<HashMap<Vec<String>, ExecResult>>,
+    reuse: Option<bool>,
+}
+
+impl fmt::Debug for MockClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockClient")
+            .field("host", &self.host)
+            .finish()
+    }
+}
+
+impl Default for MockClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClient {
+    pub fn new() -> Self {
+        let state = State {
+            next_port: FIRST_EPHEMERAL_PORT,
+            ..Default::default()
+        };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            host: net::IpAddr::V4(net::Ipv4Addr::LOCALHOST),
+            host_route: HostRoute::local(None),
+            scripts: Arc::new(HashMap::new()),
+            execs: Arc::new(HashMap::new()),
+            reuse: None,
+        }
+    }
+
+    /// Sets the host address reported for running containers.
+    pub fn with_host(self, host: impl Into<net::IpAddr>) -> Self {
+        Self {
+            host: host.into(),
+            ..self
+        }
+    }
+
+    /// Enables or disables reuse of containers regardless of the global setting.
+    pub fn with_reuse(self, reuse: bool) -> Self {
+        Self {
+            reuse: Some(reuse),
+            ..self
+        }
+    }
+
+    /// Sets the route that containers reach the test machine on, see [`crate::host`].
+    ///
+    /// By default the route uses the `host-gateway` without forwarding ports.
+    pub fn with_host_route(self, host_route: HostRoute) -> Self {
+        Self { host_route, ..self }
+    }
+
+    /// Scripts the stdout lines of every container created from `image`.
+    pub fn with_stdout<L>(self, image: impl Into<String>, lines: L) -> Self
+    where
+        L: IntoIterator,
+        L::Item: Into<String>,
+    {
+        let mut scripts = (*self.scripts).clone();
+        scripts.entry(image.into()).or_default().stdout =
+            lines.into_iter().map(Into::into).collect();
+        Self {
+            scripts: Arc::new(scripts),
+            ..self
+        }
+    }
+
+    /// Scripts the stderr lines of every container created from `image`.
+    pub fn with_stderr<L>(self, image: impl Into<String>, lines: L) -> Self
+    where
+        L: IntoIterator,
+        L::Item: Into<String>,
+    {
+        let mut scripts = (*self.scripts).clone();
+        scripts.entry(image.into()).or_default().stderr =
+            lines.into_iter().map(Into::into).collect();
+        Self {
+            scripts: Arc::new(scripts),
+            ..self
+        }
+    }
+
+    /// Scripts containers created from `image` to exit with `code` as soon as they are started.
+    pub fn with_exit_code(self, image: impl Into<String>, code: i64) -> Self {
+        let mut scripts = (*self.scripts).clone();
+        scripts.entry(image.into()).or_default().exit_code = Some(code);
+        Self {
+            scripts: Arc::new(scripts),
+            ..self
+        }
+    }
+
+    /// Delays the response to creating containers from `image` by `delay`.
+    ///
+    /// The container exists as soon as it is created, like with a daemon that
+    /// is slow to respond, so cancelling the create leaks it.
+    pub fn with_create_delay(self, image: impl Into<String>, delay: Duration) -> Self {
+        let mut scripts = (*self.scripts).clone();
+        scripts.entry(image.into()).or_default().create_delay = Some(delay);
+        Self {
+            scripts: Arc::new(scripts),
+            ..self
+        }
+    }
+
+    /// Scripts the result of executing `cmd` in any container.
+    ///
+    /// Commands without a scripted result succeed without output.
+    pub fn with_exec<I>(self, cmd: I, result: ExecResult) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let mut execs = (*self.execs).clone();
+        execs.insert(cmd.into_iter().map(Into::into).collect(), result);
+        Self {
+            execs: Arc::new(execs),
+            ..self
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        // a panicking test must not poison the client for the drop of its containers
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Makes the next call of `operation` fail with `message`.
+    ///
+    /// Failures are queued, so injecting twice fails the next two calls.
+    pub fn inject_failure(&self, operation: Operation, message: impl Into<String>) {
+        self.state()
+            .failures
+            .entry(operation)
+            .or_default()
+            .push_back(message.into());
+    }
+
+    /// Appends a line to the stdout of the container with the given id.
+    pub fn push_stdout(&self, id: &str, line: impl Into<String>) -> Result<(), Error> {
+        self.state().container(id)?.log(Output::Stdout, line.into());
+        Ok(())
+    }
+
+    /// Appends a line to the stderr of the container with the given id.
+    pub fn push_stderr(&self, id: &str, line: impl Into<String>) -> Result<(), Error> {
+        self.state().container(id)?.log(Output::Stderr, line.into());
+        Ok(())
+    }
+
+    /// Appends a resource usage sample of the container with the given id.
+    pub fn push_stats(&self, id: &str, sample: Sample) -> Result<(), Error> {
+        self.state().container(id)?.stats.push(sample);
+        Ok(())
+    }
+
+    /// Simulates the container with the given id exiting on its own.
+    pub fn exit(&self, id: &str) -> Result<(), Error> {
+        self.exit_with(id, 0)
+    }
+
+    /// Simulates the container with the given id exiting with `code`.
+    pub fn exit_with(&self, id: &str, code: i64) -> Result<(), Error> {
+        self.state().container(id)?.exit(code, false);
+        Ok(())
+    }
+
+    /// Simulates the OOM killer killing the container with the given id.
+    pub fn oom_kill(&self, id: &str) -> Result<(), Error> {
+        self.state().container(id)?.exit(137, true);
+        Ok(())
+    }
+
+    /// Sets the healthcheck status reported for the container with the given id.
+    pub fn set_health(&self, id: &str, health: HealthStatusEnum) -> Result<(), Error> {
+        self.state().container(id)?.health = Some(health);
+        Ok(())
+    }
+
+    /// Returns the simulated status of a container or `None` if it does not exist.
+    pub fn status(&self, id: &str) -> Option<Status> {
+        self.state()
+            .containers
+            .get(id)
+            .map(|container| container.status)
+    }
+
+    /// Returns the image descriptor a container was created from.
+    pub fn image(&self, id: &str) -> Option<String> {
+        self.state()
+            .containers
+            .get(id)
+            .map(|container| container.image.clone())
+    }
+
+    /// Returns the ids of all containers that have not been removed.
+    pub fn container_ids(&self) -> Vec<String> {
+        let mut ids: Vec<_> = self.state().containers.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Returns the configuration of a volume or `None` if it does not exist.
+    pub fn volume_config(&self, name: &str) -> Option<VolumeConfig> {
+        self.state()
+            .volumes
+            .get(name)
+            .map(|volume| volume.config.clone())
+    }
+
+    /// Returns the references of all committed images that were not removed, sorted.
+    pub fn images(&self) -> Vec<String> {
+        let mut images: Vec<String> = self.state().images.keys().cloned().collect();
+        images.sort();
+        images
+    }
+
+    /// Returns the id of the container an image was committed from.
+    pub fn committed_from(&self, image: &str) -> Option<String> {
+        self.state().images.get(image).cloned()
+    }
+
+    /// Returns the names of all networks, sorted.
+    pub fn networks(&self) -> Vec<String> {
+        self.state().networks.iter().cloned().collect()
+    }
+
+    /// Returns the networks a container is connected to.
+    pub fn container_networks(&self, id: &str) -> Vec<String> {
+        self.state()
+            .containers
+            .get(id)
+            .map(|container| {
+                container
+                    .networks
+                    .iter()
+                    .map(|endpoint| endpoint.network.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the commands executed in a container, in order.
+    pub fn execs(&self, id: &str) -> Vec<Vec<String>> {
+        self.state()
+            .execs
+            .iter()
+            .filter(|(container, _)| container == id)
+            .map(|(_, cmd)| cmd.clone())
+            .collect()
+    }
+
+    /// Returns the archives a volume was populated with, in order.
+    pub fn volume_archives(&self, name: &str) -> Vec<Vec<u8>> {
+        self.state()
+            .volumes
+            .get(name)
+            .map(|volume| volume.archives.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the logged frames selected by `options`.
+    ///
+    /// Mock logs are never followed, the frames logged so far are returned.
+    fn frames(&self, id: &str, options: &LogOptions) -> Result<Vec<LogFrame>, Error> {
+        let mut state = self.state();
+        state.fail(Operation::Logs)?;
+        let container = state.container(id)?;
+        if container.status == Status::Created {
+            return Ok(vec![]);
+        }
+        let mut frames: Vec<LogFrame> = container
+            .logs
+            .iter()
+            .filter(|line| options.selects(line.output, line.timestamp))
+            .map(|line| LogFrame {
+                output: line.output,
+                timestamp: options.timestamps.then_some(line.timestamp),
+                bytes: line.line.clone().into_bytes(),
+            })
+            .collect();
+        if let Some(tail) = options.tail {
+            frames.drain(..frames.len().saturating_sub(tail));
+        }
+        Ok(frames)
+    }
+
+    /// Returns the samples pushed so far for a running container.
+    fn samples(&self, id: &str) -> Result<Vec<Sample>, Error> {
+        let mut state = self.state();
+        state.fail(Operation::Stats)?;
+        let container = state.container(id)?;
+        if !container.is_up() {
+            return Err(Error::NotRunning { id: id.to_string() });
+        }
+        Ok(container.stats.clone())
+    }
+
+    fn log_lines(&self, id: &str, output: Output) -> LogStream<'_> {
+        let options = LogOptions::new()
+            .with_stdout(output == Output::Stdout)
+            .with_stderr(output == Output::Stderr);
+        let lines: Vec<Result<String, io::Error>> = match self.frames(id, &options) {
+            Ok(frames) => frames
+                .iter()
+                .map(|frame| Ok(frame.line().into_owned()))
+                .collect(),
+            Err(err) => vec![Err(io::Error::other(err))],
+        };
+        LogStream::new(futures::stream::iter(lines))
+    }
+}
+
+#[async_trait]
+impl DockerClient for MockClient {
+    type Client = Self;
+    type Error = Error;
+
+    async fn create_container(&self, image: &DockerImage) -> Result<String, Self::Error> {
+        let script = self.scripts.get(&image.image).cloned().unwrap_or_default();
+
+        let hash = (image.reuse && self.reuse_enabled()).then(|| reuse::image_hash(image));
+
+        let id = {
+            let mut state = self.state();
+            state.fail(Operation::Create)?;
+            if let Some(name) = image
+                .networks
+                .iter()
+                .find(|network| !state.networks.contains(*network))
+            {
+                return Err(Error::NetworkNotFound { name: name.clone() });
+            }
+
+            if let Some(id) = state.reusable(hash.as_deref()) {
+                log::debug!("reusing mock container {} for {}", id, image.descriptor());
+                id
+            } else {
+                let mut ports = Vec::new();
+                for port in &image.exposed_ports {
+                    let host = if port.host == 0 {
+                        let host = state.next_port;
+                        state.next_port += 1;
+                        host
+                    } else {
+                        port.host
+                    };
+                    ports.push((port.container, host));
+                }
+
+                state.next_id += 1;
+                let id = format!("{:064x}", state.next_id);
+                let mut container = MockContainer {
+                    image: image.descriptor(),
+                    status: Status::Created,
+                    hash,
+                    ports,
+                    health: None,
+                    logs: Vec::new(),
+                    stats: Vec::new(),
+                    exit: None,
+                    networks: Vec::new(),
+                    bridged: image.networks.is_empty(),
+                    exit_code: script.exit_code,
+                };
+                for line in script.stdout {
+                    container.log(Output::Stdout, line);
+                }
+                for line in script.stderr {
+                    container.log(Output::Stderr, line);
+                }
+                state.containers.insert(id.clone(), container);
+                for network in &image.networks {
+                    let aliases = image
+                        .network_aliases
+                        .get(network)
+                        .cloned()
+                        .unwrap_or_default();
+                    state.connect(&id, network, aliases)?;
+                }
+                log::debug!("created mock container {} for {}", id, image.descriptor());
+                id
+            }
+        };
+        if let Some(delay) = script.create_delay {
+            rt::sleep(delay).await;
+        }
+        Ok(id)
+    }
+
+    fn native(&self) -> &Self::Client {
+        self
+    }
+
+    fn stdout_logs(&self, id: &str) -> LogStream<'_> {
+        self.log_lines(id, Output::Stdout)
+    }
+
+    fn stderr_logs(&self, id: &str) -> LogStream<'_> {
+        self.log_lines(id, Output::Stderr)
+    }
+
+    fn logs(&self, id: &str, options: LogOptions) -> FrameStream<'_> {
+        let frames: Vec<Result<LogFrame, io::Error>> = match self.frames(id, &options) {
+            Ok(frames) => frames.into_iter().map(Ok).collect(),
+            Err(err) => vec![Err(io::Error::other(err))],
+        };
+        futures::stream::iter(frames).boxed()
+    }
+
+    async fn host(&self, id: &str) -> Result<net::IpAddr, Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Host)?;
+        let container = state.container(id)?;
+        if container.is_up() && container.is_attached() {
+            Ok(self.host)
+        } else {
+            Err(Error::MissingHost)
+        }
+    }
+
+    async fn ports(&self, id: &str) -> Result<Ports, Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Ports)?;
+        let container = state.container(id)?;
+        if !container.is_up() || !container.is_attached() {
+            return Ok(Ports::default());
+        }
+        Ok(container.port_map().into())
+    }
+
+    async fn rm(&self, id: &str) -> Result<(), Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Remove)?;
+        state
+            .containers
+            .remove(id)
+            .map(|_| ())
+            .ok_or_else(|| Error::NotFound { id: id.to_string() })
+    }
+
+    async fn stop(&self, id: &str) -> Result<(), Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Stop)?;
+        let container = state.container(id)?;
+        if container.is_up() {
+            container.exit(0, false);
+        }
+        Ok(())
+    }
+
+    async fn start(&self, id: &str) -> Result<(), Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Start)?;
+        state.container(id)?.start();
+        Ok(())
+    }
+
+    async fn stop_with_timeout(&self, id: &str, _timeout: Duration) -> Result<(), Self::Error> {
+        self.stop(id).await
+    }
+
+    async fn pause(&self, id: &str) -> Result<(), Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Pause)?;
+        let container = state.container(id)?;
+        if container.status != Status::Running {
+            return Err(Error::NotRunning { id: id.to_string() });
+        }
+        container.status = Status::Paused;
+        Ok(())
+    }
+
+    async fn unpause(&self, id: &str) -> Result<(), Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Unpause)?;
+        let container = state.container(id)?;
+        if container.status != Status::Paused {
+            return Err(Error::NotPaused { id: id.to_string() });
+        }
+        container.status = Status::Running;
+        Ok(())
+    }
+
+    async fn restart(&self, id: &str, _timeout: Option<Duration>) -> Result<(), Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Restart)?;
+        state.container(id)?.start();
+        Ok(())
+    }
+
+    /// Exits the container with `128 + signal`, like a process killed by the signal.
+    async fn kill(&self, id: &str, signal: &str) -> Result<(), Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Kill)?;
+        let number = signal_number(signal).ok_or_else(|| Error::InvalidSignal {
+            signal: signal.to_string(),
+        })?;
+        let container = state.container(id)?;
+        if !container.is_up() {
+            return Err(Error::NotRunning { id: id.to_string() });
+        }
+        container.exit(128 + number, false);
+        Ok(())
+    }
+
+    async fn wait(&self, id: &str) -> Result<ExitStatus, Self::Error> {
+        self.state().fail(Operation::Wait)?;
+        while self.state().container(id)?.is_up() {
+            rt::sleep(Duration::from_millis(10)).await;
+        }
+        Ok(self.state().container(id)?.exit.unwrap_or(ExitStatus {
+            code: 0,
+            oom_killed: false,
+        }))
+    }
+
+    async fn inspect(&self, id: &str) -> Result<ContainerInspectResponse, Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Inspect)?;
+        let container = state.container(id)?;
+        let running = container.is_up();
+        let status = match container.status {
+            Status::Created => ContainerStateStatusEnum::CREATED,
+            Status::Running => ContainerStateStatusEnum::RUNNING,
+            Status::Paused => ContainerStateStatusEnum::PAUSED,
+            Status::Exited => ContainerStateStatusEnum::EXITED,
+        };
+        Ok(ContainerInspectResponse {
+            id: Some(id.to_string()),
+            image: Some(container.image.clone()),
+            state: Some(ContainerState {
+                status: Some(status),
+                running: Some(running),
+                paused: Some(container.status == Status::Paused),
+                exit_code: container.exit.map(|exit| exit.code),
+                oom_killed: container.exit.map(|exit| exit.oom_killed),
+                health: container.health.map(|status| Health {
+                    status: Some(status),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            network_settings: Some(NetworkSettings {
+                ip_address: (running && container.bridged).then(|| self.host.to_string()),
+                ports: container.is_attached().then(|| container.port_map()),
+                networks: Some(
+                    container
+                        .networks
+                        .iter()
+                        .map(|endpoint| {
+                            let settings = EndpointSettings {
+                                ip_address: running.then(|| endpoint.address.to_string()),
+                                aliases: Some(endpoint.aliases.clone()),
+                                ..Default::default()
+                            };
+                            (endpoint.network.clone(), settings)
+                        })
+                        .collect(),
+                ),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    async fn exec(&self, id: &str, cmd: Vec<String>) -> Result<ExecResult, Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Exec)?;
+        if state.container(id)?.status != Status::Running {
+            return Err(Error::NotRunning { id: id.to_string() });
+        }
+        state.execs.push((id.to_string(), cmd.clone()));
+        Ok(self.execs.get(&cmd).cloned().unwrap_or(ExecResult {
+            exit_code: Some(0),
+            ..Default::default()
+        }))
+    }
+
+    /// Streams the samples pushed so far, the stream is not followed.
+    fn stats(&self, id: &str) -> StatsStream<'_, Self::Error> {
+        let samples: Vec<Result<Sample, Error>> = match self.samples(id) {
+            Ok(samples) => samples.into_iter().map(Ok).collect(),
+            Err(err) => vec![Err(err)],
+        };
+        futures::stream::iter(samples).boxed()
+    }
+
+    /// Returns the last pushed sample, or an empty one.
+    async fn stats_snapshot(&self, id: &str) -> Result<Sample, Self::Error> {
+        Ok(self.samples(id)?.pop().unwrap_or_default())
+    }
+
+    async fn create_volume(&self, config: &VolumeConfig) -> Result<bool, Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Volume)?;
+        if state.volumes.contains_key(&config.name) {
+            return Ok(false);
+        }
+        state.volumes.insert(
+            config.name.clone(),
+            MockVolume {
+                config: config.clone(),
+                archives: Vec::new(),
+            },
+        );
+        Ok(true)
+    }
+
+    async fn rm_volume(&self, name: &str) -> Result<(), Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Volume)?;
+        state
+            .volumes
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| Error::VolumeNotFound {
+                name: name.to_string(),
+            })
+    }
+
+    async fn populate_volume(&self, name: &str, archive: Vec<u8>) -> Result<(), Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Volume)?;
+        state
+            .volumes
+            .get_mut(name)
+            .ok_or_else(|| Error::VolumeNotFound {
+                name: name.to_string(),
+            })?
+            .archives
+            .push(archive);
+        Ok(())
+    }
+
+    async fn commit(&self, id: &str, repo: &str, tag: &str) -> Result<(), Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Commit)?;
+        state.container(id)?;
+        state
+            .images
+            .insert(format!("{}:{}", repo, tag), id.to_string());
+        Ok(())
+    }
+
+    async fn rm_image(&self, image: &str) -> Result<(), Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::RemoveImage)?;
+        let (name, tag) = crate::image::split_reference(image);
+        let reference = format!("{}:{}", name, tag.unwrap_or("latest"));
+        state
+            .images
+            .remove(&reference)
+            .map(|_| ())
+            .ok_or_else(|| Error::ImageNotFound {
+                image: image.to_string(),
+            })
+    }
+
+    fn reuse_enabled(&self) -> bool {
+        self.reuse.unwrap_or_else(reuse::enabled)
+    }
+
+    async fn host_route(&self) -> Result<HostRoute, Self::Error> {
+        self.state().fail(Operation::Host)?;
+        Ok(self.host_route.clone())
+    }
+
+    async fn create_network(&self, name: &str) -> Result<bool, Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Network)?;
+        Ok(state.networks.insert(name.to_string()))
+    }
+
+    async fn rm_network(&self, name: &str) -> Result<(), Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Network)?;
+        let in_use = state
+            .containers
+            .values()
+            .any(|container| container.is_up() && container.is_connected(name));
+        if in_use {
+            return Err(Error::NetworkInUse {
+                name: name.to_string(),
+            });
+        }
+        if !state.networks.remove(name) {
+            return Err(Error::NetworkNotFound {
+                name: name.to_string(),
+            });
+        }
+        for container in state.containers.values_mut() {
+            container
+                .networks
+                .retain(|endpoint| endpoint.network != name);
+        }
+        Ok(())
+    }
+
+    async fn connect_network(
+        &self,
+        network: &str,
+        id: &str,
+        aliases: Vec<String>,
+    ) -> Result<(), Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Network)?;
+        state.connect(id, network, aliases)
+    }
+
+    async fn disconnect_network(&self, network: &str, id: &str) -> Result<(), Self::Error> {
+        let mut state = self.state();
+        state.fail(Operation::Network)?;
+        let container = state.container(id)?;
+        if !container.is_connected(network) {
+            return Err(Error::NotConnected {
+                id: id.to_string(),
+                network: network.to_string(),
+            });
+        }
+        container
+            .networks
+            .retain(|endpoint| endpoint.network != network);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DockerClient, Error, ExecResult, HealthStatusEnum, LogFrame, LogOptions, MockClient,
+        Operation, Output, Status,
+    };
+    use crate::{wait, DockerImage, WaitFor};
+    use color_eyre::eyre;
+    use futures::TryStreamExt;
+    use pretty_assertions::{assert_eq, assert_ne};
+    use std::net;
+    use std::time::SystemTime;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn container_lifecycle() -> eyre::Result<()> {
+        let client = MockClient::new();
+        let container = client.create(DockerImage::new("redis")).await?;
+        let id = container.id().to_string();
+        assert_eq!(client.status(&id), Some(Status::Created));
+        assert_eq!(client.image(&id).as_deref(), Some("redis:latest"));
+
+        container.start().await?;
+        assert_eq!(client.status(&id), Some(Status::Running));
+        container.stop().await?;
+        assert_eq!(client.status(&id), Some(Status::Exited));
+
+        container.rm().await?;
+        assert_eq!(client.status(&id), None);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pause_kill_and_wait() -> eyre::Result<()> {
+        let client = MockClient::new();
+        let container = client.create(DockerImage::new("redis")).await?;
+        let id = container.id().to_string();
+        container.start().await?;
+
+        container.pause().await?;
+        assert_eq!(client.status(&id), Some(Status::Paused));
+        let err = container.exec(["redis-cli", "ping"]).await.unwrap_err();
+        assert!(err.is_conflict());
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::NotRunning { .. })
+        ));
+        container.unpause().await?;
+        assert!(container.unpause().await.unwrap_err().is_conflict());
+
+        let wait = tokio::spawn({
+            let client = client.clone();
+            let id = id.clone();
+            async move { client.wait(&id).await }
+        });
+        let err = container.kill("SIGWINCH").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::InvalidSignal { .. })
+        ));
+        container.kill("SIGKILL").await?;
+        let status = wait.await??;
+        assert_eq!(status.code, 137);
+        assert!(!status.success());
+
+        container.restart().await?;
+        assert_eq!(client.status(&id), Some(Status::Running));
+        client.oom_kill(&id)?;
+        let status = container.wait().await?;
+        assert!(status.oom_killed);
+        assert_eq!(
+            status.to_string(),
+            "exit code 137, killed by the OOM killer"
+        );
+        let state = container.inspect().await?.state.unwrap_or_default();
+        assert_eq!(state.oom_killed, Some(true));
+
+        container.start().await?;
+        container
+            .stop_with_timeout(std::time::Duration::from_secs(1))
+            .await?;
+        assert!(container.wait().await?.success());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn drop_removes_container() -> eyre::Result<()> {
+        let client = MockClient::new();
+        let container = client.create(DockerImage::new("redis")).await?;
+        container.start().await?;
+        assert_eq!(client.container_ids().len(), 1);
+        drop(container);
+        assert!(client.container_ids().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn host_and_ports_of_running_container() -> eyre::Result<()> {
+        let host = net::IpAddr::V4(net::Ipv4Addr::new(10, 0, 0, 2));
+        let client = MockClient::new().with_host(host);
+        let image = DockerImage::new("nginx")
+            .with_mapped_port(8080, 80)
+            .with_mapped_port(0, 443);
+        let container = client.create(image).await?;
+
+        assert!(matches!(
+            container.host().await.unwrap_err().downcast_ref::<Error>(),
+            Some(Error::MissingHost)
+        ));
+        assert_eq!(container.mapped_port_ipv4(80).await?, None);
+
+        container.start().await?;
+        assert_eq!(container.host().await?, host);
+        assert_eq!(container.mapped_port_ipv4(80).await?, Some(8080));
+        assert_eq!(container.mapped_port_ipv4(443).await?, Some(49153));
+        assert_eq!(container.mapped_port_ipv6(80).await?, None);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scripted_logs() -> eyre::Result<()> {
+        let client = MockClient::new()
+            .with_stdout("redis", ["booting", "Ready to accept connections"])
+            .with_stderr("redis", ["warning"]);
+        let container = client.create(DockerImage::new("redis")).await?;
+        container.start().await?;
+
+        client
+            .stdout_logs(container.id())
+            .wait_for_message("Ready to accept")
+            .await?;
+        client
+            .stderr_logs(container.id())
+            .wait_for_message("warning")
+            .await?;
+
+        client.push_stdout(container.id(), "late line")?;
+        client
+            .stdout_logs(container.id())
+            .wait_for_message("late")
+            .await?;
+        assert!(client
+            .stdout_logs(container.id())
+            .wait_for_message("never logged")
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn interleaved_log_frames() -> eyre::Result<()> {
+        let client = MockClient::new().with_stdout("redis", ["booting"]);
+        let container = client.create(DockerImage::new("redis")).await?;
+        container.start().await?;
+        client.push_stderr(container.id(), "warning")?;
+        let since = SystemTime::now();
+        client.push_stdout(container.id(), "ready")?;
+
+        let tagged = |frames: Vec<LogFrame>| -> Vec<(Output, String)> {
+            frames
+                .iter()
+                .map(|frame| (frame.output, frame.line().into_owned()))
+                .collect()
+        };
+        assert_eq!(
+            tagged(container.logs_snapshot().await?),
+            vec![
+                (Output::Stdout, "booting".to_string()),
+                (Output::Stderr, "warning".to_string()),
+                (Output::Stdout, "ready".to_string()),
+            ]
+        );
+
+        let frames: Vec<LogFrame> = container
+            .logs(LogOptions::new().with_tail(2).with_stdout(false))
+            .try_collect()
+            .await?;
+        assert_eq!(
+            tagged(frames),
+            vec![(Output::Stderr, "warning".to_string())]
+        );
+
+        let frames: Vec<LogFrame> = container
+            .logs(LogOptions::new().with_since(since).with_timestamps(true))
+            .try_collect()
+            .await?;
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].line(), "ready");
+        assert!(frames[0].timestamp >= Some(since));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn injected_failures() -> eyre::Result<()> {
+        let client = MockClient::new();
+        client.inject_failure(Operation::Create, "no space left on device");
+        let err = client.create(DockerImage::new("redis")).await.unwrap_err();
+        assert!(err.is_retryable());
+        assert_eq!(err.image(), Some("redis:latest"));
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Injected {
+                operation: Operation::Create,
+                ..
+            })
+        ));
+
+        let container = client.create(DockerImage::new("redis")).await?;
+        client.inject_failure(Operation::Start, "port already allocated");
+        let err = container.start().await.unwrap_err();
+        assert!(err.is_retryable());
+        assert_eq!(err.container(), Some(container.id()));
+        assert_eq!(client.status(container.id()), Some(Status::Created));
+        container.start().await?;
+        assert_eq!(client.status(container.id()), Some(Status::Running));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failed_removal_is_retried_on_drop() -> eyre::Result<()> {
+        let client = MockClient::new();
+        let container = client.create(DockerImage::new("redis")).await?;
+        let id = container.id().to_string();
+        client.inject_failure(Operation::Remove, "removal already in progress");
+        assert!(container.rm().await.unwrap_err().is_retryable());
+        assert_eq!(client.status(&id), None);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reuse_running_container() -> eyre::Result<()> {
+        let client = MockClient::new().with_reuse(true);
+        let image = || DockerImage::new("kafka").with_reuse(true);
+
+        let container = client.create(image()).await?;
+        let id = container.id().to_string();
+        container.start().await?;
+        drop(container);
+        assert_eq!(client.status(&id), Some(Status::Running));
+
+        let container = client.create(image()).await?;
+        assert_eq!(container.id(), id);
+        let other = client.create(image().with_tag("3")).await?;
+        assert_ne!(other.id(), id);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scripted_exec() -> eyre::Result<()> {
+        let output = ExecResult {
+            exit_code: Some(0),
+            stdout: "PONG\n".into(),
+            stderr: String::new(),
+        };
+        let client = MockClient::new().with_exec(["redis-cli", "ping"], output.clone());
+        let container = client.create(DockerImage::new("redis")).await?;
+        assert!(matches!(
+            container
+                .exec(["redis-cli", "ping"])
+                .await
+                .unwrap_err()
+                .downcast_ref::<Error>(),
+            Some(Error::NotRunning { .. })
+        ));
+
+        container.start().await?;
+        assert_eq!(container.exec(["redis-cli", "ping"]).await?, output);
+        assert!(container.exec(["true"]).await?.success());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn wait_until_ready() -> eyre::Result<()> {
+        let client = MockClient::new().with_stdout("redis", ["Ready to accept connections"]);
+        let image = DockerImage::new("redis")
+            .with_wait_for(WaitFor::message_on_stdout("Ready to accept"))
+            .with_wait_for(WaitFor::millis(1));
+        let container = client.create(image).await?;
+        container.start().await?;
+        container.wait_until_ready().await?;
+
+        let container = client
+            .create(DockerImage::new("redis").with_wait_for(WaitFor::Healthcheck))
+            .await?;
+        container.start().await?;
+        assert!(matches!(
+            container
+                .wait_until_ready()
+                .await
+                .unwrap_err()
+                .downcast_ref(),
+            Some(wait::Error::<Error>::HealthcheckNotConfigured)
+        ));
+        client.set_health(container.id(), HealthStatusEnum::HEALTHY)?;
+        container.wait_until_ready().await?;
+        client.set_health(container.id(), HealthStatusEnum::UNHEALTHY)?;
+        assert!(matches!(
+            container
+                .wait_until_ready()
+                .await
+                .unwrap_err()
+                .downcast_ref(),
+            Some(wait::Error::<Error>::Unhealthy)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unknown_container() -> eyre::Result<()> {
+        let client = MockClient::new();
+        assert!(matches!(
+            client.start("missing").await,
+            Err(Error::NotFound { .. })
+        ));
+
+        let container = client.create(DockerImage::new("redis")).await?;
+        client.rm(container.id()).await?;
+        let err = container.stop().await.unwrap_err();
+        assert!(err.is_not_found() && !err.is_retryable());
+        assert_eq!(err.operation(), crate::error::Operation::Stop);
+        Ok(())
+    }
+}
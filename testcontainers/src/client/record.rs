@@ -0,0 +1,1286 @@
+//! Record-and-replay docker clients for deterministic offline tests.
+//!
+//! A [`RecordingClient`] forwards every call to another client, usually a
+//! [`bollard::Client`](super::bollard::Client), and writes the request and the
+//! response (including the log lines consumed from log streams) to a [`Cassette`].
+//! A [`ReplayClient`] implements [`DockerClient`] on top of a cassette and
+//! serves the recorded responses back without talking to a docker daemon.
+//!
+//! Containers are matched by a hash of their full configuration, so creates of
+//! the same image with different ports, environment or mounts replay their own
+//! responses. Time windows of log requests are not recorded, since they are
+//! usually derived from the wall clock and differ between runs.
+
+use super::{
+    bollard, ContainerInspectResponse, DockerClient, DockerImage, Duration, ExecResult, ExitStatus,
+    FrameStream, HookError, HostRoute, LogOptions, LogStream, Ports, Sample, StatsStream,
+    VolumeConfig,
+};
+use crate::error::{Classify, ErrorKind};
+use crate::logs::LogFrame;
+use crate::reuse;
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::{fmt, fs, io, net};
+
+pub use crate::logs::Output;
+
+/// A docker API request issued through the [`DockerClient`] trait.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    Create {
+        image: String,
+        name: Option<String>,
+        /// The [hash](reuse::hash) of the container configuration.
+        config: String,
+    },
+    Start {
+        id: String,
+    },
+    Stop {
+        id: String,
+    },
+    StopWithTimeout {
+        id: String,
+        timeout_secs: u64,
+    },
+    Pause {
+        id: String,
+    },
+    Unpause {
+        id: String,
+    },
+    Restart {
+        id: String,
+        timeout_secs: Option<u64>,
+    },
+    Kill {
+        id: String,
+        signal: String,
+    },
+    Wait {
+        id: String,
+    },
+    Remove {
+        id: String,
+    },
+    Host {
+        id: String,
+    },
+    Ports {
+        id: String,
+    },
+    Logs {
+        id: String,
+        output: Output,
+    },
+    Frames {
+        id: String,
+        options: LogOptions,
+    },
+    Inspect {
+        id: String,
+    },
+    Exec {
+        id: String,
+        cmd: Vec<String>,
+    },
+    Stats {
+        id: String,
+    },
+    StatsSnapshot {
+        id: String,
+    },
+    CreateVolume {
+        config: VolumeConfig,
+    },
+    RemoveVolume {
+        name: String,
+    },
+    /// Archives are recorded by their size only.
+    PopulateVolume {
+        name: String,
+        bytes: usize,
+    },
+    Commit {
+        id: String,
+        repo: String,
+        tag: String,
+    },
+    RemoveImage {
+        image: String,
+    },
+    HostRoute,
+    CreateNetwork {
+        name: String,
+    },
+    RemoveNetwork {
+        name: String,
+    },
+    ConnectNetwork {
+        network: String,
+        id: String,
+        aliases: Vec<String>,
+    },
+    DisconnectNetwork {
+        network: String,
+        id: String,
+    },
+}
+
+impl Request {
+    /// Returns the request creating a container for `image`.
+    pub fn create(image: &DockerImage) -> Self {
+        Self::Create {
+            image: image.descriptor(),
+            name: image.container_name.clone(),
+            config: reuse::image_hash(image),
+        }
+    }
+
+    /// Returns the request retrieving log frames, without the time window of `options`.
+    pub fn frames(id: &str, options: &LogOptions) -> Self {
+        Self::Frames {
+            id: id.to_string(),
+            options: LogOptions {
+                since: None,
+                until: None,
+                ..options.clone()
+            },
+        }
+    }
+}
+
+/// The recorded response to a [`Request`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Response {
+    Created {
+        id: String,
+    },
+    Empty,
+    /// Whether a volume or network was created, or already existed.
+    Ensured {
+        created: bool,
+    },
+    Host {
+        addr: net::IpAddr,
+    },
+    Ports {
+        ports: Ports,
+    },
+    /// The lines consumed from a log stream and the error that ended it, if any.
+    Logs {
+        lines: Vec<String>,
+        error: Option<String>,
+    },
+    /// The frames consumed from a log frame stream and the error that ended it, if any.
+    Frames {
+        frames: Vec<LogFrame>,
+        error: Option<String>,
+    },
+    Inspect {
+        inspect: Box<ContainerInspectResponse>,
+    },
+    Exec {
+        result: ExecResult,
+    },
+    Exited {
+        status: ExitStatus,
+    },
+    /// The samples consumed from a stats stream and the error that ended it, if any.
+    Stats {
+        samples: Vec<Sample>,
+        error: Option<String>,
+    },
+    Sample {
+        sample: Sample,
+    },
+    HostRoute {
+        route: HostRoute,
+    },
+    Error {
+        message: String,
+        /// Missing in cassettes recorded before errors were classified.
+        #[serde(default)]
+        class: ErrorKind,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Interaction {
+    pub request: Request,
+    pub response: Response,
+}
+
+/// An ordered list of recorded interactions with the docker daemon.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    /// Loads a cassette from a JSON file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let file = fs::File::open(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        serde_json::from_reader(io::BufReader::new(file)).map_err(|source| Error::Json {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Writes the cassette to a JSON file, creating parent directories as needed.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let io_err = |source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(io_err)?;
+        }
+        let file = fs::File::create(path).map_err(io_err)?;
+        serde_json::to_writer_pretty(io::BufWriter::new(file), self).map_err(|source| Error::Json {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("no recorded interaction for {0:?}")]
+    Unrecorded(Request),
+
+    #[error("recorded response {response:?} does not match {request:?}")]
+    Mismatch {
+        request: Request,
+        response: Box<Response>,
+    },
+
+    #[error("recorded failure: {message}")]
+    Recorded { message: String, kind: ErrorKind },
+
+    #[error("failed to access cassette {}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+
+    #[error("failed to (de)serialize cassette {}", path.display())]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[error(transparent)]
+    Hook(#[from] HookError),
+}
+
+impl Classify for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Recorded { kind, .. } => *kind,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Writes the cassette once the last clone of a [`RecordingClient`] is dropped.
+struct Recorder {
+    path: PathBuf,
+    cassette: Mutex<Cassette>,
+}
+
+impl Recorder {
+    fn record(&self, request: Request, response: Response) -> usize {
+        let mut cassette = lock(&self.cassette);
+        cassette
+            .interactions
+            .push(Interaction { request, response });
+        cassette.interactions.len() - 1
+    }
+
+    fn record_result<T, E>(
+        &self,
+        request: Request,
+        result: &Result<T, E>,
+        response: impl FnOnce(&T) -> Response,
+    ) where
+        E: std::error::Error + Classify,
+    {
+        let response = match result {
+            Ok(value) => response(value),
+            Err(err) => Response::Error {
+                message: err.to_string(),
+                class: err.kind(),
+            },
+        };
+        self.record(request, response);
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        lock(&self.cassette).save(&self.path)
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(err) = self.save() {
+            log::error!("failed to save cassette: {}", err);
+        }
+    }
+}
+
+/// A [`DockerClient`] that records all interactions of another client to a cassette.
+///
+/// The cassette is written when [`RecordingClient::save`] is called and when
+/// the last clone of the client (including those held by containers) is dropped.
+#[derive(Clone)]
+pub struct RecordingClient<C = bollard::Client> {
+    inner: C,
+    recorder: Arc<Recorder>,
+}
+
+impl<C> fmt::Debug for RecordingClient<C>
+where
+    C: DockerClient + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordingClient")
+            .field("inner", &self.inner)
+            .field("path", &self.recorder.path)
+            .finish()
+    }
+}
+
+impl<C> RecordingClient<C>
+where
+    C: DockerClient,
+{
+    /// Records the interactions of `inner` to a cassette at `path`.
+    pub fn new(inner: C, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            recorder: Arc::new(Recorder {
+                path: path.into(),
+                cassette: Mutex::new(Cassette::default()),
+            }),
+        }
+    }
+
+    /// Returns a copy of the interactions recorded so far.
+    pub fn cassette(&self) -> Cassette {
+        lock(&self.recorder.cassette).clone()
+    }
+
+    /// Writes the interactions recorded so far to the cassette file.
+    pub fn save(&self) -> Result<(), Error> {
+        self.recorder.save()
+    }
+
+    fn log_lines<'a>(&'a self, id: &str, output: Output, stream: LogStream<'a>) -> LogStream<'a> {
+        let request = Request::Logs {
+            id: id.to_string(),
+            output,
+        };
+        let response = Response::Logs {
+            lines: vec![],
+            error: None,
+        };
+        let index = self.recorder.record(request, response);
+        let recorder = self.recorder.clone();
+        LogStream::new(stream.into_inner().inspect(move |line| {
+            let mut cassette = lock(&recorder.cassette);
+            if let Response::Logs { lines, error } = &mut cassette.interactions[index].response {
+                match line {
+                    Ok(line) => lines.push(line.clone()),
+                    Err(err) => *error = Some(err.to_string()),
+                }
+            }
+        }))
+    }
+}
+
+#[async_trait]
+impl<C> DockerClient for RecordingClient<C>
+where
+    C: DockerClient,
+{
+    type Client = C;
+    type Error = C::Error;
+
+    async fn create_container(&self, image: &DockerImage) -> Result<String, Self::Error> {
+        let request = Request::create(image);
+        let result = self.inner.create_container(image).await;
+        self.recorder
+            .record_result(request, &result, |id| Response::Created { id: id.clone() });
+        result
+    }
+
+    fn native(&self) -> &Self::Client {
+        &self.inner
+    }
+
+    fn detached(&self) -> Result<Self, Self::Error> {
+        Ok(Self {
+            inner: self.inner.detached()?,
+            recorder: self.recorder.clone(),
+        })
+    }
+
+    fn stdout_logs(&self, id: &str) -> LogStream<'_> {
+        self.log_lines(id, Output::Stdout, self.inner.stdout_logs(id))
+    }
+
+    fn stderr_logs(&self, id: &str) -> LogStream<'_> {
+        self.log_lines(id, Output::Stderr, self.inner.stderr_logs(id))
+    }
+
+    fn logs(&self, id: &str, options: LogOptions) -> FrameStream<'_> {
+        let request = Request::frames(id, &options);
+        let response = Response::Frames {
+            frames: vec![],
+            error: None,
+        };
+        let index = self.recorder.record(request, response);
+        let recorder = self.recorder.clone();
+        self.inner
+            .logs(id, options)
+            .inspect(move |frame| {
+                let mut cassette = lock(&recorder.cassette);
+                if let Response::Frames { frames, error } =
+                    &mut cassette.interactions[index].response
+                {
+                    match frame {
+                        Ok(frame) => frames.push(frame.clone()),
+                        Err(err) => *error = Some(err.to_string()),
+                    }
+                }
+            })
+            .boxed()
+    }
+
+    async fn host(&self, id: &str) -> Result<net::IpAddr, Self::Error> {
+        let result = self.inner.host(id).await;
+        let request = Request::Host { id: id.to_string() };
+        self.recorder
+            .record_result(request, &result, |addr| Response::Host { addr: *addr });
+        result
+    }
+
+    async fn ports(&self, id: &str) -> Result<Ports, Self::Error> {
+        let result = self.inner.ports(id).await;
+        let request = Request::Ports { id: id.to_string() };
+        self.recorder
+            .record_result(request, &result, |ports| Response::Ports {
+                ports: ports.clone(),
+            });
+        result
+    }
+
+    async fn rm(&self, id: &str) -> Result<(), Self::Error> {
+        let result = self.inner.rm(id).await;
+        let request = Request::Remove { id: id.to_string() };
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+
+    async fn stop(&self, id: &str) -> Result<(), Self::Error> {
+        let result = self.inner.stop(id).await;
+        let request = Request::Stop { id: id.to_string() };
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+
+    async fn start(&self, id: &str) -> Result<(), Self::Error> {
+        let result = self.inner.start(id).await;
+        let request = Request::Start { id: id.to_string() };
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+
+    async fn inspect(&self, id: &str) -> Result<ContainerInspectResponse, Self::Error> {
+        let result = self.inner.inspect(id).await;
+        let request = Request::Inspect { id: id.to_string() };
+        self.recorder
+            .record_result(request, &result, |inspect| Response::Inspect {
+                inspect: Box::new(inspect.clone()),
+            });
+        result
+    }
+
+    async fn exec(&self, id: &str, cmd: Vec<String>) -> Result<ExecResult, Self::Error> {
+        let result = self.inner.exec(id, cmd.clone()).await;
+        let request = Request::Exec {
+            id: id.to_string(),
+            cmd,
+        };
+        self.recorder
+            .record_result(request, &result, |result| Response::Exec {
+                result: result.clone(),
+            });
+        result
+    }
+
+    fn stats(&self, id: &str) -> StatsStream<'_, Self::Error> {
+        let request = Request::Stats { id: id.to_string() };
+        let response = Response::Stats {
+            samples: vec![],
+            error: None,
+        };
+        let index = self.recorder.record(request, response);
+        let recorder = self.recorder.clone();
+        self.inner
+            .stats(id)
+            .inspect(move |sample| {
+                let mut cassette = lock(&recorder.cassette);
+                if let Response::Stats { samples, error } =
+                    &mut cassette.interactions[index].response
+                {
+                    match sample {
+                        Ok(sample) => samples.push(*sample),
+                        Err(err) => *error = Some(err.to_string()),
+                    }
+                }
+            })
+            .boxed()
+    }
+
+    async fn stats_snapshot(&self, id: &str) -> Result<Sample, Self::Error> {
+        let result = self.inner.stats_snapshot(id).await;
+        let request = Request::StatsSnapshot { id: id.to_string() };
+        self.recorder
+            .record_result(request, &result, |sample| Response::Sample {
+                sample: *sample,
+            });
+        result
+    }
+
+    async fn stop_with_timeout(&self, id: &str, timeout: Duration) -> Result<(), Self::Error> {
+        let result = self.inner.stop_with_timeout(id, timeout).await;
+        let request = Request::StopWithTimeout {
+            id: id.to_string(),
+            timeout_secs: timeout.as_secs(),
+        };
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+
+    async fn pause(&self, id: &str) -> Result<(), Self::Error> {
+        let result = self.inner.pause(id).await;
+        let request = Request::Pause { id: id.to_string() };
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+
+    async fn unpause(&self, id: &str) -> Result<(), Self::Error> {
+        let result = self.inner.unpause(id).await;
+        let request = Request::Unpause { id: id.to_string() };
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+
+    async fn restart(&self, id: &str, timeout: Option<Duration>) -> Result<(), Self::Error> {
+        let result = self.inner.restart(id, timeout).await;
+        let request = Request::Restart {
+            id: id.to_string(),
+            timeout_secs: timeout.map(|timeout| timeout.as_secs()),
+        };
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+
+    async fn kill(&self, id: &str, signal: &str) -> Result<(), Self::Error> {
+        let result = self.inner.kill(id, signal).await;
+        let request = Request::Kill {
+            id: id.to_string(),
+            signal: signal.to_string(),
+        };
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+
+    async fn wait(&self, id: &str) -> Result<ExitStatus, Self::Error> {
+        let result = self.inner.wait(id).await;
+        let request = Request::Wait { id: id.to_string() };
+        self.recorder
+            .record_result(request, &result, |status| Response::Exited {
+                status: *status,
+            });
+        result
+    }
+
+    async fn create_volume(&self, config: &VolumeConfig) -> Result<bool, Self::Error> {
+        let result = self.inner.create_volume(config).await;
+        let request = Request::CreateVolume {
+            config: config.clone(),
+        };
+        self.recorder
+            .record_result(request, &result, |created| Response::Ensured {
+                created: *created,
+            });
+        result
+    }
+
+    async fn rm_volume(&self, name: &str) -> Result<(), Self::Error> {
+        let result = self.inner.rm_volume(name).await;
+        let request = Request::RemoveVolume {
+            name: name.to_string(),
+        };
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+
+    async fn populate_volume(&self, name: &str, archive: Vec<u8>) -> Result<(), Self::Error> {
+        let request = Request::PopulateVolume {
+            name: name.to_string(),
+            bytes: archive.len(),
+        };
+        let result = self.inner.populate_volume(name, archive).await;
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+
+    async fn commit(&self, id: &str, repo: &str, tag: &str) -> Result<(), Self::Error> {
+        let result = self.inner.commit(id, repo, tag).await;
+        let request = Request::Commit {
+            id: id.to_string(),
+            repo: repo.to_string(),
+            tag: tag.to_string(),
+        };
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+
+    async fn rm_image(&self, image: &str) -> Result<(), Self::Error> {
+        let result = self.inner.rm_image(image).await;
+        let request = Request::RemoveImage {
+            image: image.to_string(),
+        };
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+
+    async fn host_route(&self) -> Result<HostRoute, Self::Error> {
+        let result = self.inner.host_route().await;
+        self.recorder
+            .record_result(Request::HostRoute, &result, |route| Response::HostRoute {
+                route: route.clone(),
+            });
+        result
+    }
+
+    async fn create_network(&self, name: &str) -> Result<bool, Self::Error> {
+        let result = self.inner.create_network(name).await;
+        let request = Request::CreateNetwork {
+            name: name.to_string(),
+        };
+        self.recorder
+            .record_result(request, &result, |created| Response::Ensured {
+                created: *created,
+            });
+        result
+    }
+
+    async fn rm_network(&self, name: &str) -> Result<(), Self::Error> {
+        let result = self.inner.rm_network(name).await;
+        let request = Request::RemoveNetwork {
+            name: name.to_string(),
+        };
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+
+    async fn connect_network(
+        &self,
+        network: &str,
+        id: &str,
+        aliases: Vec<String>,
+    ) -> Result<(), Self::Error> {
+        let request = Request::ConnectNetwork {
+            network: network.to_string(),
+            id: id.to_string(),
+            aliases: aliases.clone(),
+        };
+        let result = self.inner.connect_network(network, id, aliases).await;
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+
+    async fn disconnect_network(&self, network: &str, id: &str) -> Result<(), Self::Error> {
+        let result = self.inner.disconnect_network(network, id).await;
+        let request = Request::DisconnectNetwork {
+            network: network.to_string(),
+            id: id.to_string(),
+        };
+        self.recorder
+            .record_result(request, &result, |_| Response::Empty);
+        result
+    }
+}
+
+#[derive(Debug)]
+struct Tape {
+    interactions: Vec<Interaction>,
+    played: Vec<bool>,
+}
+
+/// A [`DockerClient`] that serves the responses recorded in a [`Cassette`].
+///
+/// Each request is answered by the first not yet replayed interaction with an
+/// equal request, so the order of independent requests (e.g. of concurrent
+/// tests) may differ from the recording.
+#[derive(Clone)]
+pub struct ReplayClient {
+    tape: Arc<Mutex<Tape>>,
+}
+
+impl fmt::Debug for ReplayClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplayClient").finish()
+    }
+}
+
+impl From<Cassette> for ReplayClient {
+    fn from(cassette: Cassette) -> Self {
+        let played = vec![false; cassette.interactions.len()];
+        Self {
+            tape: Arc::new(Mutex::new(Tape {
+                interactions: cassette.interactions,
+                played,
+            })),
+        }
+    }
+}
+
+impl ReplayClient {
+    /// Replays the cassette stored at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Cassette::load(path)?.into())
+    }
+
+    /// Returns the interactions that have not been replayed yet.
+    pub fn remaining(&self) -> Vec<Interaction> {
+        let tape = lock(&self.tape);
+        tape.interactions
+            .iter()
+            .zip(&tape.played)
+            .filter(|(_, played)| !**played)
+            .map(|(interaction, _)| interaction.clone())
+            .collect()
+    }
+
+    fn replay(&self, request: Request) -> Result<Response, Error> {
+        let mut tape = lock(&self.tape);
+        let Tape {
+            interactions,
+            played,
+        } = &mut *tape;
+        let index = interactions
+            .iter()
+            .zip(played.iter())
+            .position(|(interaction, played)| !played && interaction.request == request)
+            .ok_or_else(|| Error::Unrecorded(request.clone()))?;
+        played[index] = true;
+        match interactions[index].response.clone() {
+            Response::Error { message, class } => Err(Error::Recorded {
+                message,
+                kind: class,
+            }),
+            response => Ok(response),
+        }
+    }
+
+    fn replay_empty(&self, request: Request) -> Result<(), Error> {
+        match self.replay(request.clone())? {
+            Response::Empty => Ok(()),
+            response => Err(Error::Mismatch {
+                request,
+                response: Box::new(response),
+            }),
+        }
+    }
+
+    /// Replays whether a resource was created, which cassettes recorded
+    /// before this was tracked do not know.
+    fn replay_ensured(&self, request: Request) -> Result<bool, Error> {
+        match self.replay(request.clone())? {
+            Response::Ensured { created } => Ok(created),
+            Response::Empty => Ok(true),
+            response => Err(Error::Mismatch {
+                request,
+                response: Box::new(response),
+            }),
+        }
+    }
+
+    fn log_lines(&self, id: &str, output: Output) -> LogStream<'_> {
+        let request = Request::Logs {
+            id: id.to_string(),
+            output,
+        };
+        let lines: Vec<Result<String, io::Error>> = match self.replay(request.clone()) {
+            Ok(Response::Logs { lines, error }) => lines
+                .into_iter()
+                .map(Ok)
+                .chain(error.map(|err| Err(io::Error::other(err))))
+                .collect(),
+            Ok(response) => vec![Err(io::Error::other(Error::Mismatch {
+                request,
+                response: Box::new(response),
+            }))],
+            Err(err) => vec![Err(io::Error::other(err))],
+        };
+        LogStream::new(futures::stream::iter(lines))
+    }
+}
+
+#[async_trait]
+impl DockerClient for ReplayClient {
+    type Client = Self;
+    type Error = Error;
+
+    async fn create_container(&self, image: &DockerImage) -> Result<String, Self::Error> {
+        let request = Request::create(image);
+        match self.replay(request.clone())? {
+            Response::Created { id } => Ok(id),
+            response => Err(Error::Mismatch {
+                request,
+                response: Box::new(response),
+            }),
+        }
+    }
+
+    fn native(&self) -> &Self::Client {
+        self
+    }
+
+    fn stdout_logs(&self, id: &str) -> LogStream<'_> {
+        self.log_lines(id, Output::Stdout)
+    }
+
+    fn stderr_logs(&self, id: &str) -> LogStream<'_> {
+        self.log_lines(id, Output::Stderr)
+    }
+
+    fn logs(&self, id: &str, options: LogOptions) -> FrameStream<'_> {
+        let request = Request::frames(id, &options);
+        let frames: Vec<Result<LogFrame, io::Error>> = match self.replay(request.clone()) {
+            Ok(Response::Frames { frames, error }) => frames
+                .into_iter()
+                .map(Ok)
+                .chain(error.map(|err| Err(io::Error::other(err))))
+                .collect(),
+            Ok(response) => vec![Err(io::Error::other(Error::Mismatch {
+                request,
+                response: Box::new(response),
+            }))],
+            Err(err) => vec![Err(io::Error::other(err))],
+        };
+        futures::stream::iter(frames).boxed()
+    }
+
+    async fn host(&self, id: &str) -> Result<net::IpAddr, Self::Error> {
+        let request = Request::Host { id: id.to_string() };
+        match self.replay(request.clone())? {
+            Response::Host { addr } => Ok(addr),
+            response => Err(Error::Mismatch {
+                request,
+                response: Box::new(response),
+            }),
+        }
+    }
+
+    async fn ports(&self, id: &str) -> Result<Ports, Self::Error> {
+        let request = Request::Ports { id: id.to_string() };
+        match self.replay(request.clone())? {
+            Response::Ports { ports } => Ok(ports),
+            response => Err(Error::Mismatch {
+                request,
+                response: Box::new(response),
+            }),
+        }
+    }
+
+    async fn rm(&self, id: &str) -> Result<(), Self::Error> {
+        self.replay_empty(Request::Remove { id: id.to_string() })
+    }
+
+    async fn stop(&self, id: &str) -> Result<(), Self::Error> {
+        self.replay_empty(Request::Stop { id: id.to_string() })
+    }
+
+    async fn start(&self, id: &str) -> Result<(), Self::Error> {
+        self.replay_empty(Request::Start { id: id.to_string() })
+    }
+
+    async fn inspect(&self, id: &str) -> Result<ContainerInspectResponse, Self::Error> {
+        let request = Request::Inspect { id: id.to_string() };
+        match self.replay(request.clone())? {
+            Response::Inspect { inspect } => Ok(*inspect),
+            response => Err(Error::Mismatch {
+                request,
+                response: Box::new(response),
+            }),
+        }
+    }
+
+    async fn exec(&self, id: &str, cmd: Vec<String>) -> Result<ExecResult, Self::Error> {
+        let request = Request::Exec {
+            id: id.to_string(),
+            cmd,
+        };
+        match self.replay(request.clone())? {
+            Response::Exec { result } => Ok(result),
+            response => Err(Error::Mismatch {
+                request,
+                response: Box::new(response),
+            }),
+        }
+    }
+
+    fn stats(&self, id: &str) -> StatsStream<'_, Self::Error> {
+        let request = Request::Stats { id: id.to_string() };
+        let samples: Vec<Result<Sample, Error>> = match self.replay(request.clone()) {
+            Ok(Response::Stats { samples, error }) => samples
+                .into_iter()
+                .map(Ok)
+                .chain(error.map(|message| {
+                    Err(Error::Recorded {
+                        message,
+                        kind: ErrorKind::Other,
+                    })
+                }))
+                .collect(),
+            Ok(response) => vec![Err(Error::Mismatch {
+                request,
+                response: Box::new(response),
+            })],
+            Err(err) => vec![Err(err)],
+        };
+        futures::stream::iter(samples).boxed()
+    }
+
+    async fn stats_snapshot(&self, id: &str) -> Result<Sample, Self::Error> {
+        let request = Request::StatsSnapshot { id: id.to_string() };
+        match self.replay(request.clone())? {
+            Response::Sample { sample } => Ok(sample),
+            response => Err(Error::Mismatch {
+                request,
+                response: Box::new(response),
+            }),
+        }
+    }
+
+    async fn stop_with_timeout(&self, id: &str, timeout: Duration) -> Result<(), Self::Error> {
+        self.replay_empty(Request::StopWithTimeout {
+            id: id.to_string(),
+            timeout_secs: timeout.as_secs(),
+        })
+    }
+
+    async fn pause(&self, id: &str) -> Result<(), Self::Error> {
+        self.replay_empty(Request::Pause { id: id.to_string() })
+    }
+
+    async fn unpause(&self, id: &str) -> Result<(), Self::Error> {
+        self.replay_empty(Request::Unpause { id: id.to_string() })
+    }
+
+    async fn restart(&self, id: &str, timeout: Option<Duration>) -> Result<(), Self::Error> {
+        self.replay_empty(Request::Restart {
+            id: id.to_string(),
+            timeout_secs: timeout.map(|timeout| timeout.as_secs()),
+        })
+    }
+
+    async fn kill(&self, id: &str, signal: &str) -> Result<(), Self::Error> {
+        self.replay_empty(Request::Kill {
+            id: id.to_string(),
+            signal: signal.to_string(),
+        })
+    }
+
+    async fn wait(&self, id: &str) -> Result<ExitStatus, Self::Error> {
+        let request = Request::Wait { id: id.to_string() };
+        match self.replay(request.clone())? {
+            Response::Exited { status } => Ok(status),
+            response => Err(Error::Mismatch {
+                request,
+                response: Box::new(response),
+            }),
+        }
+    }
+
+    async fn create_volume(&self, config: &VolumeConfig) -> Result<bool, Self::Error> {
+        self.replay_ensured(Request::CreateVolume {
+            config: config.clone(),
+        })
+    }
+
+    async fn rm_volume(&self, name: &str) -> Result<(), Self::Error> {
+        self.replay_empty(Request::RemoveVolume {
+            name: name.to_string(),
+        })
+    }
+
+    async fn populate_volume(&self, name: &str, archive: Vec<u8>) -> Result<(), Self::Error> {
+        self.replay_empty(Request::PopulateVolume {
+            name: name.to_string(),
+            bytes: archive.len(),
+        })
+    }
+
+    async fn commit(&self, id: &str, repo: &str, tag: &str) -> Result<(), Self::Error> {
+        self.replay_empty(Request::Commit {
+            id: id.to_string(),
+            repo: repo.to_string(),
+            tag: tag.to_string(),
+        })
+    }
+
+    async fn rm_image(&self, image: &str) -> Result<(), Self::Error> {
+        self.replay_empty(Request::RemoveImage {
+            image: image.to_string(),
+        })
+    }
+
+    async fn host_route(&self) -> Result<HostRoute, Self::Error> {
+        match self.replay(Request::HostRoute)? {
+            Response::HostRoute { route } => Ok(route),
+            response => Err(Error::Mismatch {
+                request: Request::HostRoute,
+                response: Box::new(response),
+            }),
+        }
+    }
+
+    async fn create_network(&self, name: &str) -> Result<bool, Self::Error> {
+        self.replay_ensured(Request::CreateNetwork {
+            name: name.to_string(),
+        })
+    }
+
+    async fn rm_network(&self, name: &str) -> Result<(), Self::Error> {
+        self.replay_empty(Request::RemoveNetwork {
+            name: name.to_string(),
+        })
+    }
+
+    async fn connect_network(
+        &self,
+        network: &str,
+        id: &str,
+        aliases: Vec<String>,
+    ) -> Result<(), Self::Error> {
+        self.replay_empty(Request::ConnectNetwork {
+            network: network.to_string(),
+            id: id.to_string(),
+            aliases,
+        })
+    }
+
+    async fn disconnect_network(&self, network: &str, id: &str) -> Result<(), Self::Error> {
+        self.replay_empty(Request::DisconnectNetwork {
+            network: network.to_string(),
+            id: id.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::eyre;
+    use pretty_assertions::assert_eq;
+
+    fn interaction(request: Request, response: Response) -> Interaction {
+        Interaction { request, response }
+    }
+
+    fn frames() -> Vec<LogFrame> {
+        vec![
+            LogFrame {
+                output: Output::Stdout,
+                timestamp: None,
+                bytes: b"Ready to accept connections\n".to_vec(),
+            },
+            LogFrame {
+                output: Output::Stderr,
+                timestamp: None,
+                bytes: b"\xffbinary\n".to_vec(),
+            },
+        ]
+    }
+
+    fn sample() -> Sample {
+        Sample {
+            read: Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_675_082_096)),
+            cpu_percent: 12.5,
+            memory_usage: 8 << 20,
+            memory_limit: 1 << 30,
+            pids: 5,
+            ..Default::default()
+        }
+    }
+
+    fn cassette() -> Cassette {
+        let id = "c0ffee".to_string();
+        Cassette {
+            interactions: vec![
+                interaction(
+                    Request::create(&DockerImage::new("redis")),
+                    Response::Created { id: id.clone() },
+                ),
+                interaction(Request::Start { id: id.clone() }, Response::Empty),
+                interaction(
+                    Request::Logs {
+                        id: id.clone(),
+                        output: Output::Stdout,
+                    },
+                    Response::Logs {
+                        lines: vec!["Ready to accept connections".into()],
+                        error: None,
+                    },
+                ),
+                interaction(
+                    Request::Frames {
+                        id: id.clone(),
+                        options: LogOptions::default(),
+                    },
+                    Response::Frames {
+                        frames: frames(),
+                        error: None,
+                    },
+                ),
+                interaction(
+                    Request::Host { id: id.clone() },
+                    Response::Host {
+                        addr: net::Ipv4Addr::new(172, 17, 0, 2).into(),
+                    },
+                ),
+                interaction(
+                    Request::Exec {
+                        id: id.clone(),
+                        cmd: vec!["redis-cli".into(), "ping".into()],
+                    },
+                    Response::Exec {
+                        result: ExecResult {
+                            exit_code: Some(0),
+                            stdout: "PONG\n".into(),
+                            stderr: String::new(),
+                        },
+                    },
+                ),
+                interaction(
+                    Request::StatsSnapshot { id: id.clone() },
+                    Response::Sample { sample: sample() },
+                ),
+                interaction(
+                    Request::Stop { id: id.clone() },
+                    Response::Error {
+                        message: "container already stopped".into(),
+                        class: ErrorKind::Conflict,
+                    },
+                ),
+                interaction(Request::Remove { id }, Response::Empty),
+            ],
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn replay_recorded_interactions() -> eyre::Result<()> {
+        let client = ReplayClient::from(cassette());
+        let container = client.create(DockerImage::new("redis")).await?;
+        assert_eq!(container.id(), "c0ffee");
+        container.start().await?;
+        client
+            .stdout_logs(container.id())
+            .wait_for_message("Ready to accept")
+            .await?;
+        assert_eq!(container.logs_snapshot().await?, frames());
+        assert_eq!(
+            container.host().await?,
+            net::IpAddr::from(net::Ipv4Addr::new(172, 17, 0, 2))
+        );
+        assert_eq!(
+            container.exec(["redis-cli", "ping"]).await?.stdout,
+            "PONG\n"
+        );
+        assert_eq!(container.stats_snapshot().await?, sample());
+        let err = container.stop().await.unwrap_err();
+        assert!(err.is_conflict());
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Recorded { .. })
+        ));
+        drop(container);
+        assert_eq!(client.remaining(), vec![]);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unrecorded_request() -> eyre::Result<()> {
+        let client = ReplayClient::from(cassette());
+        let err = client
+            .create(DockerImage::new("postgres"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Unrecorded(Request::Create { .. }))
+        ));
+        // the same image with another configuration was not recorded either
+        let err = client
+            .create(DockerImage::new("redis").with_mapped_port(0, 6379))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Unrecorded(Request::Create { .. }))
+        ));
+        assert!(matches!(client.start("c0ffee").await, Ok(())));
+        assert!(matches!(
+            client.start("c0ffee").await,
+            Err(Error::Unrecorded(Request::Start { .. }))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn cassette_roundtrip() -> eyre::Result<()> {
+        let dir =
+            std::env::temp_dir().join(format!("testcontainers-cassette-{}", std::process::id()));
+        let path = dir.join("redis.json");
+        let cassette = cassette();
+        cassette.save(&path)?;
+        let loaded = Cassette::load(&path)?;
+        fs::remove_dir_all(&dir)?;
+        assert_eq!(loaded, cassette);
+        Ok(())
+    }
+}
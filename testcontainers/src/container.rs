@@ -1,8 +1,48 @@
 use super::client::DockerClient;
-use super::image::DockerImage;
-use bollard::Docker;
-use futures::executor::block_on;
-use std::{fmt, net};
+use super::diagnostics::{self, Registration, Report};
+use super::error::{Classify, Error, Operation};
+use super::exec::ExecResult;
+use super::hooks::{ContainerHook, HookError};
+use super::host::Forwarder;
+use super::image::{self, DockerImage, PullPolicy};
+use super::logs::consumer::{self, LogConsumer, LogFollower};
+use super::logs::{FrameStream, LogFrame, LogOptions, LogStream};
+use super::mounts::TempDir;
+use super::ports::Ports;
+use super::proxy;
+use super::rt;
+use super::stats::{self, PeakUsage, Sample, StatsStream};
+use super::trace;
+use super::wait::{self, WaitFor};
+use bollard::models::ContainerInspectResponse;
+use futures::TryStreamExt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use std::{fmt, io, net};
+
+/// The exit code of a container and whether it was killed for running out of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "record", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExitStatus {
+    pub code: i64,
+    pub oom_killed: bool,
+}
+
+impl ExitStatus {
+    pub fn success(&self) -> bool {
+        self.code == 0 && !self.oom_killed
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "exit code {}", self.code)?;
+        if self.oom_killed {
+            write!(f, ", killed by the OOM killer")?;
+        }
+        Ok(())
+    }
+}
 
 // pub struct Container<'d, I: Image> {
 pub struct Container<C>
@@ -14,7 +54,21 @@ where
     // client: Box<&dyn DockerClient>,
     // client: Box<dyn DockerClient<Client = _, Error = _>>,
     client: C,
-    image: DockerImage,
+    image: DockerImage<C>,
+    /// Reusable containers are kept running when dropped.
+    keep: bool,
+    /// Set once the container is removed, so that it is not removed again when dropped.
+    ///
+    /// Atomic since shared containers are removed at exit while still referenced.
+    removed: AtomicBool,
+    /// Keeps the container in the registry of live containers for diagnostics.
+    _diagnostics: Registration,
+    /// Parent span of all operations on the container.
+    span: trace::Span,
+    /// Forwarders of the host access ports, stopped after the container is removed.
+    forwarders: Vec<Forwarder>,
+    /// Host directories of [`DockerImage::with_temp_dir`], removed after the container.
+    temp_dirs: Vec<TempDir>,
     // image: RunnableImage<I>,
     // command: Command,
     // /// Tracks the lifetime of the client to make sure the container is dropped before the client.
@@ -33,13 +87,26 @@ where
         id: String,
         // client: impl DockerClient + 'static,
         client: C, // impl DockerClient + 'static,
-        image: DockerImage,
+        image: DockerImage<C>,
         // command: env::Command,
     ) -> Self {
+        let keep = image.reuse && client.reuse_enabled();
+        let _diagnostics = diagnostics::register(client.clone(), id.clone(), image.descriptor());
+        let span = trace::span!(
+            "testcontainers.container",
+            container.id = %id,
+            image = %image.descriptor()
+        );
         let container = Self {
             id,
             client,
             image,
+            keep,
+            removed: AtomicBool::new(false),
+            _diagnostics,
+            span,
+            forwarders: Vec::new(),
+            temp_dirs: Vec::new(),
             // command,
             // client_lifetime: PhantomData,
         };
@@ -47,54 +114,96 @@ where
         container
     }
 
-    async fn block_until_ready(&self) {
+    /// Keeps the forwarders of the host access ports running as long as the container.
+    pub(crate) fn with_forwarders(mut self, forwarders: Vec<Forwarder>) -> Self {
+        self.forwarders = forwarders;
+        self
+    }
+
+    /// Keeps the host temp directories until the container is dropped.
+    pub(crate) fn with_temp_dirs(mut self, temp_dirs: Vec<TempDir>) -> Self {
+        self.temp_dirs = temp_dirs;
+        self
+    }
+
+    /// Waits until all ready conditions of the image are met.
+    ///
+    /// The cause of a failure is a [`wait::Error`].
+    pub async fn wait_until_ready(&self) -> Result<(), Error> {
         log::debug!("Waiting for container {} to be ready", self.id);
 
-        // for condition in self.image.ready_conditions() {
-        //     match condition {
-        //         WaitFor::StdOutMessage { message } => self
-        //             .docker_client
-        //             .stdout_logs(&self.id)
-        //             .wait_for_message(&message)
-        //             .await
-        //             .unwrap(),
-        //         WaitFor::StdErrMessage { message } => self
-        //             .docker_client
-        //             .stderr_logs(&self.id)
-        //             .wait_for_message(&message)
-        //             .await
-        //             .unwrap(),
-        //         WaitFor::Duration { length } => {
-        //             tokio::time::sleep(length).await;
-        //         }
-        //         WaitFor::Healthcheck => loop {
-        //             use HealthStatusEnum::*;
-
-        //             let health_status = self
-        //                 .docker_client
-        //                 .inspect(&self.id)
-        //                 .await
-        //                 .state
-        //                 .unwrap_or_else(|| panic!("Container state not available"))
-        //                 .health
-        //                 .unwrap_or_else(|| panic!("Health state not available"))
-        //                 .status;
-
-        //             match health_status {
-        //                 Some(HEALTHY) => break,
-        //                 None | Some(EMPTY) | Some(NONE) => {
-        //                     panic!("Healthcheck not configured for container")
-        //                 }
-        //                 Some(UNHEALTHY) => panic!("Healthcheck reports unhealthy"),
-        //                 Some(STARTING) => sleep(Duration::from_millis(100)).await,
-        //             }
-        //             panic!("Healthcheck for the container is not configured");
-        //         },
-        //         WaitFor::Nothing => {}
-        //     }
-        // }
+        for condition in &self.image.waiting_for {
+            let span = trace::span!(
+                parent: self.span,
+                "testcontainers.wait",
+                container.id = %self.id,
+                condition = ?condition
+            );
+            span.run(self.wait_for(condition))
+                .await
+                .map_err(|err| self.error(Operation::Ready, err))?;
+        }
 
         log::debug!("container {} is ready!", self.id);
+        self.run_hooks(ContainerHook::PostReady)
+            .await
+            .map_err(|err| self.error(Operation::Ready, wait::Error::<C::Error>::Hook(err)))
+    }
+
+    /// Waits until a single ready condition is met.
+    async fn wait_for(&self, condition: &WaitFor) -> Result<(), wait::Error<C::Error>> {
+        match condition {
+            WaitFor::StdOutMessage { message } => {
+                self.stdout_logs().wait_for_message(message).await?;
+            }
+            WaitFor::StdErrMessage { message } => {
+                self.stderr_logs().wait_for_message(message).await?;
+            }
+            WaitFor::Duration { length } => {
+                rt::sleep(*length).await;
+            }
+            WaitFor::Healthcheck => loop {
+                use bollard::models::HealthStatusEnum::*;
+
+                let health_status = self
+                    .client
+                    .inspect(&self.id)
+                    .await
+                    .map_err(wait::Error::Inspect)?
+                    .state
+                    .and_then(|state| state.health)
+                    .and_then(|health| health.status);
+
+                match health_status {
+                    Some(HEALTHY) => break,
+                    None | Some(EMPTY) | Some(NONE) => {
+                        return Err(wait::Error::HealthcheckNotConfigured);
+                    }
+                    Some(UNHEALTHY) => return Err(wait::Error::Unhealthy),
+                    Some(STARTING) => {
+                        rt::sleep(Duration::from_millis(100)).await;
+                    }
+                }
+            },
+            WaitFor::Nothing => {}
+        }
+        Ok(())
+    }
+
+    /// Runs the hooks of the image registered for `hook`.
+    pub(crate) async fn run_hooks(&self, hook: ContainerHook) -> Result<(), HookError> {
+        self.image.hooks.run(hook, self).await
+    }
+
+    /// Returns an error of `operation` on this container.
+    pub(crate) fn error<E>(&self, operation: Operation, source: E) -> Error
+    where
+        E: std::error::Error + Classify + Send + Sync + 'static,
+    {
+        Error::new(operation, source)
+            .with_container(&self.id)
+            .with_name(self.image.container_name.clone())
+            .with_image(self.image.descriptor())
     }
 
     /// Returns the id of this container.
@@ -102,46 +211,311 @@ where
         &self.id
     }
 
-    /// Starts the container.
-    pub async fn start(&self) -> Result<(), C::Error> {
+    /// Returns the client that manages this container.
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    /// Returns the image this container was created from.
+    pub fn image(&self) -> &DockerImage<C> {
+        &self.image
+    }
+
+    /// Returns the host path of the temp directory mounted at `dest`.
+    pub fn temp_dir(&self, dest: &str) -> Option<&std::path::Path> {
+        self.temp_dirs
+            .iter()
+            .find(|dir| dir.target() == dest)
+            .map(TempDir::path)
+    }
+
+    /// Starts the container and runs its post-start hooks.
+    pub async fn start(&self) -> Result<(), Error> {
         log::debug!("starting docker container {}", self.id);
-        self.client.start(&self.id).await
+        let span = trace::span!(parent: self.span, "testcontainers.start", container.id = %self.id);
+        span.run(async {
+            self.client.start(&self.id).await?;
+            self.run_hooks(ContainerHook::PostStart).await?;
+            Ok::<_, C::Error>(())
+        })
+        .await
+        .map_err(|err| self.error(Operation::Start, err))
+    }
+
+    /// Runs the pre-stop hooks and stops the container
+    pub async fn stop(&self) -> Result<(), Error> {
+        let span = trace::span!(parent: self.span, "testcontainers.stop", container.id = %self.id);
+        span.run(async {
+            self.run_hooks(ContainerHook::PreStop).await?;
+            log::debug!("stopping docker container {}", self.id);
+            self.client.stop(&self.id).await
+        })
+        .await
+        .map_err(|err| self.error(Operation::Stop, err))
+    }
+
+    /// Runs the pre-stop hooks and stops the container, killing it after `timeout`
+    ///
+    /// The timeout is rounded up to whole seconds.
+    pub async fn stop_with_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        let span = trace::span!(parent: self.span, "testcontainers.stop", container.id = %self.id);
+        span.run(async {
+            self.run_hooks(ContainerHook::PreStop).await?;
+            log::debug!("stopping docker container {} within {:?}", self.id, timeout);
+            self.client.stop_with_timeout(&self.id, timeout).await
+        })
+        .await
+        .map_err(|err| self.error(Operation::Stop, err))
+    }
+
+    /// Freezes all processes of the container
+    pub async fn pause(&self) -> Result<(), Error> {
+        log::debug!("pausing docker container {}", self.id);
+        let span = trace::span!(parent: self.span, "testcontainers.pause", container.id = %self.id);
+        span.run(self.client.pause(&self.id))
+            .await
+            .map_err(|err| self.error(Operation::Pause, err))
     }
 
-    /// Stops the container
-    pub async fn stop(&self) -> Result<(), C::Error> {
-        log::debug!("stopping docker container {}", self.id);
-        self.client.stop(&self.id).await
+    /// Resumes the processes of a paused container
+    pub async fn unpause(&self) -> Result<(), Error> {
+        log::debug!("unpausing docker container {}", self.id);
+        let span =
+            trace::span!(parent: self.span, "testcontainers.unpause", container.id = %self.id);
+        span.run(self.client.unpause(&self.id))
+            .await
+            .map_err(|err| self.error(Operation::Unpause, err))
     }
 
-    /// Removes the container
-    pub async fn rm(self) -> Result<(), C::Error> {
-        log::debug!("removing docker container {}", self.id);
-        self.client.rm(&self.id).await
+    /// Restarts the container, running the pre-stop and post-start hooks
+    pub async fn restart(&self) -> Result<(), Error> {
+        let span =
+            trace::span!(parent: self.span, "testcontainers.restart", container.id = %self.id);
+        span.run(async {
+            self.run_hooks(ContainerHook::PreStop).await?;
+            log::debug!("restarting docker container {}", self.id);
+            self.client.restart(&self.id, None).await?;
+            self.run_hooks(ContainerHook::PostStart).await?;
+            Ok::<_, C::Error>(())
+        })
+        .await
+        .map_err(|err| self.error(Operation::Restart, err))
+    }
+
+    /// Sends a signal such as `SIGKILL` or `SIGTERM` to the main process of the container
+    pub async fn kill(&self, signal: &str) -> Result<(), Error> {
+        log::debug!("sending {} to docker container {}", signal, self.id);
+        let span = trace::span!(
+            parent: self.span,
+            "testcontainers.kill",
+            container.id = %self.id,
+            signal = %signal
+        );
+        span.run(self.client.kill(&self.id, signal))
+            .await
+            .map_err(|err| self.error(Operation::Kill, err))
+    }
+
+    /// Creates an image from the current filesystem of the container, e.g. `postgres:seeded`.
+    ///
+    /// Returns the committed image to create copies of the container from.
+    /// Data in volumes is not part of the image, and the image is not removed
+    /// automatically, see [`crate::snapshot`] for that.
+    pub async fn commit(&self, reference: &str) -> Result<DockerImage, Error> {
+        let (repo, tag) = image::split_reference(reference);
+        let tag = tag.unwrap_or("latest");
+        log::debug!(
+            "committing docker container {} as {}:{}",
+            self.id,
+            repo,
+            tag
+        );
+        let span = trace::span!(
+            parent: self.span,
+            "testcontainers.commit",
+            container.id = %self.id,
+            image = %reference
+        );
+        span.run(self.client.commit(&self.id, repo, tag))
+            .await
+            .map_err(|err| self.error(Operation::Commit, err))?;
+        // the image only exists locally
+        Ok(DockerImage::new(repo)
+            .with_tag(tag)
+            .with_pull_policy(PullPolicy::Missing))
+    }
+
+    /// Waits until the container exits
+    pub async fn wait(&self) -> Result<ExitStatus, Error> {
+        let span =
+            trace::span!(parent: self.span, "testcontainers.wait_exit", container.id = %self.id);
+        span.run(self.client.wait(&self.id))
+            .await
+            .map_err(|err| self.error(Operation::Wait, err))
+    }
+
+    /// Runs the pre-remove hooks and removes the container
+    ///
+    /// If a hook or the removal fails, the container is left to be removed when dropped.
+    pub async fn rm(self) -> Result<(), Error> {
+        let span =
+            trace::span!(parent: self.span, "testcontainers.remove", container.id = %self.id);
+        span.run(async {
+            self.run_hooks(ContainerHook::PreRemove).await?;
+            log::debug!("removing docker container {}", self.id);
+            self.client.rm(&self.id).await
+        })
+        .await
+        .map_err(|err| self.error(Operation::Remove, err))?;
+        self.removed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Returns low-level information about the container
+    pub async fn inspect(&self) -> Result<ContainerInspectResponse, Error> {
+        self.client
+            .inspect(&self.id)
+            .await
+            .map_err(|err| self.error(Operation::Inspect, err))
+    }
+
+    /// Executes a command inside the running container and collects its output
+    ///
+    /// Commands that exit with a non-zero code succeed, see [`ExecResult::into_result`].
+    pub async fn exec<I>(&self, cmd: I) -> Result<ExecResult, Error>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let cmd: Vec<String> = cmd.into_iter().map(Into::into).collect();
+        log::debug!("executing {:?} in docker container {}", cmd, self.id);
+        let span = trace::span!(
+            parent: self.span,
+            "testcontainers.exec",
+            container.id = %self.id,
+            cmd = ?cmd
+        );
+        span.run(self.client.exec(&self.id, cmd))
+            .await
+            .map_err(|err| self.error(Operation::Exec, err))
+    }
+
+    /// Follows the stdout logs of the container
+    pub fn stdout_logs(&self) -> LogStream<'_> {
+        self.client.stdout_logs(&self.id)
+    }
+
+    /// Follows the stderr logs of the container
+    pub fn stderr_logs(&self) -> LogStream<'_> {
+        self.client.stderr_logs(&self.id)
+    }
+
+    /// Collects the diagnostics of the container, including its last `lines` log lines.
+    pub async fn report(&self, lines: usize) -> Report {
+        Report::collect(&self.client, &self.id, &self.image.descriptor(), lines).await
+    }
+
+    /// Forwards stdout and stderr of the container as `tracing` events under the container span.
+    #[cfg(feature = "tracing")]
+    pub fn trace_logs(&self) -> LogFollower {
+        self.follow_logs(consumer::Events::new(self.span.inner().clone()))
+    }
+
+    /// Retrieves the logs selected by `options` as tagged frames.
+    pub fn logs(&self, options: LogOptions) -> FrameStream<'_> {
+        self.client.logs(&self.id, options)
+    }
+
+    /// Returns all stdout and stderr frames logged so far, in the order they were logged.
+    pub async fn logs_snapshot(&self) -> Result<Vec<LogFrame>, io::Error> {
+        self.logs(LogOptions::default()).try_collect().await
+    }
+
+    /// Forwards stdout and stderr of the container to `consumer` from a background task.
+    ///
+    /// See [`crate::logs::consumer`] for the built-in consumers.
+    pub fn follow_logs<L: LogConsumer>(&self, consumer: L) -> LogFollower {
+        consumer::follow(self.client.clone(), self.id.clone(), consumer)
+    }
+
+    /// Streams resource usage samples of the running container, about one per second.
+    pub fn stats(&self) -> StatsStream<'_, C::Error> {
+        self.client.stats(&self.id)
+    }
+
+    /// Returns the current resource usage of the container.
+    pub async fn stats_snapshot(&self) -> Result<Sample, Error> {
+        self.client
+            .stats_snapshot(&self.id)
+            .await
+            .map_err(|err| self.error(Operation::Stats, err))
+    }
+
+    /// Records the peak resource usage of the container from a background task.
+    pub fn track_peak_usage(&self) -> PeakUsage {
+        stats::track(self.client.clone(), self.id.clone())
     }
 
     /// Gets the host IP address of the container
-    pub async fn host(&self) -> Result<net::IpAddr, C::Error> {
-        self.client.host(&self.id).await
+    pub async fn host(&self) -> Result<net::IpAddr, Error> {
+        self.client
+            .host(&self.id)
+            .await
+            .map_err(|err| self.error(Operation::Host, err))
+    }
+
+    /// Gets all port mappings of the container
+    pub async fn ports(&self) -> Result<Ports, Error> {
+        self.client
+            .ports(&self.id)
+            .await
+            .map_err(|err| self.error(Operation::Ports, err))
     }
 
     /// Get the mapped host IPv4 port for the given internal port
-    pub async fn mapped_port_ipv4(&self, internal_port: u16) -> Result<Option<u16>, C::Error> {
-        let ports = self.client.ports(&self.id).await?;
+    pub async fn mapped_port_ipv4(&self, internal_port: u16) -> Result<Option<u16>, Error> {
+        let ports = self.ports().await?;
         Ok(ports.mapped_port_ipv4(internal_port))
     }
 
     /// Get the mapped host IPv6 port for the given internal port
-    pub async fn mapped_port_ipv6(&self, internal_port: u16) -> Result<Option<u16>, C::Error> {
-        let ports = self.client.ports(&self.id).await?;
+    pub async fn mapped_port_ipv6(&self, internal_port: u16) -> Result<Option<u16>, Error> {
+        let ports = self.ports().await?;
         Ok(ports.mapped_port_ipv6(internal_port))
     }
 
+    /// Starts a fault-injecting [`Proxy`](proxy::Proxy) to the mapped port of `internal_port`.
+    pub async fn proxy(&self, internal_port: u16) -> Result<proxy::Proxy, proxy::Error> {
+        let host = self.host().await.map_err(proxy::Error::Client)?;
+        let port = self
+            .mapped_port_ipv4(internal_port)
+            .await
+            .map_err(proxy::Error::Client)?
+            .ok_or(proxy::Error::PortNotMapped(internal_port))?;
+        proxy::Proxy::start(net::SocketAddr::new(host, port)).map_err(proxy::Error::Start)
+    }
+
     /// Drops and removes the container
-    async fn drop_async(&self) {
-        if let Err(err) = self.client.rm(&self.id).await {
-            log::error!("failed to remove docker container {}", self.id);
+    pub(crate) async fn drop_async(&self) {
+        if self.removed.load(Ordering::SeqCst) {
+            return;
+        }
+        if self.keep {
+            log::debug!("keeping reusable docker container {}", self.id);
+            return;
         }
+        let span =
+            trace::span!(parent: self.span, "testcontainers.remove", container.id = %self.id);
+        span.run(async {
+            if let Err(err) = self.run_hooks(ContainerHook::PreRemove).await {
+                log::error!("{} of docker container {}: {}", err, self.id, err.source);
+            }
+            match self.client.rm(&self.id).await {
+                Ok(()) => self.removed.store(true, Ordering::SeqCst),
+                Err(err) => log::error!("failed to remove docker container {}: {}", self.id, err),
+            }
+        })
+        .await;
         // match self.command {
         //     env::Command::Remove => self.docker_client.rm(&self.id).await,
         //     env::Command::Keep => {}
@@ -174,6 +548,6 @@ where
     // I: Image,
 {
     fn drop(&mut self) {
-        block_on(self.drop_async())
+        rt::block_on(self.drop_async())
     }
 }
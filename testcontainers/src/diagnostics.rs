@@ -0,0 +1,444 @@
+//! Diagnostics of live containers for failing tests.
+//!
+//! Every container registers itself here while it is alive.
+//! When a thread panics, e.g. because an assertion failed, a panic hook
+//! reports the last log lines, state, exit code, health log and port mappings
+//! of the live containers created on that thread, which under libtest are the
+//! containers of the failing test. [`dump`] reports all live containers on demand.
+//!
+//! Reports are printed to stderr, so libtest shows them for failing tests.
+//! If [`DIR_ENV`] is set, e.g. to `target/testcontainers-reports`, they are
+//! written to files in that directory instead, for upload as CI artifacts.
+
+use super::client::DockerClient;
+use super::logs::LogOptions;
+use super::rt;
+use futures::future::{self, BoxFuture, Either};
+use futures::{FutureExt, TryStreamExt};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+use std::{env, fs, io};
+
+/// Directory to write reports to instead of printing them.
+pub const DIR_ENV: &str = "TESTCONTAINERS_REPORT_DIR";
+
+/// Number of log lines per report, 100 by default.
+pub const LINES_ENV: &str = "TESTCONTAINERS_REPORT_LINES";
+
+/// Set to `false` or `0` to disable reports on panic.
+pub const ON_PANIC_ENV: &str = "TESTCONTAINERS_REPORT_ON_PANIC";
+
+const DEFAULT_LINES: usize = 100;
+
+/// Upper bound for collecting reports from the panic hook, e.g. if the daemon hangs.
+const PANIC_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Collect = Arc<dyn Fn(usize) -> BoxFuture<'static, Report> + Send + Sync>;
+
+/// A live container and the thread that created it.
+struct Live {
+    thread: ThreadId,
+    collect: Collect,
+}
+
+lazy_static::lazy_static! {
+    static ref LIVE: Mutex<BTreeMap<u64, Live>> = Mutex::new(BTreeMap::new());
+}
+
+thread_local! {
+    /// Set while the panic hook collects reports, so that a panic during
+    /// collection does not report again.
+    static REPORTING: Cell<bool> = const { Cell::new(false) };
+}
+
+static NEXT_KEY: AtomicU64 = AtomicU64::new(0);
+static PANIC_HOOK: Once = Once::new();
+
+/// Keeps a container in the registry of live containers until dropped.
+pub(crate) struct Registration(u64);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        live().remove(&self.0);
+    }
+}
+
+fn live() -> std::sync::MutexGuard<'static, BTreeMap<u64, Live>> {
+    LIVE.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Registers a live container and installs the panic hook on first use.
+///
+/// Reports are collected with a [detached](DockerClient::detached) client, since
+/// the connections of `client` may belong to the runtime of a panicking thread.
+pub(crate) fn register<C: DockerClient>(client: C, id: String, image: String) -> Registration {
+    PANIC_HOOK.call_once(install_panic_hook);
+    let collect: Collect = Arc::new(move |lines| {
+        let client = client.detached().unwrap_or_else(|err| {
+            log::warn!("failed to connect for the report of {}: {}", id, err);
+            client.clone()
+        });
+        let id = id.clone();
+        let image = image.clone();
+        async move { Report::collect(&client, &id, &image, lines).await }.boxed()
+    });
+    let key = NEXT_KEY.fetch_add(1, Ordering::Relaxed);
+    let thread = thread::current().id();
+    live().insert(key, Live { thread, collect });
+    Registration(key)
+}
+
+/// Returns the live containers created on `thread`.
+fn created_on(thread: ThreadId) -> Vec<Collect> {
+    live()
+        .values()
+        .filter(|live| live.thread == thread)
+        .map(|live| live.collect.clone())
+        .collect()
+}
+
+/// Returns whether reports are produced when a thread panics.
+pub(crate) fn on_panic() -> bool {
+    !matches!(
+        env::var(ON_PANIC_ENV).as_deref(),
+        Ok("0") | Ok("false") | Ok("no")
+    )
+}
+
+/// Returns the number of log lines per report.
+pub(crate) fn lines() -> usize {
+    env::var(LINES_ENV)
+        .ok()
+        .and_then(|lines| lines.parse().ok())
+        .unwrap_or(DEFAULT_LINES)
+}
+
+fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        if !on_panic() || REPORTING.with(Cell::get) {
+            return;
+        }
+        let collects = created_on(thread::current().id());
+        if collects.is_empty() {
+            return;
+        }
+        let count = collects.len();
+        REPORTING.with(|reporting| reporting.set(true));
+        // the panicking thread may be the only one driving its runtime, so
+        // the reports are collected on a fresh runtime with fresh connections
+        let lines = lines();
+        let reports = std::thread::spawn(move || {
+            let collect = async move {
+                let reports = future::join_all(collects.iter().map(|collect| collect(lines)));
+                let timeout = rt::sleep(PANIC_TIMEOUT);
+                match future::select(reports.boxed(), Box::pin(timeout)).await {
+                    Either::Left((reports, _)) => Some(reports),
+                    Either::Right(_) => None,
+                }
+            };
+            #[cfg(feature = "tokio")]
+            {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .ok()?
+                    .block_on(collect)
+            }
+            #[cfg(not(feature = "tokio"))]
+            {
+                futures::executor::block_on(collect)
+            }
+        })
+        .join();
+        match reports {
+            Ok(Some(reports)) => {
+                if let Err(err) = emit(&reports) {
+                    eprintln!("failed to write container reports: {}", err);
+                }
+            }
+            _ => eprintln!("timed out collecting reports of {} live containers", count),
+        }
+        REPORTING.with(|reporting| reporting.set(false));
+    }));
+}
+
+/// The diagnostics of a container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub id: String,
+    pub image: String,
+    /// The rendered report.
+    pub text: String,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Report {
+    /// Collects the diagnostics of a container, including its last `lines` log lines.
+    pub async fn collect<C: DockerClient>(client: &C, id: &str, image: &str, lines: usize) -> Self {
+        let mut text = String::new();
+        let _ = writeln!(text, "==== container {} ({}) ====", id, image);
+
+        match client.inspect(id).await {
+            Ok(inspect) => {
+                if let Some(state) = inspect.state {
+                    let status = state
+                        .status
+                        .map_or_else(|| "unknown".to_string(), |status| status.to_string());
+                    let _ = write!(text, "state: {}", status);
+                    if let Some(exit_code) = state.exit_code {
+                        let _ = write!(text, ", exit code {}", exit_code);
+                    }
+                    if state.oom_killed == Some(true) {
+                        let _ = write!(text, ", killed by the OOM killer");
+                    }
+                    text.push('\n');
+                    if let Some(error) = state.error.filter(|error| !error.is_empty()) {
+                        let _ = writeln!(text, "error: {}", error);
+                    }
+                    if let Some(health) = state.health {
+                        let status = health
+                            .status
+                            .map_or_else(|| "unknown".to_string(), |status| status.to_string());
+                        let _ = writeln!(text, "health: {}", status);
+                        for check in health.log.unwrap_or_default() {
+                            let _ = writeln!(
+                                text,
+                                "  {} exit {}: {}",
+                                check.start.unwrap_or_default(),
+                                check
+                                    .exit_code
+                                    .map_or_else(|| "?".to_string(), |code| code.to_string()),
+                                check.output.unwrap_or_default().trim_end()
+                            );
+                        }
+                    }
+                }
+                let ports = inspect
+                    .network_settings
+                    .and_then(|settings| settings.ports)
+                    .unwrap_or_default();
+                let _ = writeln!(text, "ports:");
+                for (port, bindings) in ports {
+                    for binding in bindings.unwrap_or_default() {
+                        let _ = writeln!(
+                            text,
+                            "  {} -> {}:{}",
+                            port,
+                            binding.host_ip.unwrap_or_default(),
+                            binding.host_port.unwrap_or_default()
+                        );
+                    }
+                }
+            }
+            Err(err) => {
+                let _ = writeln!(text, "failed to inspect: {}", err);
+            }
+        }
+
+        let _ = writeln!(text, "---- last {} log lines ----", lines);
+        let frames: Result<Vec<_>, _> = client
+            .logs(id, LogOptions::new().with_tail(lines))
+            .try_collect()
+            .await;
+        match frames {
+            Ok(frames) => {
+                for frame in frames {
+                    let _ = writeln!(
+                        text,
+                        "{} | {}",
+                        frame.output,
+                        frame.line().trim_end_matches('\n')
+                    );
+                }
+            }
+            Err(err) => {
+                let _ = writeln!(text, "failed to read logs: {}", err);
+            }
+        }
+
+        Self {
+            id: id.to_string(),
+            image: image.to_string(),
+            text,
+        }
+    }
+
+    /// Returns the file name of the report, prefixed with the name of the current thread,
+    /// which is the test name under libtest.
+    fn file_name(&self) -> String {
+        let thread = std::thread::current();
+        let name = format!(
+            "{}--{}-{}.txt",
+            thread.name().unwrap_or("unnamed"),
+            self.image,
+            &self.id[..self.id.len().min(12)]
+        );
+        name.chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+                _ => '_',
+            })
+            .collect()
+    }
+}
+
+/// Collects the reports of all live containers.
+pub async fn collect() -> Vec<Report> {
+    let collects: Vec<Collect> = live().values().map(|live| live.collect.clone()).collect();
+    let lines = lines();
+    future::join_all(collects.iter().map(|collect| collect(lines))).await
+}
+
+/// Writes the reports to `dir`, returning the paths of the written files.
+pub fn write_to(dir: impl AsRef<Path>, reports: &[Report]) -> io::Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    reports
+        .iter()
+        .map(|report| {
+            let path = dir.join(report.file_name());
+            fs::write(&path, &report.text)?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Prints the reports, or writes them to the directory configured with [`DIR_ENV`].
+fn emit(reports: &[Report]) -> io::Result<()> {
+    match env::var_os(DIR_ENV) {
+        Some(dir) => {
+            for path in write_to(dir, reports)? {
+                eprintln!("wrote container report to {}", path.display());
+            }
+        }
+        None => {
+            for report in reports {
+                eprint!("{}", report);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reports all live containers, like the panic hook does.
+pub async fn dump() -> io::Result<()> {
+    emit(&collect().await)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::{collect, created_on, write_to, Report, DIR_ENV, ON_PANIC_ENV};
+    use crate::client::mock::MockClient;
+    use crate::client::DockerClient;
+    use crate::DockerImage;
+    use color_eyre::eyre;
+    use pretty_assertions::assert_eq;
+
+    /// Set for the child process that runs [`panicking_test`].
+    const PANICKING_ENV: &str = "TESTCONTAINERS_TEST_PANICKING";
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reports_live_containers() -> eyre::Result<()> {
+        let client = MockClient::new().with_stderr("postgres", ["FATAL: role does not exist"]);
+        let container = client
+            .create(DockerImage::new("postgres").with_mapped_port(5432, 5432))
+            .await?;
+        container.start().await?;
+        let id = container.id().to_string();
+
+        let report = collect()
+            .await
+            .into_iter()
+            .find(|report| report.id == id)
+            .expect("live container is reported");
+        assert_eq!(report.image, "postgres:latest");
+        assert!(report.text.contains("state: running"));
+        assert!(report.text.contains("5432/tcp -> 0.0.0.0:5432"));
+        assert!(report.text.contains("stderr | FATAL: role does not exist"));
+
+        let dir = std::env::temp_dir().join(format!("testcontainers-reports-{}", id));
+        let paths = write_to(&dir, std::slice::from_ref(&report))?;
+        assert_eq!(paths.len(), 1);
+        assert_eq!(std::fs::read_to_string(&paths[0])?, report.text);
+        std::fs::remove_dir_all(&dir)?;
+
+        drop(container);
+        assert!(collect().await.iter().all(|report| report.id != id));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn panics_report_containers_of_their_thread() -> eyre::Result<()> {
+        let client = MockClient::new();
+        let container = client.create(DockerImage::new("redis")).await?;
+        let thread = std::thread::current().id();
+        assert!(!created_on(thread).is_empty());
+        let other = std::thread::spawn(|| created_on(std::thread::current().id()).len());
+        assert_eq!(other.join().unwrap(), 0);
+        drop(container);
+        Ok(())
+    }
+
+    /// Fails with a live container, when run by [`reports_containers_of_panicking_test`].
+    #[tokio::test]
+    async fn panicking_test() {
+        if std::env::var_os(PANICKING_ENV).is_none() {
+            return;
+        }
+        let client = MockClient::new().with_stderr("postgres", ["FATAL: role does not exist"]);
+        let container = client.create(DockerImage::new("postgres")).await.unwrap();
+        container.start().await.unwrap();
+        panic!("query failed");
+    }
+
+    #[test]
+    fn reports_containers_of_panicking_test() -> eyre::Result<()> {
+        let output = std::process::Command::new(std::env::current_exe()?)
+            .args([
+                "diagnostics::tests::panicking_test",
+                "--exact",
+                "--nocapture",
+            ])
+            .env(PANICKING_ENV, "1")
+            .env_remove(DIR_ENV)
+            .env_remove(ON_PANIC_ENV)
+            .output()?;
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr)?;
+        assert!(stderr.contains("query failed"), "{}", stderr);
+        assert!(stderr.contains("==== container "), "{}", stderr);
+        assert!(stderr.contains("(postgres:latest) ===="), "{}", stderr);
+        assert!(stderr.contains("state: running"), "{}", stderr);
+        assert!(
+            stderr.contains("stderr | FATAL: role does not exist"),
+            "{}",
+            stderr
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn limits_log_lines() -> eyre::Result<()> {
+        let client = MockClient::new().with_stdout("redis", ["one", "two", "three"]);
+        let container = client.create(DockerImage::new("redis")).await?;
+        container.start().await?;
+        let report = Report::collect(&client, container.id(), "redis:latest", 2).await;
+        assert!(report
+            .text
+            .contains("---- last 2 log lines ----\nstdout | two\nstdout | three\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,368 @@
+//! The error type of container operations.
+//!
+//! Every operation on a [`Container`](crate::Container) fails with an [`Error`]
+//! that carries the failed [`Operation`], the container and image it concerned,
+//! and the underlying cause, e.g. an error of the [`DockerClient`](crate::client::DockerClient).
+//! Failures are classified by their [`ErrorKind`], so that callers can retry
+//! transient failures or tolerate missing containers without matching the
+//! errors of a specific client.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), testcontainers_rs::Error> {
+//! use testcontainers_rs::client::{bollard::Client, DockerClient};
+//! use testcontainers_rs::DockerImage;
+//!
+//! let client = Client::new().await.unwrap();
+//! let container = client.create(DockerImage::new("redis")).await?;
+//! loop {
+//!     match container.start().await {
+//!         Err(err) if err.is_retryable() => continue,
+//!         result => break result?,
+//!     }
+//! }
+//! if let Err(err) = container.stop().await {
+//!     if !err.is_not_found() {
+//!         return Err(err);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use super::exec::ExecError;
+use super::hooks::{BoxError, HookError};
+use super::logs::WaitError;
+use super::run;
+use super::wait;
+use std::fmt;
+
+/// An operation on a container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Create,
+    Pull,
+    Start,
+    Stop,
+    Pause,
+    Unpause,
+    Restart,
+    Kill,
+    Wait,
+    Ready,
+    Commit,
+    Remove,
+    Inspect,
+    Exec,
+    Logs,
+    Stats,
+    Host,
+    Ports,
+    /// Forwarding host access ports to the test machine, see [`crate::host`].
+    Forward,
+    /// Creating, removing, connecting to or disconnecting from a network.
+    Network,
+    /// Creating, populating or removing a volume.
+    Volume,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Create => "create",
+            Self::Pull => "pull",
+            Self::Start => "start",
+            Self::Stop => "stop",
+            Self::Pause => "pause",
+            Self::Unpause => "unpause",
+            Self::Restart => "restart",
+            Self::Kill => "kill",
+            Self::Wait => "wait",
+            Self::Ready => "ready check",
+            Self::Commit => "commit",
+            Self::Remove => "remove",
+            Self::Inspect => "inspect",
+            Self::Exec => "exec",
+            Self::Logs => "logs",
+            Self::Stats => "stats",
+            Self::Host => "host lookup",
+            Self::Ports => "port lookup",
+            Self::Forward => "port forwarding",
+            Self::Network => "network",
+            Self::Volume => "volume",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The class of a failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "record", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "record", serde(rename_all = "snake_case"))]
+pub enum ErrorKind {
+    /// The container, image, network or volume does not exist.
+    NotFound,
+    /// The operation conflicts with the state of the container, e.g. it is not running.
+    Conflict,
+    /// The docker daemon could not be reached or is temporarily unavailable.
+    Unavailable,
+    /// The operation did not complete in time.
+    Timeout,
+    #[default]
+    Other,
+}
+
+/// Classifies the errors of a [`DockerClient`](crate::client::DockerClient).
+pub trait Classify {
+    fn kind(&self) -> ErrorKind;
+
+    /// Returns the operation that actually failed, if it differs from the
+    /// requested one, e.g. pulling the image when creating a container.
+    fn operation(&self) -> Option<Operation> {
+        None
+    }
+}
+
+impl Classify for HookError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl Classify for WaitError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::EOF(_) => ErrorKind::Conflict,
+            Self::Io(_) => ErrorKind::Unavailable,
+        }
+    }
+}
+
+impl Classify for run::Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Container(err) => err.kind(),
+            Self::Logs(_) => ErrorKind::Unavailable,
+            Self::Timeout(_) => ErrorKind::Timeout,
+        }
+    }
+
+    fn operation(&self) -> Option<Operation> {
+        match self {
+            Self::Container(err) => Some(err.operation()),
+            Self::Logs(_) => Some(Operation::Logs),
+            Self::Timeout(_) => None,
+        }
+    }
+}
+
+impl<E> Classify for wait::Error<E>
+where
+    E: std::error::Error + Classify + 'static,
+{
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Message(err) => err.kind(),
+            Self::Inspect(err) => err.kind(),
+            Self::HealthcheckNotConfigured | Self::Unhealthy | Self::Hook(_) => ErrorKind::Other,
+        }
+    }
+}
+
+/// A failed operation on a container.
+#[derive(Debug)]
+pub struct Error {
+    operation: Operation,
+    kind: ErrorKind,
+    container: Option<String>,
+    name: Option<String>,
+    image: Option<String>,
+    source: BoxError,
+}
+
+impl Error {
+    /// Creates an error of `operation` caused by `source`, classified by the source.
+    pub fn new<E>(operation: Operation, source: E) -> Self
+    where
+        E: std::error::Error + Classify + Send + Sync + 'static,
+    {
+        Self {
+            operation: source.operation().unwrap_or(operation),
+            kind: source.kind(),
+            container: None,
+            name: None,
+            image: None,
+            source: Box::new(source),
+        }
+    }
+
+    /// Creates an error of `operation` with an unclassified cause.
+    pub fn other(operation: Operation, source: impl Into<BoxError>) -> Self {
+        Self {
+            operation,
+            kind: ErrorKind::Other,
+            container: None,
+            name: None,
+            image: None,
+            source: source.into(),
+        }
+    }
+
+    pub fn with_container(self, id: impl Into<String>) -> Self {
+        Self {
+            container: Some(id.into()),
+            ..self
+        }
+    }
+
+    pub fn with_name(self, name: Option<String>) -> Self {
+        Self { name, ..self }
+    }
+
+    pub fn with_image(self, image: impl Into<String>) -> Self {
+        Self {
+            image: Some(image.into()),
+            ..self
+        }
+    }
+
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns the id of the container, unless it was not created.
+    pub fn container(&self) -> Option<&str> {
+        self.container.as_deref()
+    }
+
+    /// Returns the name of the container, if the image configured one.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the descriptor of the image, e.g. `postgres:13`.
+    pub fn image(&self) -> Option<&str> {
+        self.image.as_deref()
+    }
+
+    /// Returns the underlying cause as `T`, e.g. the error of the client.
+    pub fn downcast_ref<T: std::error::Error + 'static>(&self) -> Option<&T> {
+        self.source.downcast_ref()
+    }
+
+    /// Returns whether retrying the operation may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.kind, ErrorKind::Unavailable | ErrorKind::Timeout)
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.kind == ErrorKind::NotFound
+    }
+
+    pub fn is_conflict(&self) -> bool {
+        self.kind == ErrorKind::Conflict
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed", self.operation)?;
+        match (&self.name, &self.container) {
+            (Some(name), _) => write!(f, " for container {}", name)?,
+            (None, Some(id)) => write!(f, " for container {:.12}", id)?,
+            (None, None) => {}
+        }
+        if let Some(image) = &self.image {
+            write!(f, " ({})", image)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+impl<E> From<wait::Error<E>> for Error
+where
+    E: std::error::Error + Classify + Send + Sync + 'static,
+{
+    fn from(err: wait::Error<E>) -> Self {
+        Self::new(Operation::Ready, err)
+    }
+}
+
+impl From<run::Error> for Error {
+    fn from(err: run::Error) -> Self {
+        match err {
+            run::Error::Container(err) => err,
+            err => Self::new(Operation::Wait, err),
+        }
+    }
+}
+
+impl From<ExecError> for Error {
+    fn from(err: ExecError) -> Self {
+        Self::other(Operation::Exec, err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ErrorKind, Operation};
+    use crate::exec::ExecResult;
+    use crate::logs::WaitError;
+    use crate::{run, wait};
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    #[test]
+    fn describes_operation_and_container() {
+        let err = Error::new(Operation::Ready, wait::Error::<WaitError>::Unhealthy)
+            .with_container("4f2a8c0e9d1b7a6f5e3c2b1a")
+            .with_image("postgres:13");
+        assert_eq!(
+            err.to_string(),
+            "ready check failed for container 4f2a8c0e9d1b (postgres:13)"
+        );
+        let err = err.with_name(Some("db".to_string()));
+        assert_eq!(
+            err.to_string(),
+            "ready check failed for container db (postgres:13)"
+        );
+        assert_eq!(err.container(), Some("4f2a8c0e9d1b7a6f5e3c2b1a"));
+        assert!(err.downcast_ref::<wait::Error<WaitError>>().is_some());
+    }
+
+    #[test]
+    fn classifies_causes() {
+        let err: Error =
+            wait::Error::<WaitError>::Message(WaitError::Io(std::io::ErrorKind::BrokenPipe.into()))
+                .into();
+        assert_eq!(err.operation(), Operation::Ready);
+        assert!(err.is_retryable());
+
+        let result = ExecResult {
+            exit_code: Some(1),
+            stderr: "no such table\n".to_string(),
+            ..Default::default()
+        };
+        let err: Error = result.into_result().unwrap_err().into();
+        assert_eq!(err.kind(), ErrorKind::Other);
+        assert!(!err.is_retryable() && !err.is_not_found() && !err.is_conflict());
+        assert_eq!(
+            std::error::Error::source(&err).unwrap().to_string(),
+            "command exited with Some(1): no such table"
+        );
+
+        let err: Error = run::Error::Timeout(Duration::from_secs(60)).into();
+        assert_eq!(err.operation(), Operation::Wait);
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+        assert!(err.downcast_ref::<run::Error>().is_some());
+    }
+}
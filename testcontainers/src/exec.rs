@@ -0,0 +1,32 @@
+/// The output of a command executed inside a running container.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "record", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExecResult {
+    /// The exit code of the command, if it has finished.
+    pub exit_code: Option<i64>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl ExecResult {
+    /// Returns whether the command exited with code zero.
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// Returns the result if the command exited with code zero, and an [`ExecError`] otherwise.
+    pub fn into_result(self) -> Result<Self, ExecError> {
+        if self.success() {
+            Ok(self)
+        } else {
+            Err(ExecError { result: self })
+        }
+    }
+}
+
+/// A command executed in a container did not exit with code zero.
+#[derive(thiserror::Error, Debug)]
+#[error("command exited with {:?}: {}", .result.exit_code, .result.stderr.trim())]
+pub struct ExecError {
+    pub result: ExecResult,
+}
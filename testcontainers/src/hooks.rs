@@ -0,0 +1,410 @@
+//! Hooks that run at fixed points of the container lifecycle.
+//!
+//! Hooks are registered on a [`DockerImage`] and run for every container
+//! created from it:
+//!
+//! - [`Hook::PreCreate`] mutates the image before the container is created
+//! - [`Hook::PostCreate`] runs after the container was created
+//! - [`Hook::PostStart`] runs after the container was started
+//! - [`Hook::PostReady`] runs once all ready conditions are met
+//! - [`Hook::PreStop`] runs before the container is stopped
+//! - [`Hook::PreRemove`] runs before the container is removed
+//!
+//! All but the pre-create hook are [`ContainerHook`]s, async closures receiving
+//! the [`Container`]. They take a container of a specific client, so an image
+//! with container hooks is a `DockerImage<C>` that only clients of type `C`
+//! create containers from.
+//! A failing hook aborts the operation it belongs to with a [`HookError`].
+//!
+//! Creating a container with another client fails to compile:
+//!
+//! ```compile_fail
+//! # async fn run(client: testcontainers_rs::client::mock::MockClient) {
+//! use futures::FutureExt;
+//! use testcontainers_rs::client::{bollard::Client, DockerClient};
+//! use testcontainers_rs::hooks::ContainerHook;
+//! use testcontainers_rs::{Container, DockerImage};
+//!
+//! let image = DockerImage::new("redis")
+//!     .with_hook(ContainerHook::PostStart, |_: &Container<Client>| {
+//!         async { Ok(()) }.boxed()
+//!     });
+//! let container = client.create(image).await;
+//! # }
+//! ```
+
+use super::client::DockerClient;
+use super::container::Container;
+use super::image::DockerImage;
+use futures::future::BoxFuture;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::sync::Arc;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The result of a hook.
+pub type HookResult = Result<(), BoxError>;
+
+/// A point of the container lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Hook {
+    PreCreate,
+    PostCreate,
+    PostStart,
+    PostReady,
+    PreStop,
+    PreRemove,
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::PreCreate => "pre-create",
+            Self::PostCreate => "post-create",
+            Self::PostStart => "post-start",
+            Self::PostReady => "post-ready",
+            Self::PreStop => "pre-stop",
+            Self::PreRemove => "pre-remove",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A point of the container lifecycle whose hooks receive the container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ContainerHook {
+    PostCreate,
+    PostStart,
+    PostReady,
+    PreStop,
+    PreRemove,
+}
+
+impl From<ContainerHook> for Hook {
+    fn from(hook: ContainerHook) -> Self {
+        match hook {
+            ContainerHook::PostCreate => Self::PostCreate,
+            ContainerHook::PostStart => Self::PostStart,
+            ContainerHook::PostReady => Self::PostReady,
+            ContainerHook::PreStop => Self::PreStop,
+            ContainerHook::PreRemove => Self::PreRemove,
+        }
+    }
+}
+
+impl fmt::Display for ContainerHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Hook::from(*self).fmt(f)
+    }
+}
+
+/// A hook failed.
+#[derive(thiserror::Error, Debug)]
+#[error("{hook} hook failed")]
+pub struct HookError {
+    pub hook: Hook,
+    #[source]
+    pub source: BoxError,
+}
+
+/// The client of the containers created from an image.
+///
+/// Images without container hooks are created by any client, marked by [`AnyClient`].
+/// Registering a container hook binds the image to the client of the hook.
+pub trait ImageClient: 'static {
+    /// A registered container hook.
+    type Hook: Clone + Send + Sync;
+}
+
+/// Marks images without container hooks, which any client creates containers from.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnyClient;
+
+impl ImageClient for AnyClient {
+    type Hook = Infallible;
+}
+
+impl<C: DockerClient> ImageClient for C {
+    type Hook = Arc<dyn for<'a> Fn(&'a Container<C>) -> BoxFuture<'a, HookResult> + Send + Sync>;
+}
+
+/// A client of images that container hooks of the client `D` can be added to.
+///
+/// Adding a hook binds images of [`AnyClient`] to `D`, while images of another
+/// client do not accept the hook.
+pub trait BindTo<D: DockerClient>: ImageClient {
+    fn bind(hooks: Hooks<Self>) -> Hooks<D>
+    where
+        Self: Sized;
+}
+
+impl<D: DockerClient> BindTo<D> for AnyClient {
+    fn bind(hooks: Hooks<Self>) -> Hooks<D> {
+        hooks.with_container(ContainerHooks::default()).0
+    }
+}
+
+impl<D: DockerClient> BindTo<D> for D {
+    fn bind(hooks: Hooks<Self>) -> Hooks<D> {
+        hooks
+    }
+}
+
+type PreCreateHook = Arc<dyn Fn(&mut DockerImage) -> HookResult + Send + Sync>;
+
+/// The container hooks of the client `C`, by lifecycle point.
+pub(crate) struct ContainerHooks<C: ImageClient>(BTreeMap<ContainerHook, Vec<C::Hook>>);
+
+impl<C: ImageClient> Default for ContainerHooks<C> {
+    fn default() -> Self {
+        Self(BTreeMap::new())
+    }
+}
+
+impl<C: ImageClient> Clone for ContainerHooks<C> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+/// The hooks registered on an image.
+pub struct Hooks<C: ImageClient = AnyClient> {
+    pre_create: Vec<PreCreateHook>,
+    container: ContainerHooks<C>,
+}
+
+impl<C: ImageClient> Default for Hooks<C> {
+    fn default() -> Self {
+        Self {
+            pre_create: Vec::new(),
+            container: ContainerHooks::default(),
+        }
+    }
+}
+
+impl<C: ImageClient> Clone for Hooks<C> {
+    fn clone(&self) -> Self {
+        Self {
+            pre_create: self.pre_create.clone(),
+            container: self.container.clone(),
+        }
+    }
+}
+
+impl<C: ImageClient> fmt::Debug for Hooks<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts: BTreeMap<Hook, usize> =
+            std::iter::once((Hook::PreCreate, self.pre_create.len()))
+                .chain(
+                    self.container
+                        .0
+                        .iter()
+                        .map(|(hook, hooks)| (Hook::from(*hook), hooks.len())),
+                )
+                .filter(|(_, count)| *count > 0)
+                .collect();
+        f.debug_tuple("Hooks").field(&counts).finish()
+    }
+}
+
+impl<C: ImageClient> Hooks<C> {
+    pub(crate) fn add_pre_create<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut DockerImage) -> HookResult + Send + Sync + 'static,
+    {
+        self.pre_create.push(Arc::new(hook));
+    }
+
+    /// Replaces the container hooks by those of the client `D` and returns the previous ones.
+    pub(crate) fn with_container<D: ImageClient>(
+        self,
+        container: ContainerHooks<D>,
+    ) -> (Hooks<D>, ContainerHooks<C>) {
+        let hooks = Hooks {
+            pre_create: self.pre_create,
+            container,
+        };
+        (hooks, self.container)
+    }
+}
+
+impl<C: DockerClient> Hooks<C> {
+    pub(crate) fn add<F>(&mut self, hook: ContainerHook, f: F)
+    where
+        F: for<'a> Fn(&'a Container<C>) -> BoxFuture<'a, HookResult> + Send + Sync + 'static,
+    {
+        self.container.0.entry(hook).or_default().push(Arc::new(f));
+    }
+
+    /// Runs the hooks registered for `hook` on the container, in order of registration.
+    pub(crate) async fn run(
+        &self,
+        hook: ContainerHook,
+        container: &Container<C>,
+    ) -> Result<(), HookError> {
+        for f in self.container.0.get(&hook).into_iter().flatten() {
+            log::debug!("running {} hook of container {}", hook, container.id());
+            f(container).await.map_err(|source| HookError {
+                hook: hook.into(),
+                source,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl Hooks {
+    /// Runs the pre-create hooks on the image they are registered on.
+    pub(crate) fn run_pre_create(image: &mut DockerImage) -> Result<(), HookError> {
+        for hook in image.hooks.pre_create.clone() {
+            hook(image).map_err(|source| HookError {
+                hook: Hook::PreCreate,
+                source,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::{ContainerHook, Hook};
+    use crate::client::mock::{self, MockClient};
+    use crate::client::DockerClient;
+    use crate::image::IntoImage;
+    use crate::{Container, DockerImage};
+    use color_eyre::eyre;
+    use futures::FutureExt;
+    use pretty_assertions::assert_eq;
+    use std::sync::{Arc, Mutex};
+
+    fn recorded(
+        image: DockerImage,
+        calls: &Arc<Mutex<Vec<ContainerHook>>>,
+    ) -> DockerImage<MockClient> {
+        let hooks = [
+            ContainerHook::PostCreate,
+            ContainerHook::PostStart,
+            ContainerHook::PostReady,
+            ContainerHook::PreStop,
+            ContainerHook::PreRemove,
+        ];
+        let image = IntoImage::<MockClient>::into_image(image);
+        hooks.into_iter().fold(image, |image, hook| {
+            let calls = calls.clone();
+            image.with_hook(hook, move |_| {
+                calls.lock().unwrap().push(hook);
+                futures::future::ready(Ok(())).boxed()
+            })
+        })
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn runs_hooks_in_lifecycle_order() -> eyre::Result<()> {
+        let client = MockClient::new();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let image = recorded(DockerImage::new("redis"), &calls);
+
+        let container = client.create(image).await?;
+        container.start().await?;
+        container.wait_until_ready().await?;
+        container.stop().await?;
+        container.rm().await?;
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                ContainerHook::PostCreate,
+                ContainerHook::PostStart,
+                ContainerHook::PostReady,
+                ContainerHook::PreStop,
+                ContainerHook::PreRemove,
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn runs_pre_remove_hooks_on_drop() -> eyre::Result<()> {
+        let client = MockClient::new();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let container = client
+            .create(recorded(DockerImage::new("redis"), &calls))
+            .await?;
+        drop(container);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![ContainerHook::PostCreate, ContainerHook::PreRemove]
+        );
+        assert!(client.container_ids().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn runs_pre_remove_hooks_on_drop_in_current_thread_runtime() -> eyre::Result<()> {
+        let client = MockClient::new();
+        let removed = Arc::new(Mutex::new(false));
+        let image = DockerImage::new("redis").with_hook(ContainerHook::PreRemove, {
+            let removed = removed.clone();
+            move |_: &Container<MockClient>| {
+                let removed = removed.clone();
+                async move {
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                    *removed.lock().unwrap() = true;
+                    Ok(())
+                }
+                .boxed()
+            }
+        });
+        let container = client.create(image).await?;
+        drop(container);
+        assert!(*removed.lock().unwrap());
+        assert!(client.container_ids().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pre_create_hook_changes_image() -> eyre::Result<()> {
+        let client = MockClient::new();
+        let image = DockerImage::new("redis").with_pre_create(|image| {
+            image
+                .labels
+                .insert("suite".to_string(), "hooks".to_string());
+            Ok(())
+        });
+        let container = client.create(image).await?;
+        assert_eq!(
+            container.image().labels.get("suite").map(String::as_str),
+            Some("hooks")
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn hook_failure_fails_start() -> eyre::Result<()> {
+        let client = MockClient::new();
+        let image = DockerImage::new("redis").with_hook(
+            ContainerHook::PostStart,
+            |container: &Container<MockClient>| {
+                async move {
+                    let result = container.exec(["redis-cli", "flushall"]).await?;
+                    assert!(result.success());
+                    Err("seeding failed".into())
+                }
+                .boxed()
+            },
+        );
+        let container = client.create(image).await?;
+        let err = container.start().await.unwrap_err();
+        match err.downcast_ref::<mock::Error>() {
+            Some(mock::Error::Hook(err)) => {
+                assert_eq!(err.hook, Hook::PostStart);
+                assert_eq!(err.source.to_string(), "seeding failed");
+            }
+            other => panic!("expected post-start hook failure, got {:?}", other),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+//! Access from containers to services running on the machine that runs the tests.
+//!
+//! Images created with [`DockerImage::with_host_access`] resolve [`HOST_ALIAS`]
+//! to the test machine.
+//! With a local daemon, the alias maps to the `host-gateway` of docker.
+//! With a remote daemon, e.g. docker-in-docker in CI, it maps to the address
+//! of the test machine on its route to the daemon.
+//!
+//! Services in tests usually listen on `127.0.0.1` only, so every given port is
+//! additionally forwarded in-process from the address that containers reach
+//! the host on to `127.0.0.1`.
+//! The forwarders stop when the container is dropped.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use testcontainers_rs::client::{bollard::Client, DockerClient};
+//! use testcontainers_rs::host::HOST_ALIAS;
+//! use testcontainers_rs::DockerImage;
+//!
+//! let server = std::net::TcpListener::bind("127.0.0.1:0")?;
+//! let port = server.local_addr()?.port();
+//! let client = Client::new().await?;
+//! let container = client
+//!     .create(
+//!         DockerImage::new("curlimages/curl")
+//!             .with_host_access([port])
+//!             .with_cmd([format!("http://{}:{}/callback", HOST_ALIAS, port)]),
+//!     )
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use super::client::DockerClient;
+use super::error::{Error, Operation};
+use super::image::DockerImage;
+use std::io;
+use std::net::{self, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{fmt, thread};
+
+/// The hostname that containers with host access reach the test machine on.
+pub const HOST_ALIAS: &str = "host.testcontainers.internal";
+
+/// How containers reach the machine running the tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "record", derive(serde::Serialize, serde::Deserialize))]
+pub struct HostRoute {
+    /// Target of the extra host entry, `host-gateway` or an IP address.
+    pub gateway: String,
+    /// Local address that containers reach the host on, if forwarders can listen on it.
+    pub listen: Option<net::IpAddr>,
+}
+
+impl HostRoute {
+    /// Returns the route of a daemon on the same machine.
+    pub fn local(gateway: Option<net::IpAddr>) -> Self {
+        Self {
+            gateway: "host-gateway".to_string(),
+            listen: gateway,
+        }
+    }
+
+    /// Returns the route of a remote daemon that reaches the host on `addr`.
+    pub fn remote(addr: net::IpAddr) -> Self {
+        Self {
+            gateway: addr.to_string(),
+            listen: Some(addr),
+        }
+    }
+}
+
+/// Forwards connections from a listening address to a target address.
+pub struct Forwarder {
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl fmt::Debug for Forwarder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Forwarder")
+            .field("addr", &self.addr)
+            .finish()
+    }
+}
+
+impl Forwarder {
+    /// Starts forwarding connections to `listen` to `target` from a background thread.
+    pub fn start(listen: SocketAddr, target: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(listen)?;
+        let addr = listener.local_addr()?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stopped = stopped.clone();
+            thread::Builder::new()
+                .name("testcontainers-forwarder".to_string())
+                .spawn(move || {
+                    for incoming in listener.incoming() {
+                        if stopped.load(Ordering::SeqCst) {
+                            break;
+                        }
+                        let forwarded = incoming.and_then(|incoming| forward(incoming, target));
+                        if let Err(err) = forwarded {
+                            log::warn!("failed to forward connection to {}: {}", target, err);
+                        }
+                    }
+                })?
+        };
+        log::debug!("forwarding {} to {}", addr, target);
+        Ok(Self {
+            addr,
+            stopped,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns the address the forwarder listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for Forwarder {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // wakes up the blocking accept
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Copies both directions between `incoming` and a new connection to `target`.
+fn forward(incoming: TcpStream, target: SocketAddr) -> io::Result<()> {
+    let outgoing = TcpStream::connect(target)?;
+    let pipe = |mut from: TcpStream, mut to: TcpStream| {
+        thread::spawn(move || {
+            let _ = io::copy(&mut from, &mut to);
+            let _ = to.shutdown(Shutdown::Write);
+        })
+    };
+    pipe(incoming.try_clone()?, outgoing.try_clone()?);
+    pipe(outgoing, incoming);
+    Ok(())
+}
+
+/// Maps [`HOST_ALIAS`] and forwards the host access ports of `image`.
+///
+/// Returns the running forwarders, which the container keeps until it is dropped.
+pub(crate) async fn expose<C: DockerClient>(
+    client: &C,
+    image: &mut DockerImage,
+) -> Result<Vec<Forwarder>, Error> {
+    let mut forwarders = Vec::new();
+    if image.host_access.is_empty() {
+        return Ok(forwarders);
+    }
+    let route = client
+        .host_route()
+        .await
+        .map_err(|err| Error::new(Operation::Create, err))?;
+    image
+        .extra_hosts
+        .push(format!("{}:{}", HOST_ALIAS, route.gateway));
+    let Some(listen) = route.listen else {
+        return Ok(forwarders);
+    };
+    for port in image.host_access.clone() {
+        let target = SocketAddr::from((net::Ipv4Addr::LOCALHOST, port));
+        match Forwarder::start(SocketAddr::new(listen, port), target) {
+            Ok(forwarder) => forwarders.push(forwarder),
+            // the service already listens on all interfaces, or the gateway is not
+            // an address of this machine, e.g. within the VM of Docker Desktop
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::AddrInUse | io::ErrorKind::AddrNotAvailable
+                ) =>
+            {
+                log::debug!("not forwarding port {} from {}: {}", port, listen, err);
+            }
+            Err(err) => return Err(Error::other(Operation::Forward, err)),
+        }
+    }
+    Ok(forwarders)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Forwarder;
+    use color_eyre::eyre;
+    use pretty_assertions::assert_eq;
+    use std::io::{Read, Write};
+    use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+
+    #[test]
+    fn forwards_connections() -> eyre::Result<()> {
+        let server = TcpListener::bind("127.0.0.1:0")?;
+        let target = server.local_addr()?;
+        let echo = std::thread::spawn(move || -> std::io::Result<()> {
+            let (mut stream, _) = server.accept()?;
+            let mut request = String::new();
+            stream.read_to_string(&mut request)?;
+            stream.write_all(request.to_uppercase().as_bytes())
+        });
+
+        let forwarder = Forwarder::start("127.0.0.2:0".parse::<SocketAddr>()?, target)?;
+        let mut stream = TcpStream::connect(forwarder.local_addr())?;
+        stream.write_all(b"ping")?;
+        stream.shutdown(Shutdown::Write)?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        assert_eq!(response, "PING");
+        echo.join().unwrap()?;
+
+        let addr = forwarder.local_addr();
+        drop(forwarder);
+        assert!(TcpStream::connect(addr).is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn maps_alias_and_forwards_ports() -> eyre::Result<()> {
+        use super::{HostRoute, HOST_ALIAS};
+        use crate::client::mock::MockClient;
+        use crate::client::DockerClient;
+        use crate::DockerImage;
+
+        let server = TcpListener::bind("127.0.0.1:0")?;
+        let port = server.local_addr()?.port();
+        let client = MockClient::new().with_host_route(HostRoute::remote("127.0.0.3".parse()?));
+        let container = client
+            .create(DockerImage::new("curlimages/curl").with_host_access([port]))
+            .await?;
+        assert_eq!(
+            container.image().extra_hosts,
+            vec![format!("{}:127.0.0.3", HOST_ALIAS)]
+        );
+        let _forwarded = TcpStream::connect(("127.0.0.3", port))?;
+        let (_, peer) = server.accept()?;
+        assert!(peer.ip().is_loopback());
+
+        drop(container);
+        assert!(TcpStream::connect(("127.0.0.3", port)).is_err());
+        Ok(())
+    }
+}
@@ -1,5 +1,16 @@
+use super::client::DockerClient;
+use super::container::Container;
+use super::hooks::{
+    AnyClient, BindTo, ContainerHook, ContainerHooks, HookResult, Hooks, ImageClient,
+};
+use super::mounts::{self, Mount};
+use super::resources::{Resources, Ulimit};
+use super::run::{self, Output};
+use super::security::Security;
 use super::wait::WaitFor;
+use futures::future::BoxFuture;
 use std::collections::BTreeMap;
+use std::time::Duration;
 
 /// Represents a port mapping between a local port and the internal port of a container.
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -14,16 +25,28 @@ pub struct Port {
 //     }
 // }
 
+/// When the image is pulled before a container is created.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PullPolicy {
+    /// Pulls the image every time, so that tags like `latest` are refreshed.
+    #[default]
+    Always,
+    /// Pulls the image only if it does not exist locally, e.g. for committed snapshots.
+    Missing,
+}
+
 // todo: add wait conditions etc.
 
 /// DockerImage describes a docker container image
 ///
 /// https://pkg.go.dev/github.com/testcontainers/testcontainers-go#ContainerRequest
 ///
+/// Images with [container hooks](crate::hooks) are bound to the client `C` of
+/// their hooks, other images to [`AnyClient`].
 #[must_use]
 #[derive(Default, Debug)]
 // pub struct DockerImage<I: Image> {
-pub struct DockerImage {
+pub struct DockerImage<C: ImageClient = AnyClient> {
     // todo: add more configuration settings from the go implementation
     pub image: String,
     pub image_tag: Option<String>,
@@ -32,22 +55,33 @@ pub struct DockerImage {
     pub port_mapping: Vec<Port>,
     pub cmd: Vec<String>,
     pub labels: BTreeMap<String, String>,
-    pub tmpfs: BTreeMap<String, String>,
     pub registry_credentials: Option<String>,
     pub hostname: Vec<String>,
+    /// Entries of `/etc/hosts` in the form `host:ip`.
     pub extra_hosts: Vec<String>,
+    /// Ports of the test machine that the container reaches on [`crate::host::HOST_ALIAS`].
+    pub host_access: Vec<u16>,
     pub container_name: Option<String>,
     pub networks: Vec<String>,
     pub network_aliases: BTreeMap<String, Vec<String>>,
     pub network_mode: Option<String>,
     pub env_vars: BTreeMap<String, String>,
-    pub volumes: BTreeMap<String, String>,
+    pub mounts: Vec<Mount>,
+    /// Targets of the host temp directories of [`DockerImage::with_temp_dir`].
+    pub temp_dirs: Vec<String>,
     pub privileged: bool,
     pub shm_size: Option<i64>,
+    pub resources: Resources,
+    pub security: Security,
     pub waiting_for: Vec<WaitFor>,
+    pub reuse: bool,
+    pub pull_policy: PullPolicy,
+    /// Maximum run time of [`DockerImage::output`].
+    pub run_timeout: Option<Duration>,
+    pub hooks: Hooks<C>,
 }
 
-impl DockerImage {
+impl<C: ImageClient> DockerImage<C> {
     pub fn descriptor(&self) -> String {
         format!(
             "{}:{}",
@@ -57,6 +91,18 @@ impl DockerImage {
     }
 }
 
+/// Splits an image reference such as `localhost:5000/postgres:seeded` into name and tag.
+pub(crate) fn split_reference(reference: &str) -> (&str, Option<&str>) {
+    let name_start = reference.rfind('/').map_or(0, |slash| slash + 1);
+    match reference[name_start..].rfind(':') {
+        Some(colon) => {
+            let colon = name_start + colon;
+            (&reference[..colon], Some(&reference[colon + 1..]))
+        }
+        None => (reference, None),
+    }
+}
+
 impl DockerImage {
     pub fn new(image: impl Into<String>) -> DockerImage {
         DockerImage {
@@ -65,6 +111,59 @@ impl DockerImage {
         }
     }
 
+    /// Binds the image to the client `C` with the given container hooks.
+    pub(crate) fn with_container_hooks<C: ImageClient>(
+        self,
+        container: ContainerHooks<C>,
+    ) -> DockerImage<C> {
+        self.map_hooks(|hooks| hooks.with_container(container).0)
+    }
+}
+
+impl<C: ImageClient> DockerImage<C> {
+    /// Moves the container hooks off the image, which any client accepts then.
+    pub(crate) fn split_hooks(self) -> (DockerImage, ContainerHooks<C>) {
+        let mut container = None;
+        let image = self.map_hooks(|hooks| {
+            let (hooks, previous) = hooks.with_container(ContainerHooks::default());
+            container = Some(previous);
+            hooks
+        });
+        (image, container.unwrap_or_default())
+    }
+
+    fn map_hooks<D: ImageClient>(self, f: impl FnOnce(Hooks<C>) -> Hooks<D>) -> DockerImage<D> {
+        DockerImage {
+            image: self.image,
+            image_tag: self.image_tag,
+            entrypoint: self.entrypoint,
+            exposed_ports: self.exposed_ports,
+            port_mapping: self.port_mapping,
+            cmd: self.cmd,
+            labels: self.labels,
+            registry_credentials: self.registry_credentials,
+            hostname: self.hostname,
+            extra_hosts: self.extra_hosts,
+            host_access: self.host_access,
+            container_name: self.container_name,
+            networks: self.networks,
+            network_aliases: self.network_aliases,
+            network_mode: self.network_mode,
+            env_vars: self.env_vars,
+            mounts: self.mounts,
+            temp_dirs: self.temp_dirs,
+            privileged: self.privileged,
+            shm_size: self.shm_size,
+            resources: self.resources,
+            security: self.security,
+            waiting_for: self.waiting_for,
+            reuse: self.reuse,
+            pull_policy: self.pull_policy,
+            run_timeout: self.run_timeout,
+            hooks: f(self.hooks),
+        }
+    }
+
     pub fn with_tag(self, tag: impl Into<String>) -> Self {
         Self {
             image_tag: Some(tag.into()),
@@ -85,16 +184,101 @@ impl DockerImage {
         Self { networks, ..self }
     }
 
+    /// Makes the container reachable as `alias` on `network`, which it must join.
+    pub fn with_network_alias(self, network: impl Into<String>, alias: impl Into<String>) -> Self {
+        let mut network_aliases = self.network_aliases;
+        network_aliases
+            .entry(network.into())
+            .or_default()
+            .push(alias.into());
+        Self {
+            network_aliases,
+            ..self
+        }
+    }
+
+    /// Adds an entry to `/etc/hosts` of the container, `ip` may be `host-gateway`.
+    pub fn with_extra_host(self, host: impl Into<String>, ip: impl Into<String>) -> Self {
+        let mut extra_hosts = self.extra_hosts;
+        extra_hosts.push(format!("{}:{}", host.into(), ip.into()));
+        Self {
+            extra_hosts,
+            ..self
+        }
+    }
+
+    /// Lets the container reach services listening on `ports` of the test machine
+    /// on [`crate::host::HOST_ALIAS`].
+    pub fn with_host_access(self, ports: impl IntoIterator<Item = u16>) -> Self {
+        let mut host_access = self.host_access;
+        host_access.extend(ports);
+        Self {
+            host_access,
+            ..self
+        }
+    }
+
     pub fn with_env_var(self, key: impl Into<String>, value: impl Into<String>) -> Self {
         let mut env_vars = self.env_vars;
         env_vars.insert(key.into(), value.into());
         Self { env_vars, ..self }
     }
 
+    /// Overrides the default command of the image.
+    pub fn with_cmd<I>(self, cmd: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Self {
+            cmd: cmd.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+
+    /// Overrides the entrypoint of the image.
+    pub fn with_entrypoint<I>(self, entrypoint: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Self {
+            entrypoint: entrypoint.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+
+    /// Mounts `src` at `dest` like `docker run -v`.
+    ///
+    /// A `src` starting with `/`, `.` or `~` is a host path, anything else names a volume.
+    /// Host paths are made absolute, expanding `~` to the home directory and
+    /// resolving relative paths against the current directory.
     pub fn with_volume(self, src: impl Into<String>, dest: impl Into<String>) -> Self {
-        let mut volumes = self.volumes;
-        volumes.insert(src.into(), dest.into());
-        Self { volumes, ..self }
+        let src = src.into();
+        if src.starts_with(['/', '.', '~']) {
+            self.with_mount(Mount::bind(mounts::host_path(&src), dest))
+        } else {
+            self.with_mount(Mount::volume(src, dest))
+        }
+    }
+
+    pub fn with_mount(self, mount: impl Into<Mount>) -> Self {
+        let mut mounts = self.mounts;
+        mounts.push(mount.into());
+        Self { mounts, ..self }
+    }
+
+    /// Mounts a new host temp directory at `dest`, which is removed with the container.
+    ///
+    /// The directory is created with the container, see [`Container::temp_dir`].
+    /// Its random path is left out of the [reuse](crate::reuse) hash, so that
+    /// such images can be reused. A reused container still mounts the directory
+    /// of the run that created it, which was removed at the end of that run, so
+    /// do not combine temp directories with reuse when their contents matter.
+    pub fn with_temp_dir(self, dest: impl Into<String>) -> Self {
+        let mut temp_dirs = self.temp_dirs;
+        temp_dirs.push(dest.into());
+        Self { temp_dirs, ..self }
     }
 
     // pub fn with_exposed_port(self, container_port: u16) -> Self {
@@ -123,14 +307,363 @@ impl DockerImage {
         }
     }
 
+    pub fn with_wait_for(self, condition: WaitFor) -> Self {
+        let mut waiting_for = self.waiting_for;
+        waiting_for.push(condition);
+        Self {
+            waiting_for,
+            ..self
+        }
+    }
+
     pub fn with_privileged(self, privileged: bool) -> Self {
         Self { privileged, ..self }
     }
 
+    /// Keeps the container running after the test and reuses it for an identical image.
+    ///
+    /// Only has an effect when reuse is enabled globally, see [`crate::reuse::enabled`].
+    pub fn with_reuse(self, reuse: bool) -> Self {
+        Self { reuse, ..self }
+    }
+
+    pub fn with_pull_policy(self, pull_policy: PullPolicy) -> Self {
+        Self {
+            pull_policy,
+            ..self
+        }
+    }
+
+    /// Kills the container if it runs longer than `timeout` in [`DockerImage::output`].
+    pub fn with_run_timeout(self, timeout: Duration) -> Self {
+        Self {
+            run_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Runs the container until it exits and returns its exit status and output.
+    ///
+    /// See [`DockerClient::run_to_completion`].
+    pub async fn output<D>(self, client: &D) -> Result<Output, run::Error>
+    where
+        D: DockerClient,
+        Self: IntoImage<D> + Send,
+    {
+        client.run_to_completion(self).await
+    }
+
     pub fn with_shm_size(self, bytes: i64) -> Self {
         Self {
             shm_size: Some(bytes),
             ..self
         }
     }
+
+    /// Limits the memory of the container to `bytes`.
+    pub fn with_memory_limit(self, bytes: i64) -> Self {
+        let resources = Resources {
+            memory: Some(bytes),
+            ..self.resources
+        };
+        Self { resources, ..self }
+    }
+
+    /// Sets a soft memory limit that is enforced when the host runs low on memory.
+    pub fn with_memory_reservation(self, bytes: i64) -> Self {
+        let resources = Resources {
+            memory_reservation: Some(bytes),
+            ..self.resources
+        };
+        Self { resources, ..self }
+    }
+
+    /// Limits memory plus swap to `bytes`, `-1` allows unlimited swap.
+    ///
+    /// Setting it to the memory limit disables swap.
+    pub fn with_memory_swap(self, bytes: i64) -> Self {
+        let resources = Resources {
+            memory_swap: Some(bytes),
+            ..self.resources
+        };
+        Self { resources, ..self }
+    }
+
+    /// Limits the container to a number of CPUs, like `docker run --cpus`.
+    pub fn with_cpus(self, cpus: f64) -> Self {
+        let resources = Resources {
+            nano_cpus: Some((cpus * 1e9).round() as i64),
+            ..self.resources
+        };
+        Self { resources, ..self }
+    }
+
+    /// Limits the CPU time to `quota` microseconds per `period` microseconds.
+    pub fn with_cpu_quota(self, period: i64, quota: i64) -> Self {
+        let resources = Resources {
+            cpu_period: Some(period),
+            cpu_quota: Some(quota),
+            ..self.resources
+        };
+        Self { resources, ..self }
+    }
+
+    /// Sets the relative CPU weight, 1024 by default.
+    pub fn with_cpu_shares(self, shares: i64) -> Self {
+        let resources = Resources {
+            cpu_shares: Some(shares),
+            ..self.resources
+        };
+        Self { resources, ..self }
+    }
+
+    /// Pins the container to CPUs, e.g. `0-2` or `0,1`.
+    pub fn with_cpuset_cpus(self, cpus: impl Into<String>) -> Self {
+        let resources = Resources {
+            cpuset_cpus: Some(cpus.into()),
+            ..self.resources
+        };
+        Self { resources, ..self }
+    }
+
+    /// Pins the container to memory nodes, e.g. `0-1`.
+    pub fn with_cpuset_mems(self, mems: impl Into<String>) -> Self {
+        let resources = Resources {
+            cpuset_mems: Some(mems.into()),
+            ..self.resources
+        };
+        Self { resources, ..self }
+    }
+
+    /// Limits the number of processes, `-1` for unlimited.
+    pub fn with_pids_limit(self, limit: i64) -> Self {
+        let resources = Resources {
+            pids_limit: Some(limit),
+            ..self.resources
+        };
+        Self { resources, ..self }
+    }
+
+    /// Sets a ulimit, e.g. `nofile`, replacing an earlier one with the same name.
+    pub fn with_ulimit(self, name: impl Into<String>, soft: i64, hard: i64) -> Self {
+        let name = name.into();
+        let mut ulimits = self.resources.ulimits;
+        ulimits.retain(|ulimit| ulimit.name != name);
+        ulimits.push(Ulimit { name, soft, hard });
+        let resources = Resources {
+            ulimits,
+            ..self.resources
+        };
+        Self { resources, ..self }
+    }
+
+    /// Sets the relative block IO weight between 10 and 1000.
+    pub fn with_blkio_weight(self, weight: u16) -> Self {
+        let resources = Resources {
+            blkio_weight: Some(weight),
+            ..self.resources
+        };
+        Self { resources, ..self }
+    }
+
+    /// Sets the relative block IO weight for the device at `path`.
+    pub fn with_blkio_device_weight(self, path: impl Into<String>, weight: u16) -> Self {
+        let mut blkio_weight_devices = self.resources.blkio_weight_devices;
+        blkio_weight_devices.insert(path.into(), weight);
+        let resources = Resources {
+            blkio_weight_devices,
+            ..self.resources
+        };
+        Self { resources, ..self }
+    }
+
+    /// Disables the OOM killer for the container, which requires a memory limit.
+    ///
+    /// A container exceeding its limit then hangs instead of being killed.
+    pub fn with_oom_kill_disable(self, disable: bool) -> Self {
+        let resources = Resources {
+            oom_kill_disable: Some(disable),
+            ..self.resources
+        };
+        Self { resources, ..self }
+    }
+
+    /// Adjusts the preference of the OOM killer for the container, from -1000 to 1000.
+    pub fn with_oom_score_adj(self, adj: i64) -> Self {
+        let resources = Resources {
+            oom_score_adj: Some(adj),
+            ..self.resources
+        };
+        Self { resources, ..self }
+    }
+
+    /// Adds a linux capability, e.g. `NET_ADMIN`.
+    pub fn with_cap_add(self, capability: impl Into<String>) -> Self {
+        let mut cap_add = self.security.cap_add;
+        cap_add.push(capability.into());
+        let security = Security {
+            cap_add,
+            ..self.security
+        };
+        Self { security, ..self }
+    }
+
+    /// Drops a linux capability, `ALL` drops every capability not added explicitly.
+    pub fn with_cap_drop(self, capability: impl Into<String>) -> Self {
+        let mut cap_drop = self.security.cap_drop;
+        cap_drop.push(capability.into());
+        let security = Security {
+            cap_drop,
+            ..self.security
+        };
+        Self { security, ..self }
+    }
+
+    /// Runs the container as `user`, e.g. `1000:1000` or `postgres`.
+    pub fn with_user(self, user: impl Into<String>) -> Self {
+        let security = Security {
+            user: Some(user.into()),
+            ..self.security
+        };
+        Self { security, ..self }
+    }
+
+    /// Adds a supplementary group of the user.
+    pub fn with_group_add(self, group: impl Into<String>) -> Self {
+        let mut group_add = self.security.group_add;
+        group_add.push(group.into());
+        let security = Security {
+            group_add,
+            ..self.security
+        };
+        Self { security, ..self }
+    }
+
+    /// Mounts the root filesystem of the container read-only.
+    pub fn with_read_only_rootfs(self, read_only_rootfs: bool) -> Self {
+        let security = Security {
+            read_only_rootfs,
+            ..self.security
+        };
+        Self { security, ..self }
+    }
+
+    /// Adds a security option, e.g. `apparmor=<profile>` or `seccomp=<profile json>`.
+    pub fn with_security_opt(self, option: impl Into<String>) -> Self {
+        let mut security_opt = self.security.security_opt;
+        security_opt.push(option.into());
+        let security = Security {
+            security_opt,
+            ..self.security
+        };
+        Self { security, ..self }
+    }
+
+    /// Prevents processes of the container from gaining new privileges, e.g. via setuid.
+    pub fn with_no_new_privileges(self) -> Self {
+        self.with_security_opt("no-new-privileges")
+    }
+
+    /// Sets the user namespace mode, e.g. `host`.
+    pub fn with_userns_mode(self, mode: impl Into<String>) -> Self {
+        let security = Security {
+            userns_mode: Some(mode.into()),
+            ..self.security
+        };
+        Self { security, ..self }
+    }
+
+    /// Sets the PID namespace mode, e.g. `host` or `container:<id>`.
+    pub fn with_pid_mode(self, mode: impl Into<String>) -> Self {
+        let security = Security {
+            pid_mode: Some(mode.into()),
+            ..self.security
+        };
+        Self { security, ..self }
+    }
+
+    /// Sets the IPC namespace mode, e.g. `shareable` or `container:<id>`.
+    pub fn with_ipc_mode(self, mode: impl Into<String>) -> Self {
+        let security = Security {
+            ipc_mode: Some(mode.into()),
+            ..self.security
+        };
+        Self { security, ..self }
+    }
+
+    /// Joins the PID namespace of another container.
+    pub fn with_pid_namespace_of<D: DockerClient>(self, container: &Container<D>) -> Self {
+        self.with_pid_mode(format!("container:{}", container.id()))
+    }
+
+    /// Joins the IPC namespace of another container, which must be `shareable`.
+    pub fn with_ipc_namespace_of<D: DockerClient>(self, container: &Container<D>) -> Self {
+        self.with_ipc_mode(format!("container:{}", container.id()))
+    }
+
+    /// Registers a hook that can change the image before a container is created from it.
+    pub fn with_pre_create<F>(self, hook: F) -> Self
+    where
+        F: Fn(&mut DockerImage) -> HookResult + Send + Sync + 'static,
+    {
+        let mut hooks = self.hooks;
+        hooks.add_pre_create(hook);
+        Self { hooks, ..self }
+    }
+
+    /// Registers an async hook that receives the container at the given point of its lifecycle.
+    ///
+    /// See [`crate::hooks`] for when each hook runs.
+    /// The hook takes a container of the client `D`, so the image is bound to `D`
+    /// and images already bound to another client do not accept it.
+    pub fn with_hook<D, F>(self, hook: ContainerHook, f: F) -> DockerImage<D>
+    where
+        C: BindTo<D>,
+        D: DockerClient,
+        F: for<'a> Fn(&'a Container<D>) -> BoxFuture<'a, HookResult> + Send + Sync + 'static,
+    {
+        let mut image = self.map_hooks(C::bind);
+        image.hooks.add(hook, f);
+        image
+    }
+}
+
+/// Converts into an image that the client `C` creates containers from.
+///
+/// Implemented by images bound to `C` and everything that converts into an
+/// image without container hooks.
+pub trait IntoImage<C: DockerClient> {
+    fn into_image(self) -> DockerImage<C>;
+}
+
+impl<C: DockerClient, T: Into<DockerImage>> IntoImage<C> for T {
+    fn into_image(self) -> DockerImage<C> {
+        self.into().with_container_hooks(ContainerHooks::default())
+    }
+}
+
+impl<C: DockerClient> IntoImage<C> for DockerImage<C> {
+    fn into_image(self) -> DockerImage<C> {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_reference;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn splits_references() {
+        assert_eq!(split_reference("postgres"), ("postgres", None));
+        assert_eq!(split_reference("postgres:14"), ("postgres", Some("14")));
+        assert_eq!(
+            split_reference("localhost:5000/db/postgres"),
+            ("localhost:5000/db/postgres", None)
+        );
+        assert_eq!(
+            split_reference("localhost:5000/postgres:seeded"),
+            ("localhost:5000/postgres", Some("seeded"))
+        );
+    }
 }
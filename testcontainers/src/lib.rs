@@ -1,13 +1,45 @@
-#![allow(warnings)]
 
-pub mod logs;
-pub mod container;
+// lets the paths generated by the test macro resolve inside this crate
+extern crate self as testcontainers_rs;
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod client;
+pub mod container;
+pub mod diagnostics;
+pub mod error;
+pub mod exec;
+pub mod hooks;
+pub mod host;
+pub mod image;
+pub mod logs;
+pub mod mounts;
+pub mod network;
+pub mod partition;
 pub mod ports;
+pub mod proxy;
+pub mod resources;
+pub mod reuse;
+pub mod rt;
+pub mod run;
+pub mod security;
+#[cfg(feature = "tokio")]
+pub mod shared;
+#[cfg(feature = "tokio")]
+pub mod snapshot;
+pub mod stack;
+pub mod stats;
+#[cfg(feature = "macros")]
+pub mod testing;
+pub mod trace;
+pub mod volume;
 pub mod wait;
-pub mod image;
 // pub mod generic;
 
 pub use container::Container;
-pub use wait::WaitFor;
+pub use error::Error;
 pub use image::DockerImage;
+pub use wait::WaitFor;
+
+#[cfg(feature = "macros")]
+pub use testcontainers_rs_macros::test;
@@ -2,8 +2,34 @@ use futures::{
     stream::{BoxStream, Stream},
     StreamExt,
 };
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use std::{fmt, io};
 
+pub mod consumer;
+pub mod frames;
+
+pub use consumer::{LogConsumer, LogFollower};
+pub use frames::{FrameStream, LogFrame, LogOptions};
+
+/// The log stream of a container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "record", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "record", serde(rename_all = "lowercase"))]
+pub enum Output {
+    Stdout,
+    Stderr,
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stdout => f.write_str("stdout"),
+            Self::Stderr => f.write_str("stderr"),
+        }
+    }
+}
+
 /// WaitError describes errors when waiting for a log line
 #[derive(thiserror::Error, Debug)]
 pub enum WaitError {
@@ -32,6 +58,15 @@ impl<'s> fmt::Debug for LogStream<'s> {
     }
 }
 
+impl<'s> Stream for LogStream<'s> {
+    type Item = Result<String, io::Error>;
+
+    #[inline]
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
 impl<'s> LogStream<'s> {
     #[inline]
     // pub fn new(stream: BoxStream<'d, Result<String, io::Error>>) -> Self {
@@ -41,6 +76,12 @@ impl<'s> LogStream<'s> {
         }
     }
 
+    /// Returns the underlying stream of log lines.
+    #[inline]
+    pub fn into_inner(self) -> BoxStream<'s, Result<String, io::Error>> {
+        self.inner
+    }
+
     #[inline]
     pub async fn wait_for_message(mut self, message: &str) -> Result<(), WaitError> {
         let mut lines = vec![];
@@ -0,0 +1,402 @@
+//! Consumers that receive the log lines of a container while it runs.
+//!
+//! [`Container::follow_logs`](crate::Container::follow_logs) forwards every
+//! line of stdout and stderr to a [`LogConsumer`] from a background task.
+
+use super::Output;
+use crate::client::DockerClient;
+use crate::rt;
+use futures::channel::oneshot;
+use futures::future::{AbortHandle, Abortable};
+use futures::StreamExt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::{fmt, fs};
+
+/// A log line of a container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogLine<'a> {
+    /// The id of the container.
+    pub id: &'a str,
+    pub output: Output,
+    /// The line without its trailing newline.
+    pub line: &'a str,
+}
+
+impl<'a> LogLine<'a> {
+    /// Returns the abbreviated container id, as shown by `docker ps`.
+    pub fn short_id(&self) -> &'a str {
+        &self.id[..self.id.len().min(12)]
+    }
+}
+
+/// Receives the log lines of a container.
+pub trait LogConsumer: Send + 'static {
+    fn accept(&mut self, line: &LogLine<'_>);
+}
+
+impl<F> LogConsumer for F
+where
+    F: FnMut(&LogLine<'_>) + Send + 'static,
+{
+    fn accept(&mut self, line: &LogLine<'_>) {
+        self(line)
+    }
+}
+
+/// Forwards log lines to the `log` crate.
+///
+/// Lines are prefixed with the short container id, unless a prefix is set.
+#[derive(Debug, Clone)]
+pub struct Logger {
+    level: log::Level,
+    prefix: Option<String>,
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Self::new(log::Level::Info)
+    }
+}
+
+impl Logger {
+    pub fn new(level: log::Level) -> Self {
+        Self {
+            level,
+            prefix: None,
+        }
+    }
+
+    pub fn with_prefix(self, prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: Some(prefix.into()),
+            ..self
+        }
+    }
+}
+
+impl LogConsumer for Logger {
+    fn accept(&mut self, line: &LogLine<'_>) {
+        let prefix = self.prefix.as_deref().unwrap_or_else(|| line.short_id());
+        log::log!(self.level, "[{}] {}: {}", prefix, line.output, line.line);
+    }
+}
+
+/// Writes log lines to a file, one per line.
+pub struct FileWriter {
+    writer: io::LineWriter<fs::File>,
+}
+
+impl fmt::Debug for FileWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileWriter").finish()
+    }
+}
+
+impl FileWriter {
+    /// Creates or truncates the file at `path`.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            writer: io::LineWriter::new(fs::File::create(path)?),
+        })
+    }
+}
+
+impl LogConsumer for FileWriter {
+    fn accept(&mut self, line: &LogLine<'_>) {
+        if let Err(err) = writeln!(self.writer, "{}", line.line) {
+            log::warn!(
+                "failed to write log line of container {}: {}",
+                line.short_id(),
+                err
+            );
+        }
+    }
+}
+
+/// Collects log lines in memory for later assertions.
+///
+/// Clones share the same lines, so keep a clone to read what the follower collected.
+#[derive(Debug, Clone, Default)]
+pub struct Buffer {
+    lines: Arc<Mutex<Vec<(Output, String)>>>,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn collect(&self, filter: impl Fn(Output) -> bool) -> Vec<String> {
+        let lines = self.lines.lock().unwrap_or_else(|err| err.into_inner());
+        lines
+            .iter()
+            .filter(|(output, _)| filter(*output))
+            .map(|(_, line)| line.clone())
+            .collect()
+    }
+
+    /// Returns all lines in the order they were received.
+    pub fn lines(&self) -> Vec<String> {
+        self.collect(|_| true)
+    }
+
+    pub fn stdout(&self) -> Vec<String> {
+        self.collect(|output| output == Output::Stdout)
+    }
+
+    pub fn stderr(&self) -> Vec<String> {
+        self.collect(|output| output == Output::Stderr)
+    }
+
+    /// Returns whether any line contains `message`.
+    pub fn contains(&self, message: &str) -> bool {
+        self.lines().iter().any(|line| line.contains(message))
+    }
+}
+
+impl LogConsumer for Buffer {
+    fn accept(&mut self, line: &LogLine<'_>) {
+        let mut lines = self.lines.lock().unwrap_or_else(|err| err.into_inner());
+        lines.push((line.output, line.line.to_string()));
+    }
+}
+
+/// Prints log lines with `println!`.
+///
+/// libtest captures the output per test, as long as the runtime that drives
+/// the follower was created by the test, e.g. with `#[tokio::test]`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Print;
+
+impl LogConsumer for Print {
+    fn accept(&mut self, line: &LogLine<'_>) {
+        println!("{} {} | {}", line.short_id(), line.output, line.line);
+    }
+}
+
+/// Emits log lines as `tracing` events under a span.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone)]
+pub struct Events {
+    span: tracing::Span,
+}
+
+#[cfg(feature = "tracing")]
+impl Events {
+    pub fn new(span: tracing::Span) -> Self {
+        Self { span }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl LogConsumer for Events {
+    fn accept(&mut self, line: &LogLine<'_>) {
+        tracing::info!(
+            parent: &self.span,
+            container.id = line.id,
+            output = %line.output,
+            "{}",
+            line.line
+        );
+    }
+}
+
+/// A background task forwarding the logs of a container to a consumer.
+///
+/// Dropping the follower detaches the task, which ends with the logs of the container.
+#[derive(Debug)]
+pub struct LogFollower {
+    abort: AbortHandle,
+    done: oneshot::Receiver<()>,
+}
+
+impl LogFollower {
+    /// Stops forwarding log lines.
+    pub fn stop(&self) {
+        self.abort.abort();
+    }
+
+    /// Waits until the logs end, e.g. because the container exited, or the follower is stopped.
+    pub async fn finished(self) {
+        let _ = self.done.await;
+    }
+}
+
+/// Splits the chunks of a log stream into lines.
+///
+/// A chunk may hold several lines or end within a line, so the unterminated
+/// rest is kept until the next chunk of the same stream.
+#[derive(Debug, Default)]
+struct Lines {
+    partial: String,
+}
+
+impl Lines {
+    /// Appends `chunk` and returns the lines it completed.
+    fn push(&mut self, chunk: &str) -> Vec<String> {
+        self.partial.push_str(chunk);
+        let mut lines = Vec::new();
+        while let Some(end) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=end).collect();
+            lines.push(line.trim_end_matches(['\r', '\n']).to_string());
+        }
+        lines
+    }
+
+    /// Returns the last line if the stream did not end with a newline.
+    fn finish(self) -> Option<String> {
+        let line = self.partial.trim_end_matches('\r');
+        (!line.is_empty()).then(|| line.to_string())
+    }
+}
+
+/// Spawns a task forwarding stdout and stderr of the container to `consumer`.
+pub(crate) fn follow<C, L>(client: C, id: String, mut consumer: L) -> LogFollower
+where
+    C: DockerClient,
+    L: LogConsumer,
+{
+    let (abort, registration) = AbortHandle::new_pair();
+    let (done_tx, done) = oneshot::channel();
+    let forward = async move {
+        let stdout = client.stdout_logs(&id).map(|line| (Output::Stdout, line));
+        let stderr = client.stderr_logs(&id).map(|line| (Output::Stderr, line));
+        let mut chunks = futures::stream::select(stdout, stderr);
+        let (mut stdout, mut stderr) = (Lines::default(), Lines::default());
+        while let Some((output, chunk)) = chunks.next().await {
+            let lines = match output {
+                Output::Stdout => &mut stdout,
+                Output::Stderr => &mut stderr,
+            };
+            match chunk {
+                Ok(chunk) => {
+                    for line in lines.push(&chunk) {
+                        consumer.accept(&LogLine {
+                            id: &id,
+                            output,
+                            line: &line,
+                        });
+                    }
+                }
+                Err(err) => {
+                    log::warn!("failed to follow {} of container {}: {}", output, id, err);
+                }
+            }
+        }
+        for (output, lines) in [(Output::Stdout, stdout), (Output::Stderr, stderr)] {
+            if let Some(line) = lines.finish() {
+                consumer.accept(&LogLine {
+                    id: &id,
+                    output,
+                    line: &line,
+                });
+            }
+        }
+    };
+    rt::spawn(async move {
+        let _ = Abortable::new(forward, registration).await;
+        let _ = done_tx.send(());
+    });
+    LogFollower { abort, done }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::{Buffer, FileWriter, LogLine};
+    use crate::client::mock::MockClient;
+    use crate::client::DockerClient;
+    use crate::logs::Output;
+    use crate::DockerImage;
+    use color_eyre::eyre;
+    use pretty_assertions::assert_eq;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn follows_stdout_and_stderr() -> eyre::Result<()> {
+        let client = MockClient::new()
+            .with_stdout("redis", ["booting\n", "Ready to accept connections\n"])
+            .with_stderr("redis", ["warning: no config file\n"]);
+        let container = client.create(DockerImage::new("redis")).await?;
+        container.start().await?;
+
+        let buffer = Buffer::new();
+        container.follow_logs(buffer.clone()).finished().await;
+
+        assert_eq!(
+            buffer.stdout(),
+            vec![
+                "booting".to_string(),
+                "Ready to accept connections".to_string()
+            ]
+        );
+        assert_eq!(buffer.stderr(), vec!["warning: no config file".to_string()]);
+        assert!(buffer.contains("Ready"));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn splits_chunks_into_lines() -> eyre::Result<()> {
+        let client = MockClient::new()
+            .with_stdout(
+                "redis",
+                ["booting\r\nloading\n", "Ready to ", "accept connections\n"],
+            )
+            .with_stderr("redis", ["warning: no ", "config file"]);
+        let container = client.create(DockerImage::new("redis")).await?;
+        container.start().await?;
+
+        let buffer = Buffer::new();
+        container.follow_logs(buffer.clone()).finished().await;
+
+        assert_eq!(
+            buffer.stdout(),
+            vec![
+                "booting".to_string(),
+                "loading".to_string(),
+                "Ready to accept connections".to_string()
+            ]
+        );
+        assert_eq!(buffer.stderr(), vec!["warning: no config file".to_string()]);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn forwards_to_closures_and_files() -> eyre::Result<()> {
+        let client = MockClient::new().with_stdout("redis", ["one\n", "two"]);
+        let container = client.create(DockerImage::new("redis")).await?;
+        container.start().await?;
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        {
+            let seen = seen.clone();
+            let id = container.id().to_string();
+            container
+                .follow_logs(move |line: &LogLine<'_>| {
+                    assert_eq!(line.id, id);
+                    seen.lock()
+                        .unwrap()
+                        .push((line.output, line.line.to_string()));
+                })
+                .finished()
+                .await;
+        }
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (Output::Stdout, "one".to_string()),
+                (Output::Stdout, "two".to_string())
+            ]
+        );
+
+        let path = std::env::temp_dir().join(format!("testcontainers-{}.log", container.id()));
+        container
+            .follow_logs(FileWriter::create(&path)?)
+            .finished()
+            .await;
+        assert_eq!(std::fs::read_to_string(&path)?, "one\ntwo\n");
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
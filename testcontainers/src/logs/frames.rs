@@ -0,0 +1,276 @@
+//! Log retrieval with stream tags, timestamps and time windows.
+
+use super::Output;
+use futures::stream::BoxStream;
+use std::borrow::Cow;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A stream of log frames.
+pub type FrameStream<'s> = BoxStream<'s, Result<LogFrame, io::Error>>;
+
+/// Selects which logs of a container to retrieve.
+///
+/// The default is a snapshot of all stdout and stderr logs without timestamps.
+#[must_use]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "record", derive(serde::Serialize, serde::Deserialize))]
+pub struct LogOptions {
+    pub follow: bool,
+    pub stdout: bool,
+    pub stderr: bool,
+    pub timestamps: bool,
+    pub since: Option<SystemTime>,
+    pub until: Option<SystemTime>,
+    /// Only the last `tail` frames, counted over all selected streams.
+    pub tail: Option<usize>,
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        Self {
+            follow: false,
+            stdout: true,
+            stderr: true,
+            timestamps: false,
+            since: None,
+            until: None,
+            tail: None,
+        }
+    }
+}
+
+impl LogOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps the stream open and yields new frames as they are logged.
+    pub fn with_follow(self, follow: bool) -> Self {
+        Self { follow, ..self }
+    }
+
+    pub fn with_stdout(self, stdout: bool) -> Self {
+        Self { stdout, ..self }
+    }
+
+    pub fn with_stderr(self, stderr: bool) -> Self {
+        Self { stderr, ..self }
+    }
+
+    /// Parses the docker timestamp of every frame into [`LogFrame::timestamp`].
+    pub fn with_timestamps(self, timestamps: bool) -> Self {
+        Self { timestamps, ..self }
+    }
+
+    /// Only frames logged at or after `since`.
+    ///
+    /// Docker only supports whole seconds.
+    pub fn with_since(self, since: SystemTime) -> Self {
+        Self {
+            since: Some(since),
+            ..self
+        }
+    }
+
+    /// Only frames logged before `until`.
+    ///
+    /// Docker only supports whole seconds.
+    pub fn with_until(self, until: SystemTime) -> Self {
+        Self {
+            until: Some(until),
+            ..self
+        }
+    }
+
+    pub fn with_tail(self, tail: usize) -> Self {
+        Self {
+            tail: Some(tail),
+            ..self
+        }
+    }
+
+    /// Returns whether a frame of the stream at the given time is selected,
+    /// for clients that filter frames themselves.
+    #[cfg(feature = "mock")]
+    pub(crate) fn selects(&self, output: Output, timestamp: SystemTime) -> bool {
+        let stream = match output {
+            Output::Stdout => self.stdout,
+            Output::Stderr => self.stderr,
+        };
+        stream
+            && self.since.is_none_or(|since| timestamp >= since)
+            && self.until.is_none_or(|until| timestamp < until)
+    }
+}
+
+/// A chunk of log output as written by the container.
+///
+/// Docker frames usually hold a single line including its trailing newline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "record", derive(serde::Serialize, serde::Deserialize))]
+pub struct LogFrame {
+    pub output: Output,
+    /// Only set when [`LogOptions::timestamps`] was requested.
+    pub timestamp: Option<SystemTime>,
+    pub bytes: Vec<u8>,
+}
+
+impl LogFrame {
+    /// Returns the frame as text, replacing invalid UTF-8.
+    pub fn line(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.bytes)
+    }
+
+    /// Splits the docker timestamp off a frame that was requested with timestamps.
+    pub(crate) fn with_timestamp(output: Output, bytes: &[u8]) -> Result<Self, io::Error> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "log frame without timestamp: {:?}",
+                    String::from_utf8_lossy(bytes)
+                ),
+            )
+        };
+        let split = bytes.iter().position(|b| *b == b' ').unwrap_or(bytes.len());
+        let timestamp = std::str::from_utf8(&bytes[..split])
+            .ok()
+            .and_then(parse_timestamp)
+            .ok_or_else(invalid)?;
+        Ok(Self {
+            output,
+            timestamp: Some(timestamp),
+            bytes: bytes.get(split + 1..).unwrap_or_default().to_vec(),
+        })
+    }
+}
+
+/// Returns the number of days since the unix epoch of a date in the proleptic gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parses an RFC 3339 timestamp as written by docker, e.g. `2023-01-30T12:00:00.123456789Z`.
+pub(crate) fn parse_timestamp(value: &str) -> Option<SystemTime> {
+    fn number<T: std::str::FromStr>(value: &str, range: std::ops::Range<usize>) -> Option<T> {
+        let digits = value.get(range)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    }
+
+    let bytes = value.as_bytes();
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return None;
+    }
+    let year: i64 = number(value, 0..4)?;
+    let month: u32 = number(value, 5..7)?;
+    let day: u32 = number(value, 8..10)?;
+    let hour: i64 = number(value, 11..13)?;
+    let minute: i64 = number(value, 14..16)?;
+    let second: i64 = number(value, 17..19)?;
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    let mut rest = &value[19..];
+    let mut nanos = 0u32;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let len = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if len == 0 {
+            return None;
+        }
+        // nanosecond precision, further digits are truncated
+        let digits = &fraction[..len.min(9)];
+        nanos = digits.parse::<u32>().ok()? * 10u32.pow(9 - digits.len() as u32);
+        rest = &fraction[len..];
+    }
+
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.as_bytes().first()? {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            if rest.len() != 6 || rest.as_bytes()[3] != b':' {
+                return None;
+            }
+            let hours: i64 = number(rest, 1..3)?;
+            let minutes: i64 = number(rest, 4..6)?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+
+    let secs =
+        days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    let secs = u64::try_from(secs).ok()?;
+    Some(UNIX_EPOCH + Duration::new(secs, nanos))
+}
+
+/// Returns the unix timestamp in whole seconds, as expected by the docker API.
+pub(crate) fn unix_seconds(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_timestamp, LogFrame};
+    use crate::logs::Output;
+    use color_eyre::eyre;
+    use pretty_assertions::assert_eq;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn parses_docker_timestamps() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(UNIX_EPOCH));
+        assert_eq!(
+            parse_timestamp("2023-01-30T12:34:56.123456789Z"),
+            Some(UNIX_EPOCH + Duration::new(1_675_082_096, 123_456_789))
+        );
+        assert_eq!(
+            parse_timestamp("2024-02-29T00:00:00.5+01:00"),
+            Some(UNIX_EPOCH + Duration::new(1_709_161_200, 500_000_000))
+        );
+        assert_eq!(parse_timestamp("2023-13-01T00:00:00Z"), None);
+        assert_eq!(parse_timestamp("2023-01-30 12:34"), None);
+        assert_eq!(parse_timestamp("not a timestamp at all"), None);
+    }
+
+    #[test]
+    fn splits_timestamp_off_frames() -> eyre::Result<()> {
+        let frame = LogFrame::with_timestamp(Output::Stderr, b"2023-01-30T12:34:56Z \xffpanic\n")?;
+        assert_eq!(frame.output, Output::Stderr);
+        assert_eq!(
+            frame.timestamp,
+            Some(UNIX_EPOCH + Duration::from_secs(1_675_082_096))
+        );
+        assert_eq!(frame.bytes, b"\xffpanic\n".to_vec());
+        assert_eq!(frame.line(), "\u{fffd}panic\n");
+        assert!(LogFrame::with_timestamp(Output::Stdout, b"panic").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,576 @@
+//! Bind, volume and tmpfs mounts of containers.
+//!
+//! Mounts are added with [`DockerImage::with_mount`](crate::DockerImage::with_mount)
+//! and map to the `HostConfig::mounts` of the docker API.
+//! Bind mounts with SELinux relabeling are passed as `HostConfig::binds`
+//! instead, because the mounts API has no relabel option.
+
+use super::image::DockerImage;
+use bollard::models::{
+    HostConfig, Mount as DockerMount, MountBindOptions, MountBindOptionsPropagationEnum,
+    MountTmpfsOptions, MountTypeEnum, MountVolumeOptions, MountVolumeOptionsDriverConfig,
+};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fs, io};
+
+/// A mount of a container.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mount {
+    Bind(BindMount),
+    Volume(VolumeMount),
+    Tmpfs(TmpfsMount),
+}
+
+impl Mount {
+    /// Mounts the host path `source` at `target`.
+    ///
+    /// Unlike with `docker run -v`, a missing `source` is not created.
+    pub fn bind(source: impl Into<PathBuf>, target: impl Into<String>) -> BindMount {
+        BindMount {
+            source: source.into(),
+            target: target.into(),
+            read_only: false,
+            relabel: None,
+            propagation: None,
+        }
+    }
+
+    /// Mounts the named volume at `target`, creating the volume if it does not exist.
+    pub fn volume(name: impl Into<String>, target: impl Into<String>) -> VolumeMount {
+        VolumeMount {
+            name: Some(name.into()),
+            ..Self::anonymous_volume(target)
+        }
+    }
+
+    /// Mounts a new anonymous volume at `target`.
+    pub fn anonymous_volume(target: impl Into<String>) -> VolumeMount {
+        VolumeMount {
+            name: None,
+            target: target.into(),
+            read_only: false,
+            no_copy: false,
+            driver: None,
+            driver_options: BTreeMap::new(),
+            labels: BTreeMap::new(),
+        }
+    }
+
+    /// Mounts an in-memory filesystem at `target`.
+    pub fn tmpfs(target: impl Into<String>) -> TmpfsMount {
+        TmpfsMount {
+            target: target.into(),
+            size: None,
+            mode: None,
+        }
+    }
+
+    /// Returns the path of the mount inside the container.
+    pub fn target(&self) -> &str {
+        match self {
+            Self::Bind(bind) => &bind.target,
+            Self::Volume(volume) => &volume.target,
+            Self::Tmpfs(tmpfs) => &tmpfs.target,
+        }
+    }
+
+    /// Sets the mounts in the host configuration of a container.
+    pub(crate) fn apply(mounts: &[Self], host_config: &mut HostConfig) {
+        let (binds, mounts): (Vec<_>, Vec<_>) = mounts.iter().partition(|mount| {
+            matches!(
+                mount,
+                Self::Bind(BindMount {
+                    relabel: Some(_),
+                    ..
+                })
+            )
+        });
+        host_config.binds = (!binds.is_empty()).then(|| {
+            binds
+                .into_iter()
+                .filter_map(|mount| match mount {
+                    Self::Bind(bind) => Some(bind.to_bind()),
+                    _ => None,
+                })
+                .collect()
+        });
+        host_config.mounts =
+            (!mounts.is_empty()).then(|| mounts.into_iter().map(Self::to_docker).collect());
+    }
+
+    fn to_docker(&self) -> DockerMount {
+        match self {
+            Self::Bind(bind) => DockerMount {
+                target: Some(bind.target.clone()),
+                source: Some(bind.source.to_string_lossy().into_owned()),
+                typ: Some(MountTypeEnum::BIND),
+                read_only: Some(bind.read_only),
+                bind_options: bind.propagation.map(|propagation| MountBindOptions {
+                    propagation: Some(propagation.into()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            Self::Volume(volume) => {
+                let options = MountVolumeOptions {
+                    no_copy: volume.no_copy.then_some(true),
+                    labels: (!volume.labels.is_empty())
+                        .then(|| volume.labels.clone().into_iter().collect()),
+                    driver_config: volume.driver.as_ref().map(|driver| {
+                        MountVolumeOptionsDriverConfig {
+                            name: Some(driver.clone()),
+                            options: Some(volume.driver_options.clone().into_iter().collect()),
+                        }
+                    }),
+                };
+                DockerMount {
+                    target: Some(volume.target.clone()),
+                    source: volume.name.clone(),
+                    typ: Some(MountTypeEnum::VOLUME),
+                    read_only: Some(volume.read_only),
+                    volume_options: (options != MountVolumeOptions::default()).then_some(options),
+                    ..Default::default()
+                }
+            }
+            Self::Tmpfs(tmpfs) => DockerMount {
+                target: Some(tmpfs.target.clone()),
+                typ: Some(MountTypeEnum::TMPFS),
+                tmpfs_options: (tmpfs.size.is_some() || tmpfs.mode.is_some()).then(|| {
+                    MountTmpfsOptions {
+                        size_bytes: tmpfs.size,
+                        mode: tmpfs.mode.map(i64::from),
+                    }
+                }),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// SELinux relabeling of a bind mount.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Relabel {
+    /// The content is shared between containers (`z`).
+    Shared,
+    /// The content is private to the container (`Z`).
+    Private,
+}
+
+/// Propagation of mounts below a bind mount.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Propagation {
+    Private,
+    RPrivate,
+    Shared,
+    RShared,
+    Slave,
+    RSlave,
+}
+
+impl From<Propagation> for MountBindOptionsPropagationEnum {
+    fn from(propagation: Propagation) -> Self {
+        match propagation {
+            Propagation::Private => Self::PRIVATE,
+            Propagation::RPrivate => Self::RPRIVATE,
+            Propagation::Shared => Self::SHARED,
+            Propagation::RShared => Self::RSHARED,
+            Propagation::Slave => Self::SLAVE,
+            Propagation::RSlave => Self::RSLAVE,
+        }
+    }
+}
+
+/// A host path mounted into a container.
+#[must_use]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BindMount {
+    pub source: PathBuf,
+    pub target: String,
+    pub read_only: bool,
+    pub relabel: Option<Relabel>,
+    pub propagation: Option<Propagation>,
+}
+
+impl BindMount {
+    pub fn read_only(self) -> Self {
+        Self {
+            read_only: true,
+            ..self
+        }
+    }
+
+    pub fn with_relabel(self, relabel: Relabel) -> Self {
+        Self {
+            relabel: Some(relabel),
+            ..self
+        }
+    }
+
+    pub fn with_propagation(self, propagation: Propagation) -> Self {
+        Self {
+            propagation: Some(propagation),
+            ..self
+        }
+    }
+
+    /// Returns the mount in the `source:target:options` form of `HostConfig::binds`.
+    fn to_bind(&self) -> String {
+        let mut options = vec![if self.read_only { "ro" } else { "rw" }];
+        match self.relabel {
+            Some(Relabel::Shared) => options.push("z"),
+            Some(Relabel::Private) => options.push("Z"),
+            None => {}
+        }
+        if let Some(propagation) = self.propagation {
+            options.push(match propagation {
+                Propagation::Private => "private",
+                Propagation::RPrivate => "rprivate",
+                Propagation::Shared => "shared",
+                Propagation::RShared => "rshared",
+                Propagation::Slave => "slave",
+                Propagation::RSlave => "rslave",
+            });
+        }
+        format!(
+            "{}:{}:{}",
+            self.source.display(),
+            self.target,
+            options.join(",")
+        )
+    }
+}
+
+/// Resolves a host path in the form of `docker run -v`, since the mounts API
+/// only accepts absolute paths.
+///
+/// A leading `~` is expanded to the home directory and relative paths are
+/// resolved against the current directory.
+pub(crate) fn host_path(source: &str) -> PathBuf {
+    let home = || {
+        env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .map(PathBuf::from)
+    };
+    let path = match source.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => match home() {
+            Some(home) => home.join(rest.trim_start_matches('/')),
+            None => PathBuf::from(source),
+        },
+        _ => PathBuf::from(source),
+    };
+    if path.is_absolute() {
+        return path;
+    }
+    match env::current_dir() {
+        // collecting the components drops the `.` components
+        Ok(dir) => dir.join(&path).components().collect(),
+        Err(_) => path,
+    }
+}
+
+/// A named or anonymous docker volume mounted into a container.
+#[must_use]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VolumeMount {
+    /// The name of the volume, `None` for an anonymous volume.
+    pub name: Option<String>,
+    pub target: String,
+    pub read_only: bool,
+    /// Does not copy the content of `target` in the image into a new volume.
+    pub no_copy: bool,
+    /// The volume driver used if the volume is created.
+    pub driver: Option<String>,
+    pub driver_options: BTreeMap<String, String>,
+    /// Labels of the volume if it is created.
+    pub labels: BTreeMap<String, String>,
+}
+
+impl VolumeMount {
+    pub fn read_only(self) -> Self {
+        Self {
+            read_only: true,
+            ..self
+        }
+    }
+
+    pub fn with_no_copy(self) -> Self {
+        Self {
+            no_copy: true,
+            ..self
+        }
+    }
+
+    pub fn with_driver(self, driver: impl Into<String>) -> Self {
+        Self {
+            driver: Some(driver.into()),
+            ..self
+        }
+    }
+
+    pub fn with_driver_option(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        let mut driver_options = self.driver_options;
+        driver_options.insert(key.into(), value.into());
+        Self {
+            driver_options,
+            ..self
+        }
+    }
+
+    pub fn with_label(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        let mut labels = self.labels;
+        labels.insert(key.into(), value.into());
+        Self { labels, ..self }
+    }
+}
+
+/// An in-memory filesystem mounted into a container.
+#[must_use]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TmpfsMount {
+    pub target: String,
+    /// Size limit in bytes, unlimited by default.
+    pub size: Option<i64>,
+    /// File mode of the mount point, e.g. `0o1777`.
+    pub mode: Option<u32>,
+}
+
+impl TmpfsMount {
+    pub fn with_size(self, bytes: i64) -> Self {
+        Self {
+            size: Some(bytes),
+            ..self
+        }
+    }
+
+    pub fn with_mode(self, mode: u32) -> Self {
+        Self {
+            mode: Some(mode),
+            ..self
+        }
+    }
+}
+
+impl From<BindMount> for Mount {
+    fn from(bind: BindMount) -> Self {
+        Self::Bind(bind)
+    }
+}
+
+impl From<VolumeMount> for Mount {
+    fn from(volume: VolumeMount) -> Self {
+        Self::Volume(volume)
+    }
+}
+
+impl From<TmpfsMount> for Mount {
+    fn from(tmpfs: TmpfsMount) -> Self {
+        Self::Tmpfs(tmpfs)
+    }
+}
+
+static NEXT_TEMP_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A host directory that is removed when dropped.
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+    target: String,
+}
+
+impl TempDir {
+    /// Creates a world-writable directory to be mounted at `target`,
+    /// so that containers running as any user can write to it.
+    pub(crate) fn create(target: impl Into<String>) -> io::Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "testcontainers-{}-{}",
+            std::process::id(),
+            NEXT_TEMP_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o777))?;
+        }
+        Ok(Self {
+            path,
+            target: target.into(),
+        })
+    }
+
+    /// Returns the path of the directory on the host.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path the directory is mounted at inside the container.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+}
+
+/// Creates the temp directories of `image` and mounts them at their targets.
+pub(crate) fn create_temp_dirs(image: &mut DockerImage) -> io::Result<Vec<TempDir>> {
+    let mut dirs = Vec::new();
+    for target in &image.temp_dirs {
+        let dir = TempDir::create(target.clone())?;
+        image
+            .mounts
+            .push(Mount::bind(dir.path(), target.clone()).into());
+        dirs.push(dir);
+    }
+    Ok(dirs)
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // files created by a container running as root cannot always be removed
+        if let Err(err) = fs::remove_dir_all(&self.path) {
+            log::warn!(
+                "failed to remove temporary directory {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{host_path, Mount, Propagation, Relabel};
+    use crate::client::bollard::container_config;
+    use crate::DockerImage;
+    use bollard::models::{
+        Mount as DockerMount, MountBindOptions, MountBindOptionsPropagationEnum, MountTmpfsOptions,
+        MountTypeEnum, MountVolumeOptions, MountVolumeOptionsDriverConfig,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn host_paths_are_absolute() {
+        let dir = std::env::current_dir().unwrap();
+        assert_eq!(
+            host_path("/etc/postgres"),
+            std::path::PathBuf::from("/etc/postgres")
+        );
+        assert_eq!(host_path("./conf/pg.conf"), dir.join("conf/pg.conf"));
+        assert_eq!(host_path("../conf"), dir.join("../conf"));
+        if let Some(home) = std::env::var_os("HOME") {
+            let home = std::path::PathBuf::from(home);
+            assert_eq!(host_path("~"), home);
+            assert_eq!(host_path("~/.aws"), home.join(".aws"));
+        }
+    }
+
+    #[test]
+    fn mounts_in_host_config() {
+        let image = DockerImage::new("postgres")
+            .with_mount(
+                Mount::bind("/etc/postgres", "/etc/postgresql")
+                    .read_only()
+                    .with_propagation(Propagation::RSlave),
+            )
+            .with_mount(Mount::bind("/srv/data", "/data").with_relabel(Relabel::Private))
+            .with_mount(
+                Mount::volume("pgdata", "/var/lib/postgresql/data")
+                    .with_driver("local")
+                    .with_driver_option("type", "tmpfs"),
+            )
+            .with_mount(Mount::tmpfs("/run").with_size(64 << 20).with_mode(0o1777))
+            .with_volume("./conf", "/conf")
+            .with_volume("cache", "/cache");
+        let host_config = container_config(&image).host_config.unwrap_or_default();
+        assert_eq!(
+            host_config.binds,
+            Some(vec!["/srv/data:/data:rw,Z".to_string()])
+        );
+        assert_eq!(
+            host_config.mounts,
+            Some(vec![
+                DockerMount {
+                    target: Some("/etc/postgresql".to_string()),
+                    source: Some("/etc/postgres".to_string()),
+                    typ: Some(MountTypeEnum::BIND),
+                    read_only: Some(true),
+                    bind_options: Some(MountBindOptions {
+                        propagation: Some(MountBindOptionsPropagationEnum::RSLAVE),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                DockerMount {
+                    target: Some("/var/lib/postgresql/data".to_string()),
+                    source: Some("pgdata".to_string()),
+                    typ: Some(MountTypeEnum::VOLUME),
+                    read_only: Some(false),
+                    volume_options: Some(MountVolumeOptions {
+                        driver_config: Some(MountVolumeOptionsDriverConfig {
+                            name: Some("local".to_string()),
+                            options: Some([("type".to_string(), "tmpfs".to_string())].into()),
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                DockerMount {
+                    target: Some("/run".to_string()),
+                    typ: Some(MountTypeEnum::TMPFS),
+                    tmpfs_options: Some(MountTmpfsOptions {
+                        size_bytes: Some(64 << 20),
+                        mode: Some(0o1777),
+                    }),
+                    ..Default::default()
+                },
+                DockerMount {
+                    target: Some("/conf".to_string()),
+                    source: Some(
+                        std::env::current_dir()
+                            .unwrap()
+                            .join("conf")
+                            .to_string_lossy()
+                            .into_owned(),
+                    ),
+                    typ: Some(MountTypeEnum::BIND),
+                    read_only: Some(false),
+                    ..Default::default()
+                },
+                DockerMount {
+                    target: Some("/cache".to_string()),
+                    source: Some("cache".to_string()),
+                    typ: Some(MountTypeEnum::VOLUME),
+                    read_only: Some(false),
+                    ..Default::default()
+                },
+            ])
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn temp_dir_is_removed_with_container() -> color_eyre::eyre::Result<()> {
+        use crate::client::mock::MockClient;
+        use crate::client::DockerClient;
+
+        let client = MockClient::new();
+        let image = DockerImage::new("redis").with_temp_dir("/data");
+        assert!(image.mounts.is_empty());
+
+        let container = client.create(image).await?;
+        let path = container
+            .temp_dir("/data")
+            .expect("temp dir is created")
+            .to_path_buf();
+        assert!(path.is_dir());
+        std::fs::write(path.join("dump.rdb"), "REDIS")?;
+        assert!(container
+            .image()
+            .mounts
+            .contains(&Mount::Bind(Mount::bind(&path, "/data"))));
+
+        container.rm().await?;
+        assert!(!path.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+//! User-defined docker networks that containers can reach each other on by name.
+//!
+//! A [`Network`] is created with [`DockerClient::network`] and removed when the
+//! handle is dropped, unless it is [kept](Network::keep) or already existed.
+//! Containers join it with [`DockerImage::with_network`](crate::DockerImage::with_network)
+//! and are reachable by their [aliases](crate::DockerImage::with_network_alias).
+//! Containers can be disconnected and reconnected while running, see also
+//! [`Partition`](crate::partition::Partition) to cut the links between groups of containers.
+
+use super::client::DockerClient;
+use super::container::Container;
+use super::error::{Error, Operation};
+use super::rt;
+use std::fmt;
+
+/// The label set on every network created by this crate.
+pub const LABEL: &str = "org.testcontainers.network";
+
+/// A handle to a network that removes it when dropped.
+pub struct Network<C>
+where
+    C: DockerClient,
+{
+    name: String,
+    client: C,
+    keep: bool,
+    removed: bool,
+}
+
+impl<C> fmt::Debug for Network<C>
+where
+    C: DockerClient,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Network")
+            .field("name", &self.name)
+            .field("keep", &self.keep)
+            .finish()
+    }
+}
+
+impl<C> Network<C>
+where
+    C: DockerClient,
+{
+    pub(crate) fn new(name: String, client: C) -> Self {
+        Self {
+            name,
+            client,
+            keep: false,
+            removed: false,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Keeps the network when the handle is dropped.
+    #[must_use]
+    pub fn keep(mut self) -> Self {
+        self.keep = true;
+        self
+    }
+
+    /// Returns whether the network is kept when the handle is dropped.
+    pub fn is_kept(&self) -> bool {
+        self.keep
+    }
+
+    pub(crate) fn client(&self) -> &C {
+        &self.client
+    }
+
+    /// Disconnects a container, which can no longer reach the other containers in the network.
+    pub async fn disconnect(&self, container: &Container<C>) -> Result<(), Error> {
+        self.client
+            .disconnect_network(&self.name, container.id())
+            .await
+            .map_err(|err| container.error(Operation::Network, err))
+    }
+
+    /// Connects a container again, reachable by the aliases of its image.
+    ///
+    /// The address of the container in the network may change, and its mapped
+    /// ports are published again once it is connected to any network.
+    pub async fn reconnect(&self, container: &Container<C>) -> Result<(), Error> {
+        let aliases = container
+            .image()
+            .network_aliases
+            .get(&self.name)
+            .cloned()
+            .unwrap_or_default();
+        self.client
+            .connect_network(&self.name, container.id(), aliases)
+            .await
+            .map_err(|err| container.error(Operation::Network, err))
+    }
+
+    /// Removes the network, which fails while containers are connected to it.
+    ///
+    /// If removing fails, the network is removed again when the handle is dropped.
+    pub async fn rm(mut self) -> Result<(), Error> {
+        self.client
+            .rm_network(&self.name)
+            .await
+            .map_err(|err| Error::new(Operation::Network, err))?;
+        self.removed = true;
+        Ok(())
+    }
+}
+
+impl<C> Drop for Network<C>
+where
+    C: DockerClient,
+{
+    fn drop(&mut self) {
+        if self.removed || self.keep {
+            return;
+        }
+        if let Err(err) = rt::block_on(self.client.rm_network(&self.name)) {
+            log::error!("failed to remove docker network {}: {}", self.name, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::bollard::container_config;
+    use crate::DockerImage;
+    use pretty_assertions::assert_eq;
+
+    #[cfg(feature = "mock")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn disconnects_and_reconnects_containers() -> color_eyre::eyre::Result<()> {
+        use crate::client::mock::MockClient;
+        use crate::client::DockerClient;
+
+        let client = MockClient::new();
+        let network = client.network("backend").await?;
+        let container = client
+            .create(
+                DockerImage::new("postgres")
+                    .with_network("backend")
+                    .with_network_alias("backend", "db")
+                    .with_mapped_port(0, 5432),
+            )
+            .await?;
+        container.start().await?;
+        let address = |inspect: bollard::models::ContainerInspectResponse| {
+            let endpoint = inspect.network_settings?.networks?.remove("backend")?;
+            Some((endpoint.ip_address?, endpoint.aliases?))
+        };
+        let (before, aliases) = address(container.inspect().await?).unwrap();
+        assert_eq!(aliases, vec!["db"]);
+
+        network.disconnect(&container).await?;
+        assert!(client.container_networks(container.id()).is_empty());
+        assert!(container.host().await.is_err());
+        assert_eq!(container.mapped_port_ipv4(5432).await?, None);
+
+        network.reconnect(&container).await?;
+        let (after, aliases) = address(container.inspect().await?).unwrap();
+        assert_eq!(aliases, vec!["db"]);
+        assert_ne!(before, after);
+        assert!(container.host().await.is_ok());
+        assert!(container.mapped_port_ipv4(5432).await?.is_some());
+        Ok(())
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keeps_existing_network() -> color_eyre::eyre::Result<()> {
+        use crate::client::mock::MockClient;
+        use crate::client::DockerClient;
+
+        let client = MockClient::new();
+        let network = client.network("backend").await?;
+        assert!(!network.is_kept());
+
+        // a network that existed before is not removed with the handle
+        let existing = client.network("backend").await?;
+        assert!(existing.is_kept());
+        drop(existing);
+        assert_eq!(client.networks(), vec!["backend".to_string()]);
+
+        drop(network);
+        assert!(client.networks().is_empty());
+        Ok(())
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failed_removal_is_retried_on_drop() -> color_eyre::eyre::Result<()> {
+        use crate::client::mock::{MockClient, Operation};
+        use crate::client::DockerClient;
+
+        let client = MockClient::new();
+        let network = client.network("backend").await?;
+        client.inject_failure(Operation::Network, "network is in use");
+        let err = network.rm().await.unwrap_err();
+        assert_eq!(err.operation(), crate::error::Operation::Network);
+        assert!(client.networks().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn networks_in_config() {
+        let image = DockerImage::new("postgres")
+            .with_network("backend")
+            .with_network("monitoring")
+            .with_network_alias("backend", "db");
+        let config = container_config(&image);
+        assert_eq!(
+            config
+                .host_config
+                .unwrap_or_default()
+                .network_mode
+                .as_deref(),
+            Some("backend")
+        );
+        let endpoints = config.networking_config.unwrap().endpoints_config;
+        assert_eq!(endpoints.keys().collect::<Vec<_>>(), vec!["backend"]);
+        assert_eq!(endpoints["backend"].aliases, Some(vec!["db".to_string()]));
+    }
+}
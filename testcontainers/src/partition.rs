@@ -0,0 +1,581 @@
+//! Network partitions between groups of containers.
+//!
+//! A [`Partition`] splits the containers of a [`Network`] into groups that can
+//! only reach the containers of their own group, until the partition is healed.
+//!
+//! With [`Strategy::Disconnect`], the members of every group are moved from the
+//! network onto a private network of their group, where they keep their aliases.
+//! A group of a single container gets a private network as well, so that it
+//! stays reachable from the host with its published ports.
+//! Containers in no group lose the links to all grouped containers.
+//! With [`Strategy::Iptables`], the network stays as is and every member drops
+//! the traffic from and to the members of other groups, which requires
+//! `iptables` in the containers and the `NET_ADMIN` capability, see
+//! [`DockerImage::with_cap_add`](crate::DockerImage::with_cap_add).
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use testcontainers_rs::client::{bollard::Client, DockerClient};
+//! use testcontainers_rs::partition::Partition;
+//! use testcontainers_rs::DockerImage;
+//!
+//! let client = Client::new().await?;
+//! let network = client.network("cluster").await?;
+//! let mut nodes = Vec::new();
+//! for name in ["node-1", "node-2", "node-3"] {
+//!     let node = client
+//!         .create(
+//!             DockerImage::new("etcd")
+//!                 .with_network("cluster")
+//!                 .with_network_alias("cluster", name),
+//!         )
+//!         .await?;
+//!     node.start().await?;
+//!     nodes.push(node);
+//! }
+//! let split = Partition::new(&network)
+//!     .with_group([&nodes[0], &nodes[1]])
+//!     .with_group([&nodes[2]])
+//!     .split()
+//!     .await?;
+//! // node-3 is isolated from the majority
+//! split.heal().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Addresses of containers in the network may change once they are reconnected,
+//! so [`Container::host`] and [`Container::ports`] should be queried again after healing.
+
+use super::client::DockerClient;
+use super::container::Container;
+use super::error::Operation;
+use super::exec::ExecResult;
+use super::network::Network;
+use super::rt;
+use std::collections::BTreeSet;
+use std::fmt;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("container {id} is in more than one group")]
+    Overlap { id: String },
+
+    #[error("failed to create or remove partition network {name}")]
+    Network {
+        name: String,
+        #[source]
+        source: crate::Error,
+    },
+
+    #[error("failed to connect container {id} to network {network}")]
+    Connect {
+        id: String,
+        network: String,
+        #[source]
+        source: crate::Error,
+    },
+
+    #[error("failed to disconnect container {id} from network {network}")]
+    Disconnect {
+        id: String,
+        network: String,
+        #[source]
+        source: crate::Error,
+    },
+
+    #[error("failed to inspect container {id}")]
+    Inspect {
+        id: String,
+        #[source]
+        source: crate::Error,
+    },
+
+    #[error("container {id} has no address in network {network}")]
+    MissingAddress { id: String, network: String },
+
+    #[error("failed to run iptables in container {id}")]
+    Exec {
+        id: String,
+        #[source]
+        source: crate::Error,
+    },
+
+    #[error("iptables {rule:?} in container {id} exited with {:?}: {}", .result.exit_code, .result.stderr)]
+    Iptables {
+        id: String,
+        rule: Vec<String>,
+        result: ExecResult,
+    },
+}
+
+/// How a [`Partition`] cuts the links between groups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// Moves every group onto a private network.
+    #[default]
+    Disconnect,
+    /// Drops traffic between groups with `iptables` rules inside the containers.
+    Iptables,
+}
+
+/// Groups of containers of a network that are split from each other.
+#[must_use]
+pub struct Partition<'a, C>
+where
+    C: DockerClient,
+{
+    network: &'a Network<C>,
+    groups: Vec<Vec<&'a Container<C>>>,
+    strategy: Strategy,
+}
+
+impl<'a, C> fmt::Debug for Partition<'a, C>
+where
+    C: DockerClient,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Partition")
+            .field("network", &self.network.name())
+            .field("groups", &ids(&self.groups))
+            .field("strategy", &self.strategy)
+            .finish()
+    }
+}
+
+fn ids<C: DockerClient>(groups: &[Vec<&Container<C>>]) -> Vec<Vec<String>> {
+    groups
+        .iter()
+        .map(|group| group.iter().map(|member| member.id().to_string()).collect())
+        .collect()
+}
+
+impl<'a, C> Partition<'a, C>
+where
+    C: DockerClient,
+{
+    pub fn new(network: &'a Network<C>) -> Self {
+        Self {
+            network,
+            groups: Vec::new(),
+            strategy: Strategy::default(),
+        }
+    }
+
+    /// Adds a group of containers that can only reach each other.
+    pub fn with_group(self, members: impl IntoIterator<Item = &'a Container<C>>) -> Self {
+        let mut groups = self.groups;
+        groups.push(members.into_iter().collect());
+        Self { groups, ..self }
+    }
+
+    pub fn with_strategy(self, strategy: Strategy) -> Self {
+        Self { strategy, ..self }
+    }
+
+    /// Cuts the links between the groups.
+    ///
+    /// If splitting fails, the links cut so far are restored.
+    pub async fn split(self) -> Result<Split<'a, C>, Error> {
+        let mut seen = BTreeSet::new();
+        for member in self.groups.iter().flatten() {
+            if !seen.insert(member.id()) {
+                return Err(Error::Overlap {
+                    id: member.id().to_string(),
+                });
+            }
+        }
+        let mut split = Split {
+            network: self.network,
+            groups: self.groups,
+            strategy: self.strategy,
+            group_networks: Vec::new(),
+            disconnected: Vec::new(),
+            rules: Vec::new(),
+            healed: false,
+        };
+        let result = match split.strategy {
+            Strategy::Disconnect => split.disconnect().await,
+            Strategy::Iptables => split.drop_traffic().await,
+        };
+        match result {
+            Ok(()) => Ok(split),
+            Err(err) => {
+                if let Err(restore_err) = split.restore().await {
+                    log::error!("failed to restore partial partition: {}", restore_err);
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+/// An active partition, healed when dropped.
+pub struct Split<'a, C>
+where
+    C: DockerClient,
+{
+    network: &'a Network<C>,
+    groups: Vec<Vec<&'a Container<C>>>,
+    strategy: Strategy,
+    /// Private networks of groups with their members that are connected to them.
+    group_networks: Vec<(Network<C>, Vec<&'a Container<C>>)>,
+    /// Members that were disconnected from the network.
+    disconnected: Vec<&'a Container<C>>,
+    /// Inserted iptables rules by member.
+    rules: Vec<(&'a Container<C>, Vec<String>)>,
+    healed: bool,
+}
+
+impl<'a, C> fmt::Debug for Split<'a, C>
+where
+    C: DockerClient,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Split")
+            .field("network", &self.network.name())
+            .field("groups", &ids(&self.groups))
+            .field("strategy", &self.strategy)
+            .finish()
+    }
+}
+
+impl<'a, C> Split<'a, C>
+where
+    C: DockerClient,
+{
+    async fn disconnect(&mut self) -> Result<(), Error> {
+        let client = self.network.client();
+        for (index, group) in self.groups.iter().enumerate() {
+            let name = format!("{}-partition-{}", self.network.name(), index);
+            let network = client
+                .network(&name)
+                .await
+                .map_err(|source| Error::Network {
+                    name: name.clone(),
+                    source: crate::Error::new(Operation::Network, source),
+                })?;
+            self.group_networks.push((network, Vec::new()));
+            let (network, connected) = self.group_networks.last_mut().unwrap();
+            for member in group {
+                let aliases = member
+                    .image()
+                    .network_aliases
+                    .get(self.network.name())
+                    .cloned()
+                    .unwrap_or_default();
+                client
+                    .connect_network(&name, member.id(), aliases)
+                    .await
+                    .map_err(|source| Error::Connect {
+                        id: member.id().to_string(),
+                        network: network.name().to_string(),
+                        source: member.error(Operation::Network, source),
+                    })?;
+                connected.push(*member);
+            }
+        }
+        for member in self.groups.iter().flatten() {
+            self.network
+                .disconnect(member)
+                .await
+                .map_err(|source| Error::Disconnect {
+                    id: member.id().to_string(),
+                    network: self.network.name().to_string(),
+                    source,
+                })?;
+            self.disconnected.push(*member);
+        }
+        Ok(())
+    }
+
+    async fn drop_traffic(&mut self) -> Result<(), Error> {
+        let mut addresses = Vec::new();
+        for group in &self.groups {
+            let mut group_addresses = Vec::new();
+            for member in group {
+                group_addresses.push(self.address(member).await?);
+            }
+            addresses.push(group_addresses);
+        }
+        for (index, group) in self.groups.iter().enumerate() {
+            let others = addresses
+                .iter()
+                .enumerate()
+                .filter(|(other, _)| *other != index)
+                .flat_map(|(_, addresses)| addresses);
+            for address in others {
+                for member in group {
+                    for rule in [["INPUT", "-s"], ["OUTPUT", "-d"]] {
+                        let rule = vec![
+                            rule[0].to_string(),
+                            rule[1].to_string(),
+                            address.clone(),
+                            "-j".to_string(),
+                            "DROP".to_string(),
+                        ];
+                        iptables(member, "-I", &rule).await?;
+                        self.rules.push((*member, rule));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the address of a member in the network.
+    async fn address(&self, member: &Container<C>) -> Result<String, Error> {
+        let inspect = member.inspect().await.map_err(|source| Error::Inspect {
+            id: member.id().to_string(),
+            source,
+        })?;
+        inspect
+            .network_settings
+            .and_then(|settings| settings.networks)
+            .and_then(|mut networks| networks.remove(self.network.name()))
+            .and_then(|endpoint| endpoint.ip_address)
+            .filter(|address| !address.is_empty())
+            .ok_or_else(|| Error::MissingAddress {
+                id: member.id().to_string(),
+                network: self.network.name().to_string(),
+            })
+    }
+
+    /// Restores all links that were cut, returning the first error.
+    async fn restore(&mut self) -> Result<(), Error> {
+        self.healed = true;
+        let mut result = Ok(());
+        for member in std::mem::take(&mut self.disconnected) {
+            let reconnected =
+                self.network
+                    .reconnect(member)
+                    .await
+                    .map_err(|source| Error::Connect {
+                        id: member.id().to_string(),
+                        network: self.network.name().to_string(),
+                        source,
+                    });
+            result = result.and(reconnected);
+        }
+        let client = self.network.client();
+        for (network, members) in std::mem::take(&mut self.group_networks) {
+            for member in members {
+                let disconnected = client
+                    .disconnect_network(network.name(), member.id())
+                    .await
+                    .map_err(|source| Error::Disconnect {
+                        id: member.id().to_string(),
+                        network: network.name().to_string(),
+                        source: member.error(Operation::Network, source),
+                    });
+                result = result.and(disconnected);
+            }
+            let name = network.name().to_string();
+            let removed = network
+                .rm()
+                .await
+                .map_err(|source| Error::Network { name, source });
+            result = result.and(removed);
+        }
+        for (member, rule) in std::mem::take(&mut self.rules) {
+            result = result.and(iptables(member, "-D", &rule).await);
+        }
+        result
+    }
+
+    /// Restores the links between the groups.
+    pub async fn heal(mut self) -> Result<(), Error> {
+        self.restore().await
+    }
+}
+
+impl<'a, C> Drop for Split<'a, C>
+where
+    C: DockerClient,
+{
+    fn drop(&mut self) {
+        if self.healed {
+            return;
+        }
+        if let Err(err) = rt::block_on(self.restore()) {
+            log::error!(
+                "failed to heal partition of network {}: {}",
+                self.network.name(),
+                err
+            );
+        }
+    }
+}
+
+/// Inserts or deletes an iptables rule in a container.
+async fn iptables<C: DockerClient>(
+    member: &Container<C>,
+    action: &str,
+    rule: &[String],
+) -> Result<(), Error> {
+    let cmd = ["iptables", action]
+        .into_iter()
+        .map(str::to_string)
+        .chain(rule.iter().cloned());
+    let result = member.exec(cmd).await.map_err(|source| Error::Exec {
+        id: member.id().to_string(),
+        source,
+    })?;
+    if !result.success() {
+        return Err(Error::Iptables {
+            id: member.id().to_string(),
+            rule: rule.to_vec(),
+            result,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::{Error, Partition, Strategy};
+    use crate::client::mock::MockClient;
+    use crate::client::DockerClient;
+    use crate::container::Container;
+    use crate::exec::ExecResult;
+    use crate::DockerImage;
+    use pretty_assertions::assert_eq;
+
+    async fn node(client: &MockClient, name: &str) -> Result<Container<MockClient>, crate::Error> {
+        let node = client
+            .create(
+                DockerImage::new("etcd")
+                    .with_network("cluster")
+                    .with_network_alias("cluster", name)
+                    .with_mapped_port(0, 2379),
+            )
+            .await?;
+        node.start().await?;
+        Ok(node)
+    }
+
+    async fn address(node: &Container<MockClient>) -> Result<String, crate::Error> {
+        let inspect = node.inspect().await?;
+        let mut networks = inspect.network_settings.unwrap().networks.unwrap();
+        Ok(networks.remove("cluster").unwrap().ip_address.unwrap())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn moves_groups_to_private_networks() -> color_eyre::eyre::Result<()> {
+        let client = MockClient::new();
+        let network = client.network("cluster").await?;
+        let (a, b, c) = (
+            node(&client, "a").await?,
+            node(&client, "b").await?,
+            node(&client, "c").await?,
+        );
+
+        let port = c.mapped_port_ipv4(2379).await?;
+        assert!(port.is_some());
+
+        let split = Partition::new(&network)
+            .with_group([&a, &b])
+            .with_group([&c])
+            .split()
+            .await?;
+        assert_eq!(
+            client.networks(),
+            vec!["cluster", "cluster-partition-0", "cluster-partition-1"]
+        );
+        for node in [&a, &b] {
+            assert_eq!(
+                client.container_networks(node.id()),
+                vec!["cluster-partition-0"]
+            );
+        }
+        // the isolated node stays reachable from the host
+        assert_eq!(
+            client.container_networks(c.id()),
+            vec!["cluster-partition-1"]
+        );
+        assert!(c.host().await.is_ok());
+        assert_eq!(c.mapped_port_ipv4(2379).await?, port);
+
+        split.heal().await?;
+        assert_eq!(client.networks(), vec!["cluster"]);
+        for node in [&a, &b, &c] {
+            assert_eq!(client.container_networks(node.id()), vec!["cluster"]);
+            assert!(node.host().await.is_ok());
+        }
+        assert_eq!(c.mapped_port_ipv4(2379).await?, port);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn drops_traffic_between_groups() -> color_eyre::eyre::Result<()> {
+        let client = MockClient::new();
+        let network = client.network("cluster").await?;
+        let (a, b) = (node(&client, "a").await?, node(&client, "b").await?);
+        let rules = |action: &str, address: String| {
+            [["INPUT", "-s"], ["OUTPUT", "-d"]]
+                .into_iter()
+                .map(|[chain, flag]| {
+                    ["iptables", action, chain, flag, &address, "-j", "DROP"]
+                        .into_iter()
+                        .map(str::to_string)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let split = Partition::new(&network)
+            .with_group([&a])
+            .with_group([&b])
+            .with_strategy(Strategy::Iptables)
+            .split()
+            .await?;
+        assert_eq!(client.execs(a.id()), rules("-I", address(&b).await?));
+        assert_eq!(client.execs(b.id()), rules("-I", address(&a).await?));
+        drop(split);
+        assert_eq!(
+            client.execs(a.id()),
+            [
+                rules("-I", address(&b).await?),
+                rules("-D", address(&b).await?)
+            ]
+            .concat()
+        );
+        assert_eq!(client.container_networks(a.id()), vec!["cluster"]);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_overlapping_groups_and_failed_rules() -> color_eyre::eyre::Result<()> {
+        let client = MockClient::new();
+        let network = client.network("cluster").await?;
+        let a = node(&client, "a").await?;
+        let result = Partition::new(&network)
+            .with_group([&a])
+            .with_group([&a])
+            .split()
+            .await;
+        assert!(matches!(result, Err(Error::Overlap { id }) if id == a.id()));
+
+        let client = MockClient::new().with_exec(
+            ["iptables", "-I", "INPUT", "-s", "172.18.0.3", "-j", "DROP"],
+            ExecResult {
+                exit_code: Some(4),
+                stderr: "Permission denied (you must be root)".to_string(),
+                ..Default::default()
+            },
+        );
+        let network = client.network("cluster").await?;
+        let (a, b) = (node(&client, "a").await?, node(&client, "b").await?);
+        let result = Partition::new(&network)
+            .with_group([&a])
+            .with_group([&b])
+            .with_strategy(Strategy::Iptables)
+            .split()
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::Iptables { ref id, ref result, .. }) if id == a.id() && result.exit_code == Some(4)
+        ));
+        Ok(())
+    }
+}
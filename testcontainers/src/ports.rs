@@ -1,4 +1,4 @@
-use bollard::models::{PortBinding, PortMap};
+use bollard::models::PortMap;
 use std::collections::HashMap;
 use std::net;
 
@@ -13,11 +13,41 @@ fn parse_port(port: &str) -> u16 {
 
 /// The exposed ports of a running container.
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "record", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ports {
+    #[cfg_attr(feature = "record", serde(with = "pairs"))]
     ipv4_mapping: HashMap<u16, u16>,
+    #[cfg_attr(feature = "record", serde(with = "pairs"))]
     ipv6_mapping: HashMap<u16, u16>,
 }
 
+/// Serializes port mappings as sorted pairs.
+///
+/// Maps with integer keys cannot be read back from within the internally
+/// tagged responses of a cassette, which buffer the keys as strings.
+#[cfg(feature = "record")]
+mod pairs {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::HashMap;
+
+    pub fn serialize<S: Serializer>(
+        mapping: &HashMap<u16, u16>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut pairs: Vec<_> = mapping.iter().collect();
+        pairs.sort();
+        pairs.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<u16, u16>, D::Error> {
+        Ok(Vec::<(u16, u16)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
 impl Ports {
     /// Get the mapped host IPv4 port for the given internal port
     pub fn mapped_port_ipv4(&self, internal_port: u16) -> Option<u16> {
@@ -0,0 +1,687 @@
+//! In-process TCP proxy that injects network faults between tests and containers.
+//!
+//! A [`Proxy`] listens on a local port and forwards connections to a mapped
+//! port of a container, see [`Container::proxy`](crate::Container::proxy).
+//! Named [`Toxic`]s degrade the forwarded traffic and can be added, replaced
+//! and removed while connections are open, similar to Toxiproxy.
+//! [Disabling](Proxy::set_enabled) the proxy cuts all connections and closes
+//! new ones as soon as they are accepted, which simulates a partition between
+//! the test and the container.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::time::Duration;
+//! use testcontainers_rs::client::{bollard::Client, DockerClient};
+//! use testcontainers_rs::proxy::{Direction, Toxic};
+//! use testcontainers_rs::DockerImage;
+//!
+//! let client = Client::new().await?;
+//! let container = client
+//!     .create(DockerImage::new("redis").with_mapped_port(0, 6379))
+//!     .await?;
+//! container.start().await?;
+//! let proxy = container.proxy(6379).await?;
+//! proxy.set_toxic(
+//!     "slow",
+//!     Toxic::latency(Duration::from_millis(200)).with_jitter(Duration::from_millis(50)),
+//! );
+//! proxy.set_toxic("cut", Toxic::reset(Duration::from_secs(1)).on(Direction::Upstream));
+//! let url = format!("redis://{}", proxy.local_addr());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{fmt, thread};
+
+/// How often idle connections check for toxics that close them.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+const BUFFER_SIZE: usize = 32 * 1024;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to resolve the address of the container")]
+    Client(#[source] crate::Error),
+
+    #[error("container port {0} is not mapped to a host port")]
+    PortNotMapped(u16),
+
+    #[error("failed to start proxy")]
+    Start(#[source] io::Error),
+}
+
+/// The direction of traffic that a toxic applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// From the client to the container.
+    Upstream,
+    /// From the container to the client.
+    Downstream,
+}
+
+/// A fault injected into the traffic of a [`Proxy`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToxicKind {
+    /// Delays every chunk of data by `latency`, plus or minus up to `jitter`.
+    Latency { latency: Duration, jitter: Duration },
+    /// Limits the throughput to `bytes_per_second`.
+    Bandwidth { bytes_per_second: u64 },
+    /// Drops all data, and closes the connection after `timeout` unless it is zero.
+    Timeout { timeout: Duration },
+    /// Splits data into chunks of about `average_size` bytes, varying by up to
+    /// `size_variation`, and waits `delay` between them.
+    Slicer {
+        average_size: usize,
+        size_variation: usize,
+        delay: Duration,
+    },
+    /// Resets the connection with a TCP RST after `timeout`.
+    ///
+    /// The RST is only sent on unix, other platforms close the connection.
+    Reset { timeout: Duration },
+    /// Silently drops all data while keeping the connection open.
+    Blackhole,
+}
+
+/// A [`ToxicKind`] applied to one or both directions.
+#[must_use]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Toxic {
+    pub kind: ToxicKind,
+    /// The direction the toxic applies to, both if unset.
+    pub direction: Option<Direction>,
+}
+
+impl Toxic {
+    pub fn new(kind: ToxicKind) -> Self {
+        Self {
+            kind,
+            direction: None,
+        }
+    }
+
+    pub fn latency(latency: Duration) -> Self {
+        Self::new(ToxicKind::Latency {
+            latency,
+            jitter: Duration::ZERO,
+        })
+    }
+
+    pub fn bandwidth(bytes_per_second: u64) -> Self {
+        Self::new(ToxicKind::Bandwidth { bytes_per_second })
+    }
+
+    pub fn timeout(timeout: Duration) -> Self {
+        Self::new(ToxicKind::Timeout { timeout })
+    }
+
+    pub fn slicer(average_size: usize, size_variation: usize, delay: Duration) -> Self {
+        Self::new(ToxicKind::Slicer {
+            average_size,
+            size_variation,
+            delay,
+        })
+    }
+
+    pub fn reset(timeout: Duration) -> Self {
+        Self::new(ToxicKind::Reset { timeout })
+    }
+
+    pub fn blackhole() -> Self {
+        Self::new(ToxicKind::Blackhole)
+    }
+
+    /// Sets the jitter of a latency toxic.
+    pub fn with_jitter(self, jitter: Duration) -> Self {
+        match self.kind {
+            ToxicKind::Latency { latency, .. } => Self {
+                kind: ToxicKind::Latency { latency, jitter },
+                ..self
+            },
+            _ => self,
+        }
+    }
+
+    /// Applies the toxic to traffic in `direction` only.
+    pub fn on(self, direction: Direction) -> Self {
+        Self {
+            direction: Some(direction),
+            ..self
+        }
+    }
+
+    fn applies_to(&self, direction: Direction) -> bool {
+        self.direction.is_none_or(|only| only == direction)
+    }
+}
+
+/// A small xorshift generator for jitter and slice sizes.
+struct Random(u64);
+
+impl Random {
+    fn new() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.subsec_nanos() as u64);
+        let seed = nanos ^ COUNTER.fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed);
+        Self(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a value in `center - variation ..= center + variation`, at least zero.
+    fn around(&mut self, center: u64, variation: u64) -> u64 {
+        if variation == 0 {
+            return center;
+        }
+        let offset = self.next() % (2 * variation + 1);
+        (center + offset).saturating_sub(variation)
+    }
+}
+
+/// Both sockets of a proxied connection.
+struct Connection {
+    client: TcpStream,
+    upstream: TcpStream,
+    closed: AtomicBool,
+}
+
+impl Connection {
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        let _ = self.client.shutdown(Shutdown::Both);
+        let _ = self.upstream.shutdown(Shutdown::Both);
+    }
+
+    /// Closes the connection to the client with a TCP RST once both pipes stopped.
+    ///
+    /// Only unix sets the zero linger timeout that turns the close into a RST,
+    /// elsewhere the client sees a regular close.
+    fn reset(&self) {
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+
+            let linger = libc::linger {
+                l_onoff: 1,
+                l_linger: 0,
+            };
+            // SAFETY: the descriptor is owned by `self.client` and the option
+            // value points to a properly sized linger struct.
+            unsafe {
+                libc::setsockopt(
+                    self.client.as_raw_fd(),
+                    libc::SOL_SOCKET,
+                    libc::SO_LINGER,
+                    &linger as *const libc::linger as *const libc::c_void,
+                    std::mem::size_of::<libc::linger>() as libc::socklen_t,
+                );
+            }
+        }
+        self.closed.store(true, Ordering::SeqCst);
+        let _ = self.upstream.shutdown(Shutdown::Both);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
+
+struct Shared {
+    target: SocketAddr,
+    toxics: Mutex<BTreeMap<String, Toxic>>,
+    enabled: AtomicBool,
+    stopped: AtomicBool,
+    connections: Mutex<Vec<Weak<Connection>>>,
+}
+
+impl Shared {
+    fn toxics(&self) -> MutexGuard<'_, BTreeMap<String, Toxic>> {
+        self.toxics.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn connections(&self) -> MutexGuard<'_, Vec<Weak<Connection>>> {
+        self.connections
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Returns the toxics that apply to `direction`, in the order of their names.
+    fn active(&self, direction: Direction) -> Vec<ToxicKind> {
+        self.toxics()
+            .values()
+            .filter(|toxic| toxic.applies_to(direction))
+            .map(|toxic| toxic.kind.clone())
+            .collect()
+    }
+
+    fn close_all(&self) {
+        for connection in self.connections().drain(..) {
+            if let Some(connection) = connection.upgrade() {
+                connection.close();
+            }
+        }
+    }
+}
+
+/// A TCP proxy with runtime-adjustable toxics, stopped when dropped.
+pub struct Proxy {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl fmt::Debug for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Proxy")
+            .field("addr", &self.addr)
+            .field("target", &self.shared.target)
+            .field("toxics", &*self.shared.toxics())
+            .finish()
+    }
+}
+
+impl Proxy {
+    /// Starts a proxy to `target` on an ephemeral port of `127.0.0.1`.
+    pub fn start(target: SocketAddr) -> io::Result<Self> {
+        Self::bind(SocketAddr::from(([127, 0, 0, 1], 0)), target)
+    }
+
+    /// Starts a proxy to `target` that listens on `listen`.
+    pub fn bind(listen: SocketAddr, target: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(listen)?;
+        let addr = listener.local_addr()?;
+        let shared = Arc::new(Shared {
+            target,
+            toxics: Mutex::new(BTreeMap::new()),
+            enabled: AtomicBool::new(true),
+            stopped: AtomicBool::new(false),
+            connections: Mutex::new(Vec::new()),
+        });
+        let thread = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("testcontainers-proxy".to_string())
+                .spawn(move || accept(listener, shared))?
+        };
+        log::debug!("proxying {} to {}", addr, target);
+        Ok(Self {
+            addr,
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns the address clients connect to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the address connections are forwarded to.
+    pub fn target(&self) -> SocketAddr {
+        self.shared.target
+    }
+
+    /// Adds a toxic, or replaces the toxic with the same name.
+    ///
+    /// Toxics apply to open connections from their next chunk of data on,
+    /// in the order of their names.
+    pub fn set_toxic(&self, name: impl Into<String>, toxic: Toxic) {
+        self.shared.toxics().insert(name.into(), toxic);
+    }
+
+    /// Removes a toxic, returning it if it existed.
+    pub fn remove_toxic(&self, name: &str) -> Option<Toxic> {
+        self.shared.toxics().remove(name)
+    }
+
+    /// Removes all toxics.
+    pub fn clear_toxics(&self) {
+        self.shared.toxics().clear();
+    }
+
+    /// Returns the toxics by name.
+    pub fn toxics(&self) -> BTreeMap<String, Toxic> {
+        self.shared.toxics().clone()
+    }
+
+    /// Disabling closes all connections, and new connections are accepted and
+    /// closed right away until the proxy is enabled again.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.shared.enabled.store(enabled, Ordering::SeqCst);
+        if !enabled {
+            self.shared.close_all();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.shared.enabled.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::SeqCst);
+        self.shared.close_all();
+        // wakes up the blocking accept
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn accept(listener: TcpListener, shared: Arc<Shared>) {
+    for incoming in listener.incoming() {
+        if shared.stopped.load(Ordering::SeqCst) {
+            break;
+        }
+        if !shared.enabled.load(Ordering::SeqCst) {
+            continue;
+        }
+        if let Err(err) = incoming.and_then(|client| connect(client, &shared)) {
+            log::warn!("failed to proxy connection to {}: {}", shared.target, err);
+        }
+    }
+}
+
+fn connect(client: TcpStream, shared: &Arc<Shared>) -> io::Result<()> {
+    let upstream = TcpStream::connect(shared.target)?;
+    for stream in [&client, &upstream] {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    }
+    let connection = Arc::new(Connection {
+        client,
+        upstream,
+        closed: AtomicBool::new(false),
+    });
+    {
+        let mut connections = shared.connections();
+        connections.retain(|connection| connection.strong_count() > 0);
+        connections.push(Arc::downgrade(&connection));
+    }
+    for direction in [Direction::Upstream, Direction::Downstream] {
+        let connection = connection.clone();
+        let shared = shared.clone();
+        thread::Builder::new()
+            .name("testcontainers-proxy-pipe".to_string())
+            .spawn(move || {
+                if let Err(err) = pipe(&connection, &shared, direction) {
+                    log::debug!("closing proxied connection: {}", err);
+                    connection.close();
+                }
+            })?;
+    }
+    Ok(())
+}
+
+/// Forwards data in one direction until either side closes.
+fn pipe(connection: &Connection, shared: &Shared, direction: Direction) -> io::Result<()> {
+    let (mut from, mut to) = match direction {
+        Direction::Upstream => (&connection.client, &connection.upstream),
+        Direction::Downstream => (&connection.upstream, &connection.client),
+    };
+    let mut random = Random::new();
+    let mut buffer = vec![0; BUFFER_SIZE];
+    // when a closing toxic was first seen on this connection
+    let mut closing_since: Option<Instant> = None;
+    loop {
+        if connection.is_closed() {
+            return Ok(());
+        }
+        let toxics = shared.active(direction);
+
+        let closing = toxics.iter().find_map(|toxic| match toxic {
+            ToxicKind::Timeout { timeout } if !timeout.is_zero() => Some((*timeout, false)),
+            ToxicKind::Reset { timeout } => Some((*timeout, true)),
+            _ => None,
+        });
+        match closing {
+            Some((timeout, reset)) => {
+                let since = *closing_since.get_or_insert_with(Instant::now);
+                if since.elapsed() >= timeout {
+                    if reset {
+                        connection.reset();
+                    } else {
+                        connection.close();
+                    }
+                    return Ok(());
+                }
+            }
+            None => closing_since = None,
+        }
+
+        let read = match from.read(&mut buffer) {
+            Ok(0) => {
+                // a reset must not be preceded by a regular close
+                if !connection.is_closed() {
+                    let _ = to.shutdown(Shutdown::Write);
+                }
+                return Ok(());
+            }
+            Ok(read) => read,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        // toxics may have changed while waiting for data
+        let toxics = shared.active(direction);
+        let dropped = toxics
+            .iter()
+            .any(|toxic| matches!(toxic, ToxicKind::Blackhole | ToxicKind::Timeout { .. }));
+        if dropped {
+            continue;
+        }
+        write(&mut to, &buffer[..read], &toxics, &mut random)?;
+    }
+}
+
+/// Writes a chunk of data, delayed and sliced by the toxics.
+fn write(
+    to: &mut &TcpStream,
+    data: &[u8],
+    toxics: &[ToxicKind],
+    random: &mut Random,
+) -> io::Result<()> {
+    let mut slice_size = data.len();
+    let mut slice_delay = Duration::ZERO;
+    let mut bandwidth = None;
+    for toxic in toxics {
+        match toxic {
+            ToxicKind::Latency { latency, jitter } => {
+                let millis = random.around(latency.as_millis() as u64, jitter.as_millis() as u64);
+                thread::sleep(Duration::from_millis(millis));
+            }
+            ToxicKind::Slicer {
+                average_size,
+                size_variation,
+                delay,
+            } => {
+                slice_size = random
+                    .around(*average_size as u64, *size_variation as u64)
+                    .max(1) as usize;
+                slice_delay = *delay;
+            }
+            ToxicKind::Bandwidth { bytes_per_second } => bandwidth = Some(*bytes_per_second),
+            _ => {}
+        }
+    }
+    for (index, slice) in data.chunks(slice_size).enumerate() {
+        if index > 0 {
+            thread::sleep(slice_delay);
+        }
+        if let Some(bytes_per_second) = bandwidth {
+            let seconds = slice.len() as f64 / bytes_per_second.max(1) as f64;
+            thread::sleep(Duration::from_secs_f64(seconds));
+        }
+        to.write_all(slice)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, Proxy, Toxic};
+    use color_eyre::eyre;
+    use pretty_assertions::assert_eq;
+    use std::io::{self, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::time::{Duration, Instant};
+
+    /// Starts a server that echoes everything on every connection.
+    fn echo_server() -> io::Result<TcpListener> {
+        let server = TcpListener::bind("127.0.0.1:0")?;
+        let listener = server.try_clone()?;
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                std::thread::spawn(move || {
+                    let mut reader = stream.try_clone()?;
+                    let mut writer = stream;
+                    io::copy(&mut reader, &mut writer)
+                });
+            }
+        });
+        Ok(server)
+    }
+
+    fn roundtrip(stream: &mut TcpStream, message: &[u8]) -> io::Result<Vec<u8>> {
+        stream.write_all(message)?;
+        let mut response = vec![0; message.len()];
+        stream.read_exact(&mut response)?;
+        Ok(response)
+    }
+
+    #[test]
+    fn forwards_with_latency() -> eyre::Result<()> {
+        let server = echo_server()?;
+        let proxy = Proxy::start(server.local_addr()?)?;
+        let mut stream = TcpStream::connect(proxy.local_addr())?;
+        assert_eq!(roundtrip(&mut stream, b"ping")?, b"ping");
+
+        proxy.set_toxic("latency", Toxic::latency(Duration::from_millis(500)));
+        let started = Instant::now();
+        assert_eq!(roundtrip(&mut stream, b"ping")?, b"ping");
+        // applies to both directions
+        assert!(started.elapsed() >= Duration::from_secs(1));
+
+        assert!(proxy.remove_toxic("latency").is_some());
+        let started = Instant::now();
+        assert_eq!(roundtrip(&mut stream, b"ping")?, b"ping");
+        // far below the latency, with room for slow CI machines
+        assert!(started.elapsed() < Duration::from_secs(1));
+        Ok(())
+    }
+
+    #[test]
+    fn slices_data() -> eyre::Result<()> {
+        let server = echo_server()?;
+        let proxy = Proxy::start(server.local_addr()?)?;
+        proxy.set_toxic(
+            "slicer",
+            Toxic::slicer(2, 1, Duration::from_millis(1)).on(Direction::Upstream),
+        );
+        let mut stream = TcpStream::connect(proxy.local_addr())?;
+        let message = b"sliced into small pieces";
+        assert_eq!(roundtrip(&mut stream, message)?, message.to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn blackhole_drops_data() -> eyre::Result<()> {
+        let server = echo_server()?;
+        let proxy = Proxy::start(server.local_addr()?)?;
+        proxy.set_toxic("blackhole", Toxic::blackhole().on(Direction::Upstream));
+        let mut stream = TcpStream::connect(proxy.local_addr())?;
+        stream.set_read_timeout(Some(Duration::from_millis(100)))?;
+        stream.write_all(b"ping")?;
+        let mut response = [0; 4];
+        let err = stream.read(&mut response).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn timeout_and_reset_close_connections() -> eyre::Result<()> {
+        let server = echo_server()?;
+        let proxy = Proxy::start(server.local_addr()?)?;
+        proxy.set_toxic("timeout", Toxic::timeout(Duration::from_millis(50)));
+        let mut stream = TcpStream::connect(proxy.local_addr())?;
+        let mut response = Vec::new();
+        assert_eq!(stream.read_to_end(&mut response)?, 0);
+
+        proxy.clear_toxics();
+        proxy.set_toxic("reset", Toxic::reset(Duration::ZERO));
+        let mut stream = TcpStream::connect(proxy.local_addr())?;
+        let err = stream.read_to_end(&mut response).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+        Ok(())
+    }
+
+    #[test]
+    fn disabling_cuts_connections() -> eyre::Result<()> {
+        let server = echo_server()?;
+        let proxy = Proxy::start(server.local_addr()?)?;
+        let mut stream = TcpStream::connect(proxy.local_addr())?;
+        assert_eq!(roundtrip(&mut stream, b"ping")?, b"ping");
+
+        proxy.set_enabled(false);
+        let mut response = Vec::new();
+        assert_eq!(stream.read_to_end(&mut response)?, 0);
+        let mut closed = TcpStream::connect(proxy.local_addr())?;
+        assert_eq!(closed.read_to_end(&mut response)?, 0);
+
+        proxy.set_enabled(true);
+        let mut stream = TcpStream::connect(proxy.local_addr())?;
+        assert_eq!(roundtrip(&mut stream, b"ping")?, b"ping");
+        Ok(())
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn proxies_mapped_port_of_container() -> eyre::Result<()> {
+        use super::Error;
+        use crate::client::mock::MockClient;
+        use crate::client::DockerClient;
+        use crate::DockerImage;
+
+        let server = echo_server()?;
+        let port = server.local_addr()?.port();
+        let client = MockClient::new();
+        let container = client
+            .create(DockerImage::new("nginx").with_mapped_port(port, 80))
+            .await?;
+        container.start().await?;
+
+        let proxy = container.proxy(80).await?;
+        assert_eq!(proxy.target(), server.local_addr()?);
+        let mut stream = TcpStream::connect(proxy.local_addr())?;
+        assert_eq!(roundtrip(&mut stream, b"ping")?, b"ping");
+        assert!(matches!(
+            container.proxy(443).await,
+            Err(Error::PortNotMapped(443))
+        ));
+        Ok(())
+    }
+}
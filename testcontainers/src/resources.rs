@@ -0,0 +1,136 @@
+//! Resource limits of containers.
+//!
+//! The limits are set with the `with_*` builders of [`DockerImage`](crate::DockerImage)
+//! and map to the flags of `docker run` with the same names.
+
+use bollard::models::{HostConfig, ResourcesBlkioWeightDevice, ResourcesUlimits};
+use std::collections::BTreeMap;
+
+/// A soft and hard limit on a process resource, e.g. `nofile`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Ulimit {
+    pub name: String,
+    pub soft: i64,
+    pub hard: i64,
+}
+
+/// The resource limits of a container, unset limits use the docker defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Resources {
+    /// Memory limit in bytes.
+    pub memory: Option<i64>,
+    /// Soft memory limit in bytes.
+    pub memory_reservation: Option<i64>,
+    /// Limit of memory plus swap in bytes, `-1` for unlimited swap.
+    pub memory_swap: Option<i64>,
+    /// CPU limit in units of 10<sup>-9</sup> CPUs.
+    pub nano_cpus: Option<i64>,
+    /// CPU CFS period in microseconds.
+    pub cpu_period: Option<i64>,
+    /// CPU CFS quota in microseconds per period.
+    pub cpu_quota: Option<i64>,
+    /// Relative CPU weight.
+    pub cpu_shares: Option<i64>,
+    /// CPUs the container may run on, e.g. `0-2` or `0,1`.
+    pub cpuset_cpus: Option<String>,
+    /// Memory nodes the container may use.
+    pub cpuset_mems: Option<String>,
+    /// Maximum number of processes, `-1` for unlimited.
+    pub pids_limit: Option<i64>,
+    pub ulimits: Vec<Ulimit>,
+    /// Relative block IO weight between 10 and 1000.
+    pub blkio_weight: Option<u16>,
+    /// Relative block IO weight per device path.
+    pub blkio_weight_devices: BTreeMap<String, u16>,
+    /// Disables the OOM killer, only allowed together with a memory limit.
+    pub oom_kill_disable: Option<bool>,
+    /// Preference of the OOM killer between -1000 and 1000.
+    pub oom_score_adj: Option<i64>,
+}
+
+impl Resources {
+    /// Sets the limits in the host configuration of a container.
+    pub(crate) fn apply(&self, host_config: &mut HostConfig) {
+        host_config.memory = self.memory;
+        host_config.memory_reservation = self.memory_reservation;
+        host_config.memory_swap = self.memory_swap;
+        host_config.nano_cpus = self.nano_cpus;
+        host_config.cpu_period = self.cpu_period;
+        host_config.cpu_quota = self.cpu_quota;
+        host_config.cpu_shares = self.cpu_shares;
+        host_config.cpuset_cpus = self.cpuset_cpus.clone();
+        host_config.cpuset_mems = self.cpuset_mems.clone();
+        host_config.pids_limit = self.pids_limit;
+        host_config.ulimits = (!self.ulimits.is_empty()).then(|| {
+            self.ulimits
+                .iter()
+                .map(|ulimit| ResourcesUlimits {
+                    name: Some(ulimit.name.clone()),
+                    soft: Some(ulimit.soft),
+                    hard: Some(ulimit.hard),
+                })
+                .collect()
+        });
+        host_config.blkio_weight = self.blkio_weight;
+        host_config.blkio_weight_device = (!self.blkio_weight_devices.is_empty()).then(|| {
+            self.blkio_weight_devices
+                .iter()
+                .map(|(path, weight)| ResourcesBlkioWeightDevice {
+                    path: Some(path.clone()),
+                    weight: Some(usize::from(*weight)),
+                })
+                .collect()
+        });
+        host_config.oom_kill_disable = self.oom_kill_disable;
+        host_config.oom_score_adj = self.oom_score_adj;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::bollard::container_config;
+    use crate::DockerImage;
+    use bollard::models::{HostConfig, ResourcesBlkioWeightDevice, ResourcesUlimits};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn limits_in_host_config() {
+        let image = DockerImage::new("postgres")
+            .with_memory_limit(256 << 20)
+            .with_memory_swap(512 << 20)
+            .with_cpus(1.5)
+            .with_cpu_shares(512)
+            .with_cpuset_cpus("0,1")
+            .with_pids_limit(100)
+            .with_ulimit("nofile", 1024, 2048)
+            .with_blkio_weight(300)
+            .with_blkio_device_weight("/dev/sda", 200)
+            .with_oom_kill_disable(true)
+            .with_oom_score_adj(500);
+        let host_config = container_config(&image).host_config.unwrap_or_default();
+        assert_eq!(
+            host_config,
+            HostConfig {
+                memory: Some(256 << 20),
+                memory_swap: Some(512 << 20),
+                nano_cpus: Some(1_500_000_000),
+                cpu_shares: Some(512),
+                cpuset_cpus: Some("0,1".to_string()),
+                pids_limit: Some(100),
+                ulimits: Some(vec![ResourcesUlimits {
+                    name: Some("nofile".to_string()),
+                    soft: Some(1024),
+                    hard: Some(2048),
+                }]),
+                blkio_weight: Some(300),
+                blkio_weight_device: Some(vec![ResourcesBlkioWeightDevice {
+                    path: Some("/dev/sda".to_string()),
+                    weight: Some(200),
+                }]),
+                oom_kill_disable: Some(true),
+                oom_score_adj: Some(500),
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Reuse of containers across test runs.
+//!
+//! Containers of images configured with [`DockerImage::with_reuse`](crate::DockerImage::with_reuse)
+//! are labeled with a hash of their full configuration and are not removed when dropped.
+//! The next run looks up a running container with the same hash and uses it
+//! instead of creating a new one.
+//!
+//! Reuse must be enabled globally, either by setting the `TESTCONTAINERS_REUSE_ENABLE`
+//! environment variable to `true` or by adding `testcontainers.reuse.enable=true`
+//! to `~/.testcontainers.properties`.
+//! The environment variable takes precedence, so CI can force fresh containers
+//! with `TESTCONTAINERS_REUSE_ENABLE=false`.
+
+use super::image::DockerImage;
+use bollard::container::Config;
+use std::path::PathBuf;
+use std::{env, fs};
+
+/// Label holding the hash of the configuration of a reusable container.
+pub const HASH_LABEL: &str = "org.testcontainers.hash";
+
+/// Environment variable that enables or disables reuse.
+pub const ENABLE_ENV: &str = "TESTCONTAINERS_REUSE_ENABLE";
+
+/// Property in `~/.testcontainers.properties` that enables reuse.
+pub const ENABLE_PROPERTY: &str = "testcontainers.reuse.enable";
+
+fn parse_flag(value: &str) -> bool {
+    matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "true" | "1" | "yes" | "on"
+    )
+}
+
+fn properties_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".testcontainers.properties"))
+}
+
+/// Looks up the reuse property in the contents of a properties file.
+fn from_properties(contents: &str) -> Option<bool> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#') && !line.starts_with('!'))
+        .filter_map(|line| line.split_once(['=', ':']))
+        .filter(|(key, _)| key.trim() == ENABLE_PROPERTY)
+        .map(|(_, value)| parse_flag(value))
+        .next_back()
+}
+
+/// Returns whether containers may be reused across test runs.
+pub fn enabled() -> bool {
+    if let Ok(value) = env::var(ENABLE_ENV) {
+        return parse_flag(&value);
+    }
+    properties_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|contents| from_properties(&contents))
+        .unwrap_or(false)
+}
+
+/// 64-bit FNV-1a, whose output is fixed unlike that of the std hashers.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Hashes a container configuration.
+///
+/// The configuration is hashed in its JSON form, whose object keys are sorted,
+/// so the hash does not depend on the iteration order of the maps in `config`.
+/// The hash is stored in a label and compared across runs, so it must not
+/// change between toolchains.
+pub fn hash(config: &Config<String>) -> String {
+    let json = serde_json::to_value(config)
+        .map(|value| value.to_string())
+        .unwrap_or_default();
+    format!("{:016x}", fnv1a(json.as_bytes()))
+}
+
+/// Hashes the container configuration of `image`.
+///
+/// The host directories of [`DockerImage::with_temp_dir`] get a new random path
+/// for every container, so their mounts are left out.
+pub fn image_hash(image: &DockerImage) -> String {
+    let mut config = crate::client::bollard::container_config(image);
+    if let Some(mounts) = config
+        .host_config
+        .as_mut()
+        .and_then(|host_config| host_config.mounts.as_mut())
+    {
+        mounts.retain(
+            |mount| !matches!(&mount.target, Some(target) if image.temp_dirs.contains(target)),
+        );
+    }
+    hash(&config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fnv1a, from_properties, hash, image_hash};
+    use crate::client::bollard::container_config;
+    use crate::DockerImage;
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    #[test]
+    fn hash_is_stable() {
+        let image = || {
+            DockerImage::new("postgres")
+                .with_tag("15")
+                .with_mapped_port(0, 5432)
+                .with_mapped_port(0, 5433)
+                .with_volume("/data", "/var/lib/postgresql/data")
+        };
+        assert_eq!(
+            hash(&container_config(&image())),
+            hash(&container_config(&image()))
+        );
+        assert_ne!(
+            hash(&container_config(&image())),
+            hash(&container_config(&image().with_tag("14")))
+        );
+    }
+
+    #[test]
+    fn temp_dirs_do_not_change_hash() -> std::io::Result<()> {
+        let image = || DockerImage::new("redis").with_temp_dir("/data");
+        let (mut first, mut second) = (image(), image());
+        let _dirs = (
+            crate::mounts::create_temp_dirs(&mut first)?,
+            crate::mounts::create_temp_dirs(&mut second)?,
+        );
+        assert_ne!(first.mounts, second.mounts);
+        assert_eq!(image_hash(&first), image_hash(&second));
+        Ok(())
+    }
+
+    #[test]
+    fn hash_does_not_depend_on_toolchain() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn parse_properties() {
+        assert_eq!(from_properties(""), None);
+        assert_eq!(
+            from_properties("testcontainers.reuse.enable=true\n"),
+            Some(true)
+        );
+        assert_eq!(
+            from_properties("# comment\ntestcontainers.reuse.enable = false"),
+            Some(false)
+        );
+        assert_eq!(from_properties("#testcontainers.reuse.enable=true"), None);
+    }
+}
@@ -0,0 +1,151 @@
+//! Executor-agnostic timer, spawn and blocking primitives.
+//!
+//! The core of the crate (containers, log streams and wait conditions) only
+//! uses these functions, so it runs on whatever executor the caller uses.
+//! Within a tokio runtime they use tokio. Elsewhere, the `async-std` and `smol`
+//! features select the native implementation of the respective runtime, in
+//! this order of precedence, and without either of them timers and spawned
+//! tasks run on plain threads.
+//!
+//! The [`bollard`](crate::client::bollard) client talks to the docker daemon
+//! through `hyper`, which needs a tokio reactor on every runtime, so the
+//! `async-std` and `smol` features require the `tokio` feature.
+//! `async-std` enables its `tokio1` compatibility, which gives its tasks access
+//! to a tokio reactor. `smol` has no such option: run tests that use the bollard
+//! client in a tokio runtime, or wrap them with a compatibility layer such as
+//! `async-compat`.
+
+use std::future::Future;
+use std::time::Duration;
+
+/// Waits on a plain thread, for callers outside of any runtime.
+#[allow(dead_code)]
+async fn thread_sleep(duration: Duration) {
+    let (tx, rx) = futures::channel::oneshot::channel::<()>();
+    std::thread::spawn(move || {
+        std::thread::sleep(duration);
+        let _ = tx.send(());
+    });
+    let _ = rx.await;
+}
+
+/// Waits until `duration` has elapsed.
+pub async fn sleep(duration: Duration) {
+    #[cfg(feature = "tokio")]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        tokio::time::sleep(duration).await;
+        return;
+    }
+    #[cfg(feature = "async-std")]
+    {
+        async_std::task::sleep(duration).await;
+    }
+    #[cfg(all(not(feature = "async-std"), feature = "smol"))]
+    {
+        smol::Timer::after(duration).await;
+    }
+    #[cfg(not(any(feature = "async-std", feature = "smol")))]
+    {
+        thread_sleep(duration).await;
+    }
+}
+
+/// Spawns a detached background task.
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    #[cfg(feature = "tokio")]
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        handle.spawn(future);
+        return;
+    }
+    #[cfg(feature = "async-std")]
+    {
+        async_std::task::spawn(future);
+    }
+    #[cfg(all(not(feature = "async-std"), feature = "smol"))]
+    {
+        smol::spawn(future).detach();
+    }
+    #[cfg(not(any(feature = "async-std", feature = "smol")))]
+    {
+        std::thread::spawn(move || futures::executor::block_on(future));
+    }
+}
+
+/// Blocks the current thread until `future` completes.
+///
+/// Used where async cleanup must happen synchronously, e.g. in `Drop`.
+/// On a multi-threaded tokio runtime, the worker is moved out of the way with
+/// `block_in_place` so that the runtime keeps driving the I/O of the future.
+/// A current-thread runtime cannot make progress while its only thread is
+/// blocked, so the future is driven by a runtime of its own on another thread.
+pub fn block_on<F>(future: F) -> F::Output
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    #[cfg(feature = "tokio")]
+    {
+        use tokio::runtime::{Builder, Handle, RuntimeFlavor};
+
+        if let Ok(handle) = Handle::try_current() {
+            if handle.runtime_flavor() == RuntimeFlavor::MultiThread {
+                return tokio::task::block_in_place(|| handle.block_on(future));
+            }
+            return std::thread::scope(|scope| {
+                let thread =
+                    scope.spawn(
+                        || match Builder::new_current_thread().enable_all().build() {
+                            Ok(runtime) => runtime.block_on(future),
+                            Err(err) => {
+                                log::warn!("failed to build runtime for blocking cleanup: {}", err);
+                                futures::executor::block_on(future)
+                            }
+                        },
+                    );
+                thread
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            });
+        }
+    }
+    futures::executor::block_on(future)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn sleep_without_runtime() {
+        let start = Instant::now();
+        super::block_on(super::sleep(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn block_on_current_thread_runtime() {
+        // the timer needs a driver, which the blocked runtime cannot run
+        super::block_on(async { tokio::time::sleep(Duration::from_millis(20)).await });
+    }
+
+    #[test]
+    fn spawn_without_runtime() {
+        let done = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = futures::channel::oneshot::channel();
+        {
+            let done = done.clone();
+            super::spawn(async move {
+                done.store(true, Ordering::SeqCst);
+                let _ = tx.send(());
+            });
+        }
+        super::block_on(rx).unwrap();
+        assert!(done.load(Ordering::SeqCst));
+    }
+}
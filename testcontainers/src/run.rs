@@ -0,0 +1,144 @@
+//! Containers used as tools, e.g. for migrations, code generation or linting.
+//!
+//! [`DockerClient::run_to_completion`] and [`DockerImage::output`] work like
+//! [`std::process::Command::output`]: they create and start a container,
+//! wait for it to exit and return its exit status and output.
+//! The container is removed afterwards, and killed if it does not exit in time.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use testcontainers_rs::client::bollard::Client;
+//! use testcontainers_rs::DockerImage;
+//!
+//! let client = Client::new().await?;
+//! let output = DockerImage::new("flyway/flyway")
+//!     .with_cmd(["migrate"])
+//!     .output(&client)
+//!     .await?;
+//! assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+//! # Ok(())
+//! # }
+//! ```
+
+use super::client::DockerClient;
+use super::container::ExitStatus;
+use super::image::DockerImage;
+use super::logs::consumer::Logger;
+use super::logs::{LogFrame, Output as Stream};
+use super::rt;
+use futures::future::{self, Either};
+use std::io;
+use std::time::Duration;
+
+/// How long a container may run unless [`DockerImage::with_run_timeout`] is set.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to run container")]
+    Container(#[source] crate::Error),
+
+    #[error("failed to read container logs")]
+    Logs(#[source] io::Error),
+
+    #[error("container did not exit within {0:?}")]
+    Timeout(Duration),
+}
+
+/// The output of a finished container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    pub status: ExitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl Output {
+    fn from_frames(status: ExitStatus, frames: Vec<LogFrame>) -> Self {
+        let mut output = Self {
+            status,
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        };
+        for frame in frames {
+            match frame.output {
+                Stream::Stdout => output.stdout.extend_from_slice(&frame.bytes),
+                Stream::Stderr => output.stderr.extend_from_slice(&frame.bytes),
+            }
+        }
+        output
+    }
+}
+
+/// Runs a container created from `image` until it exits.
+///
+/// On errors the container is removed when it is dropped.
+pub(crate) async fn run<C: DockerClient>(
+    client: &C,
+    image: DockerImage<C>,
+) -> Result<Output, Error> {
+    let timeout = image.run_timeout.unwrap_or(DEFAULT_TIMEOUT);
+    let descriptor = image.descriptor();
+    let container = client.create(image).await.map_err(Error::Container)?;
+    container.start().await.map_err(Error::Container)?;
+    let follower = container.follow_logs(Logger::new(log::Level::Debug).with_prefix(descriptor));
+
+    let status =
+        match future::select(Box::pin(container.wait()), Box::pin(rt::sleep(timeout))).await {
+            Either::Left((status, _)) => status.map_err(Error::Container)?,
+            Either::Right(_) => {
+                follower.stop();
+                if let Err(err) = container.kill("SIGKILL").await {
+                    log::warn!(
+                        "failed to kill docker container {}: {}",
+                        container.id(),
+                        err
+                    );
+                }
+                return Err(Error::Timeout(timeout));
+            }
+        };
+    follower.stop();
+
+    let frames = container.logs_snapshot().await.map_err(Error::Logs)?;
+    container.rm().await.map_err(Error::Container)?;
+    Ok(Output::from_frames(status, frames))
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::Error;
+    use crate::client::mock::MockClient;
+    use crate::client::DockerClient;
+    use crate::DockerImage;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn collects_output_and_removes_container() -> color_eyre::eyre::Result<()> {
+        let client = MockClient::new()
+            .with_stdout("flyway/flyway", ["migrated 3 schemas"])
+            .with_stderr("flyway/flyway", ["no baseline"])
+            .with_exit_code("flyway/flyway", 1);
+        let output = DockerImage::new("flyway/flyway")
+            .with_cmd(["migrate"])
+            .output(&client)
+            .await?;
+        assert!(!output.status.success());
+        assert_eq!(output.status.code, 1);
+        assert_eq!(String::from_utf8(output.stdout)?, "migrated 3 schemas");
+        assert_eq!(String::from_utf8(output.stderr)?, "no baseline");
+        assert_eq!(client.container_ids(), Vec::<String>::new());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn kills_container_after_timeout() -> color_eyre::eyre::Result<()> {
+        let client = MockClient::new();
+        let image = DockerImage::new("busybox").with_run_timeout(Duration::from_millis(50));
+        let err = client.run_to_completion(image).await.unwrap_err();
+        assert!(matches!(err, Error::Timeout(timeout) if timeout == Duration::from_millis(50)));
+        assert_eq!(client.container_ids(), Vec::<String>::new());
+        Ok(())
+    }
+}
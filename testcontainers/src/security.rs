@@ -0,0 +1,95 @@
+//! Security settings of containers.
+//!
+//! The settings are set with the `with_*` builders of [`DockerImage`](crate::DockerImage)
+//! and map to the flags of `docker run` with the same names.
+
+use bollard::models::HostConfig;
+
+/// The security settings of a container, unset settings use the docker defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Security {
+    /// Linux capabilities to add, e.g. `NET_ADMIN`.
+    pub cap_add: Vec<String>,
+    /// Linux capabilities to drop, e.g. `ALL`.
+    pub cap_drop: Vec<String>,
+    /// User and optionally group to run as, e.g. `1000:1000` or `postgres`.
+    pub user: Option<String>,
+    /// Additional groups of the user.
+    pub group_add: Vec<String>,
+    /// Mounts the root filesystem read-only.
+    pub read_only_rootfs: bool,
+    /// Options such as `no-new-privileges`, `apparmor=<profile>` or `seccomp=<json>`.
+    pub security_opt: Vec<String>,
+    /// User namespace mode, e.g. `host`.
+    pub userns_mode: Option<String>,
+    /// PID namespace mode, e.g. `host` or `container:<id>`.
+    pub pid_mode: Option<String>,
+    /// IPC namespace mode, e.g. `private`, `shareable` or `container:<id>`.
+    pub ipc_mode: Option<String>,
+}
+
+impl Security {
+    /// Sets the options in the host configuration of a container.
+    pub(crate) fn apply(&self, host_config: &mut HostConfig) {
+        let list = |values: &Vec<String>| (!values.is_empty()).then(|| values.clone());
+        host_config.cap_add = list(&self.cap_add);
+        host_config.cap_drop = list(&self.cap_drop);
+        host_config.group_add = list(&self.group_add);
+        host_config.readonly_rootfs = self.read_only_rootfs.then_some(true);
+        host_config.security_opt = list(&self.security_opt);
+        host_config.userns_mode = self.userns_mode.clone();
+        host_config.pid_mode = self.pid_mode.clone();
+        host_config.ipc_mode = self.ipc_mode.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::bollard::container_config;
+    use crate::DockerImage;
+    use bollard::models::HostConfig;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn options_in_config() {
+        let image = DockerImage::new("nginx")
+            .with_cap_drop("ALL")
+            .with_cap_add("NET_BIND_SERVICE")
+            .with_user("101:101")
+            .with_group_add("audio")
+            .with_read_only_rootfs(true)
+            .with_no_new_privileges()
+            .with_security_opt("apparmor=docker-default")
+            .with_userns_mode("host")
+            .with_pid_mode("container:c0ffee")
+            .with_ipc_mode("shareable");
+        let config = container_config(&image);
+        assert_eq!(config.user.as_deref(), Some("101:101"));
+        assert_eq!(
+            config.host_config.unwrap_or_default(),
+            HostConfig {
+                cap_add: Some(vec!["NET_BIND_SERVICE".to_string()]),
+                cap_drop: Some(vec!["ALL".to_string()]),
+                group_add: Some(vec!["audio".to_string()]),
+                readonly_rootfs: Some(true),
+                security_opt: Some(vec![
+                    "no-new-privileges".to_string(),
+                    "apparmor=docker-default".to_string()
+                ]),
+                userns_mode: Some("host".to_string()),
+                pid_mode: Some("container:c0ffee".to_string()),
+                ipc_mode: Some("shareable".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn privileged() {
+        let config = container_config(&DockerImage::new("docker").with_privileged(true));
+        assert_eq!(
+            config.host_config.unwrap_or_default().privileged,
+            Some(true)
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! Containers shared by all tests of a test binary.
+//!
+//! A [`SharedContainer`] is meant to live in a `static` and starts its container
+//! the first time a test asks for it.
+//! Every test gets a reference counted [`SharedHandle`] to the same container,
+//! and the container is removed when the process exits.
+//!
+//! ```no_run
+//! use lazy_static::lazy_static;
+//! use testcontainers_rs::client::{bollard::Client, DockerClient};
+//! use testcontainers_rs::{shared::SharedContainer, DockerImage};
+//!
+//! lazy_static! {
+//!     static ref POSTGRES: SharedContainer<Client> = SharedContainer::new(|| async {
+//!         let client = Client::new().await?;
+//!         let container = client
+//!             .create(DockerImage::new("postgres").with_mapped_port(0, 5432))
+//!             .await?;
+//!         container.start().await?;
+//!         Ok::<_, Box<dyn std::error::Error + Send + Sync>>(container)
+//!     });
+//! }
+//!
+//! #[tokio::test]
+//! async fn query() {
+//!     let postgres = POSTGRES.get().await.unwrap();
+//!     let port = postgres.ports().mapped_port_ipv4(5432);
+//! }
+//! ```
+//!
+//! Tests usually run on separate tokio runtimes, which are shut down when the
+//! test ends.
+//! The container is therefore started, inspected and removed on a dedicated
+//! runtime owned by this module, and the handle caches the host and port
+//! mappings so that tests can connect without issuing docker requests.
+
+use super::client::DockerClient;
+use super::container::Container;
+use super::ports::Ports;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use lazy_static::lazy_static;
+use std::future::Future;
+use std::sync::{Arc, Mutex, Once};
+use std::{fmt, net, ops};
+use tokio::sync::OnceCell;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+type Start<C> = Box<dyn Fn() -> BoxFuture<'static, Result<Container<C>, BoxError>> + Send + Sync>;
+pub(crate) type Cleanup = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+lazy_static! {
+    pub(crate) static ref RUNTIME: tokio::runtime::Runtime =
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("testcontainers-shared")
+            .enable_all()
+            .build()
+            .expect("failed to build runtime for shared containers");
+    static ref CLEANUPS: Mutex<Vec<Cleanup>> = Mutex::new(Vec::new());
+}
+
+static REGISTER_CLEANUP: Once = Once::new();
+
+/// Removes all shared containers and snapshot images.
+///
+/// Registered with `atexit` so that it runs after the test harness returns.
+extern "C" fn cleanup_at_exit() {
+    cleanup();
+}
+
+fn cleanup() {
+    let cleanups: Vec<Cleanup> =
+        std::mem::take(&mut *CLEANUPS.lock().unwrap_or_else(|err| err.into_inner()));
+    if cleanups.is_empty() {
+        return;
+    }
+    RUNTIME.block_on(futures::future::join_all(
+        cleanups.into_iter().map(|cleanup| cleanup()),
+    ));
+}
+
+pub(crate) fn register_cleanup(cleanup: Cleanup) {
+    REGISTER_CLEANUP.call_once(|| {
+        // SAFETY: `cleanup_at_exit` is a plain function that does not unwind
+        // across the FFI boundary since `cleanup` only logs errors.
+        if unsafe { libc::atexit(cleanup_at_exit) } != 0 {
+            log::warn!("failed to register removal of shared containers and snapshots at exit");
+        }
+    });
+    CLEANUPS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .push(cleanup);
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to start shared container")]
+    Start(#[source] BoxError),
+
+    #[error("failed to inspect shared container {id}")]
+    Inspect {
+        id: String,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("shared container startup panicked")]
+    Panicked(#[source] tokio::task::JoinError),
+}
+
+struct Shared<C>
+where
+    C: DockerClient,
+{
+    container: Container<C>,
+    host: net::IpAddr,
+    ports: Ports,
+}
+
+/// A reference counted handle to a started [`SharedContainer`].
+pub struct SharedHandle<C>
+where
+    C: DockerClient,
+{
+    shared: Arc<Shared<C>>,
+}
+
+impl<C> Clone for SharedHandle<C>
+where
+    C: DockerClient,
+{
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<C> SharedHandle<C>
+where
+    C: DockerClient,
+{
+    /// Returns the host IP address of the container, as seen when it was started.
+    pub fn host(&self) -> net::IpAddr {
+        self.shared.host
+    }
+
+    /// Returns the port mappings of the container, as seen when it was started.
+    pub fn ports(&self) -> &Ports {
+        &self.shared.ports
+    }
+}
+
+impl<C> ops::Deref for SharedHandle<C>
+where
+    C: DockerClient,
+{
+    type Target = Container<C>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.shared.container
+    }
+}
+
+impl<C> fmt::Debug for SharedHandle<C>
+where
+    C: DockerClient,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedHandle")
+            .field("container", &self.shared.container)
+            .field("host", &self.shared.host)
+            .finish()
+    }
+}
+
+/// A container that is started on first use and shared by all tests of a binary.
+pub struct SharedContainer<C>
+where
+    C: DockerClient,
+{
+    start: Start<C>,
+    cell: OnceCell<Arc<Shared<C>>>,
+}
+
+impl<C> fmt::Debug for SharedContainer<C>
+where
+    C: DockerClient,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedContainer")
+            .field("started", &self.cell.initialized())
+            .finish()
+    }
+}
+
+impl<C> SharedContainer<C>
+where
+    C: DockerClient + Send + Sync + 'static,
+{
+    /// Creates a shared container that is created and started by `start` on first use.
+    ///
+    /// `start` runs again on the next use if it fails.
+    pub fn new<F, Fut, E>(start: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Container<C>, E>> + Send + 'static,
+        E: Into<BoxError>,
+    {
+        let start: Start<C> = Box::new(move || {
+            let started = start();
+            async move { started.await.map_err(Into::into) }.boxed()
+        });
+        Self {
+            start,
+            cell: OnceCell::new(),
+        }
+    }
+
+    async fn start(&self) -> Result<Arc<Shared<C>>, Error> {
+        let started = (self.start)();
+        let shared = RUNTIME
+            .spawn(async move {
+                let container = started.await.map_err(Error::Start)?;
+                let inspect_err = |source: crate::Error| Error::Inspect {
+                    id: container.id().to_string(),
+                    source: Box::new(source),
+                };
+                let host = container.host().await.map_err(inspect_err)?;
+                let ports = container.ports().await.map_err(inspect_err)?;
+                Ok::<_, Error>(Arc::new(Shared {
+                    container,
+                    host,
+                    ports,
+                }))
+            })
+            .await
+            .map_err(Error::Panicked)??;
+
+        log::debug!("started shared container {}", shared.container.id());
+        let registered = shared.clone();
+        register_cleanup(Box::new(move || {
+            async move {
+                log::debug!("removing shared container {}", registered.container.id());
+                registered.container.drop_async().await;
+            }
+            .boxed()
+        }));
+        Ok(shared)
+    }
+
+    /// Returns a handle to the container, starting it if this is the first use.
+    ///
+    /// Concurrent callers wait for the same startup.
+    pub async fn get(&self) -> Result<SharedHandle<C>, Error> {
+        let shared = self.cell.get_or_try_init(|| self.start()).await?;
+        Ok(SharedHandle {
+            shared: shared.clone(),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::{cleanup, SharedContainer};
+    use crate::client::mock::{Error, MockClient, Operation, Status};
+    use crate::client::DockerClient;
+    use crate::DockerImage;
+    use color_eyre::eyre;
+    use pretty_assertions::assert_eq;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn start_once_across_runtimes() -> eyre::Result<()> {
+        let client = MockClient::new();
+        let starts = Arc::new(AtomicUsize::new(0));
+        let shared = {
+            let client = client.clone();
+            let starts = starts.clone();
+            Arc::new(SharedContainer::new(move || {
+                let client = client.clone();
+                starts.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    let container = client
+                        .create(DockerImage::new("postgres").with_mapped_port(0, 5432))
+                        .await?;
+                    container.start().await?;
+                    Ok::<_, crate::Error>(container)
+                }
+            }))
+        };
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                std::thread::spawn(move || {
+                    let runtime = tokio::runtime::Builder::new_current_thread()
+                        .build()
+                        .unwrap();
+                    runtime.block_on(async {
+                        let handle = shared.get().await.unwrap();
+                        (
+                            handle.id().to_string(),
+                            handle.ports().mapped_port_ipv4(5432),
+                        )
+                    })
+                })
+            })
+            .collect();
+        let results: Vec<_> = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+
+        assert_eq!(starts.load(Ordering::SeqCst), 1);
+        let (id, port) = results[0].clone();
+        assert!(port.is_some());
+        assert!(results.iter().all(|result| result == &(id.clone(), port)));
+        assert_eq!(client.status(&id), Some(Status::Running));
+
+        cleanup();
+        assert_eq!(client.status(&id), None);
+
+        // the container is not removed again when the last handle is dropped
+        client.inject_failure(Operation::Remove, "removed twice");
+        drop(shared);
+        let err = super::RUNTIME.block_on(client.rm(&id)).unwrap_err();
+        assert!(matches!(err, Error::Injected { .. }));
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+//! Snapshots of seeded containers, committed once and copied for every test.
+//!
+//! Seeding a database for each test is slow.
+//! A [`Snapshot`] starts a container, seeds it once and commits it to an image
+//! the first time a test asks for a copy.
+//! Every test then gets a fresh container created from the committed image,
+//! and the image is removed when the process exits.
+//!
+//! ```no_run
+//! use futures::FutureExt;
+//! use lazy_static::lazy_static;
+//! use testcontainers_rs::client::bollard::Client;
+//! use testcontainers_rs::{snapshot::Snapshot, DockerImage};
+//!
+//! lazy_static! {
+//!     static ref SEEDED: Snapshot<Client> = Snapshot::new(
+//!         "postgres-seeded",
+//!         || {
+//!             DockerImage::new("postgres")
+//!                 .with_env_var("POSTGRES_PASSWORD", "postgres")
+//!                 .with_env_var("PGDATA", "/pgdata")
+//!                 .with_mapped_port(0, 5432)
+//!         },
+//!         |container| {
+//!             async move {
+//!                 container.exec(["psql", "-U", "postgres", "-f", "/seed.sql"]).await?;
+//!                 Ok(())
+//!             }
+//!             .boxed()
+//!         },
+//!     );
+//! }
+//!
+//! #[tokio::test]
+//! async fn query() {
+//!     let client = Client::new().await.unwrap();
+//!     let postgres = SEEDED.spawn(&client).await.unwrap();
+//! }
+//! ```
+//!
+//! Data in volumes is not part of a committed image, so the seeded data must be
+//! written outside of the volumes declared by the image, e.g. by moving `PGDATA`.
+//!
+//! Like [shared containers](crate::shared), the snapshot is prepared and removed
+//! on a dedicated runtime, since tests usually run on separate runtimes that are
+//! shut down when the test ends.
+
+use super::client::DockerClient;
+use super::container::Container;
+use super::hooks::{BoxError, HookResult};
+use super::image::DockerImage;
+use super::shared::{register_cleanup, RUNTIME};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+type Template = Arc<dyn Fn() -> DockerImage + Send + Sync>;
+type Seed<C> = Arc<dyn for<'a> Fn(&'a Container<C>) -> BoxFuture<'a, HookResult> + Send + Sync>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to prepare snapshot {reference}")]
+    Prepare {
+        reference: String,
+        #[source]
+        source: crate::Error,
+    },
+
+    #[error("snapshot container {reference} did not become ready")]
+    Ready {
+        reference: String,
+        #[source]
+        source: crate::Error,
+    },
+
+    #[error("failed to seed snapshot {reference}")]
+    Seed {
+        reference: String,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("snapshot preparation panicked")]
+    Panicked(#[source] tokio::task::JoinError),
+
+    #[error("failed to start copy of snapshot {reference}")]
+    Spawn {
+        reference: String,
+        #[source]
+        source: crate::Error,
+    },
+
+    #[error("copy of snapshot {reference} did not become ready")]
+    SpawnReady {
+        reference: String,
+        #[source]
+        source: crate::Error,
+    },
+}
+
+/// An image committed from a seeded container, prepared on first use.
+pub struct Snapshot<C>
+where
+    C: DockerClient,
+{
+    reference: String,
+    image: Template,
+    seed: Seed<C>,
+    committed: OnceCell<DockerImage>,
+}
+
+impl<C> fmt::Debug for Snapshot<C>
+where
+    C: DockerClient,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("reference", &self.reference)
+            .field("committed", &self.committed.initialized())
+            .finish()
+    }
+}
+
+impl<C> Snapshot<C>
+where
+    C: DockerClient,
+{
+    /// Creates a snapshot of containers created from `image` and seeded by `seed`.
+    ///
+    /// The snapshot is committed as `name:<process id>`, so that concurrent test
+    /// processes do not share or remove each others snapshots.
+    /// `image` also describes the copies, e.g. their ports and ready conditions.
+    pub fn new<I, S>(name: impl Into<String>, image: I, seed: S) -> Self
+    where
+        I: Fn() -> DockerImage + Send + Sync + 'static,
+        S: for<'a> Fn(&'a Container<C>) -> BoxFuture<'a, HookResult> + Send + Sync + 'static,
+    {
+        Self {
+            reference: format!("{}:{}", name.into(), std::process::id()),
+            image: Arc::new(image),
+            seed: Arc::new(seed),
+            committed: OnceCell::new(),
+        }
+    }
+
+    /// Returns the reference of the committed image.
+    pub fn reference(&self) -> &str {
+        &self.reference
+    }
+
+    /// Starts, seeds and commits a container, then removes it.
+    async fn prepare(&self, client: &C) -> Result<DockerImage, Error> {
+        let client = client.clone();
+        let reference = self.reference.clone();
+        let image = self.image.clone();
+        let seed = self.seed.clone();
+        let committed = RUNTIME
+            .spawn(async move {
+                let prepare_err = |source| Error::Prepare {
+                    reference: reference.clone(),
+                    source,
+                };
+                let container = client.create(image()).await.map_err(prepare_err)?;
+                container.start().await.map_err(prepare_err)?;
+                container
+                    .wait_until_ready()
+                    .await
+                    .map_err(|source| Error::Ready {
+                        reference: reference.clone(),
+                        source,
+                    })?;
+                seed(&container).await.map_err(|source| Error::Seed {
+                    reference: reference.clone(),
+                    source,
+                })?;
+                let committed = container.commit(&reference).await.map_err(prepare_err)?;
+                log::debug!("committed snapshot {}", reference);
+
+                register_cleanup(Box::new(move || {
+                    async move {
+                        log::debug!("removing snapshot {}", reference);
+                        if let Err(err) = client.rm_image(&reference).await {
+                            log::error!("failed to remove snapshot {}: {}", reference, err);
+                        }
+                    }
+                    .boxed()
+                }));
+                if let Err(err) = container.rm().await {
+                    log::warn!("failed to remove seeded container: {}", err);
+                }
+                Ok::<_, Error>(committed)
+            })
+            .await
+            .map_err(Error::Panicked)??;
+        Ok(committed)
+    }
+
+    /// Creates, starts and waits for a fresh copy of the seeded container.
+    ///
+    /// The snapshot is prepared on first use, concurrent callers wait for the
+    /// same preparation.
+    pub async fn spawn(&self, client: &C) -> Result<Container<C>, Error> {
+        let committed = self
+            .committed
+            .get_or_try_init(|| self.prepare(client))
+            .await?;
+        let image = DockerImage {
+            image: committed.image.clone(),
+            image_tag: committed.image_tag.clone(),
+            pull_policy: committed.pull_policy,
+            ..(self.image)()
+        };
+        let spawn_err = |source| Error::Spawn {
+            reference: self.reference.clone(),
+            source,
+        };
+        let container = client.create(image).await.map_err(spawn_err)?;
+        container.start().await.map_err(spawn_err)?;
+        container
+            .wait_until_ready()
+            .await
+            .map_err(|source| Error::SpawnReady {
+                reference: self.reference.clone(),
+                source,
+            })?;
+        Ok(container)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::Snapshot;
+    use crate::client::mock::{MockClient, Status};
+    use crate::image::PullPolicy;
+    use crate::DockerImage;
+    use futures::FutureExt;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn copies_seeded_container() -> color_eyre::eyre::Result<()> {
+        let client = MockClient::new();
+        let snapshot = Snapshot::new(
+            "postgres-seeded",
+            || DockerImage::new("postgres").with_mapped_port(0, 5432),
+            |container| {
+                async move {
+                    container.exec(["psql", "-f", "/seed.sql"]).await?;
+                    Ok(())
+                }
+                .boxed()
+            },
+        );
+        let reference = format!("postgres-seeded:{}", std::process::id());
+        assert_eq!(snapshot.reference(), reference);
+
+        let first = snapshot.spawn(&client).await?;
+        let second = snapshot.spawn(&client).await?;
+        assert_eq!(client.images(), vec![reference.clone()]);
+        let seeded = client.committed_from(&reference).unwrap();
+        assert_eq!(client.status(&seeded), None);
+
+        for copy in [&first, &second] {
+            assert_eq!(client.image(copy.id()).as_deref(), Some(reference.as_str()));
+            assert_eq!(client.status(copy.id()), Some(Status::Running));
+            assert_eq!(copy.image().pull_policy, PullPolicy::Missing);
+            assert!(copy.ports().await?.mapped_port_ipv4(5432).is_some());
+        }
+        assert_ne!(first.id(), second.id());
+        Ok(())
+    }
+}
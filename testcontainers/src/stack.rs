@@ -0,0 +1,550 @@
+//! Stacks of containers that start in dependency order on a private network.
+//!
+//! A [`Stack`] creates a network for its members, which reach each other by
+//! their member names.
+//! Members without pending dependencies start in parallel, and a member only
+//! starts once all of its dependencies are ready.
+//! If any member fails to start or become ready, the members that are already
+//! starting run to completion, then the whole stack is torn down and the error
+//! carries the diagnostics of all its containers.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use testcontainers_rs::client::bollard::Client;
+//! use testcontainers_rs::stack::Stack;
+//! use testcontainers_rs::{DockerImage, WaitFor};
+//!
+//! let client = Client::new().await?;
+//! let stack = Stack::new()
+//!     .with_container(
+//!         "db",
+//!         DockerImage::new("postgres").with_wait_for(WaitFor::Healthcheck),
+//!     )
+//!     .with_container("cache", DockerImage::new("redis"))
+//!     .with_container("app", DockerImage::new("my-app").with_mapped_port(0, 8080))
+//!     .with_depends_on("app", "db")
+//!     .with_depends_on("app", "cache")
+//!     .start(&client)
+//!     .await?;
+//! let app = stack.container("app").unwrap();
+//! # Ok(())
+//! # }
+//! ```
+
+use super::client::DockerClient;
+use super::container::Container;
+use super::diagnostics::{self, Report};
+use super::error::Operation;
+use super::image::{DockerImage, IntoImage};
+use super::network::Network;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_NETWORK: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("stack member {0} is defined more than once")]
+    Duplicate(String),
+
+    #[error("dependency of unknown stack member {0}")]
+    UnknownMember(String),
+
+    #[error("stack member {member} depends on unknown member {dependency}")]
+    UnknownDependency { member: String, dependency: String },
+
+    #[error("stack members {0:?} depend on each other")]
+    Cycle(Vec<String>),
+
+    #[error("failed to create stack network {name}")]
+    Network {
+        name: String,
+        #[source]
+        source: Box<crate::Error>,
+    },
+
+    #[error("failed to start stack member {member}{}", render(.reports))]
+    Start {
+        member: String,
+        #[source]
+        source: Box<crate::Error>,
+        /// Diagnostics of all containers of the stack.
+        reports: Vec<Report>,
+    },
+
+    #[error("stack member {member} did not become ready{}", render(.reports))]
+    NotReady {
+        member: String,
+        #[source]
+        source: Box<crate::Error>,
+        /// Diagnostics of all containers of the stack.
+        reports: Vec<Report>,
+    },
+}
+
+fn render(reports: &[Report]) -> String {
+    reports
+        .iter()
+        .map(|report| format!("\n{}", report))
+        .collect()
+}
+
+#[derive(Debug)]
+struct Member<C: DockerClient> {
+    name: String,
+    image: DockerImage<C>,
+}
+
+/// A builder of containers that start together on a private network.
+///
+/// The stack is started by a client of type `C`, so its members may be images
+/// with [container hooks](crate::hooks) of that client.
+#[must_use]
+#[derive(Debug)]
+pub struct Stack<C: DockerClient> {
+    network: Option<String>,
+    members: Vec<Member<C>>,
+    depends_on: Vec<(String, String)>,
+}
+
+impl<C: DockerClient> Default for Stack<C> {
+    fn default() -> Self {
+        Self {
+            network: None,
+            members: Vec::new(),
+            depends_on: Vec::new(),
+        }
+    }
+}
+
+impl<C: DockerClient> Stack<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names the network of the stack, which is unique per stack by default.
+    pub fn with_network_name(self, name: impl Into<String>) -> Self {
+        Self {
+            network: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Adds a container that the other members reach as `name`.
+    pub fn with_container(self, name: impl Into<String>, image: impl IntoImage<C>) -> Self {
+        let mut members = self.members;
+        members.push(Member {
+            name: name.into(),
+            image: image.into_image(),
+        });
+        Self { members, ..self }
+    }
+
+    /// Starts the member `name` only once the member `dependency` is ready.
+    pub fn with_depends_on(self, name: impl Into<String>, dependency: impl Into<String>) -> Self {
+        let mut depends_on = self.depends_on;
+        depends_on.push((name.into(), dependency.into()));
+        Self { depends_on, ..self }
+    }
+
+    /// Returns the pending dependencies of every member.
+    fn dependencies(&self) -> Result<BTreeMap<String, BTreeSet<String>>, Error> {
+        let mut dependencies: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for member in &self.members {
+            if dependencies
+                .insert(member.name.clone(), BTreeSet::new())
+                .is_some()
+            {
+                return Err(Error::Duplicate(member.name.clone()));
+            }
+        }
+        for (member, dependency) in &self.depends_on {
+            if !dependencies.contains_key(dependency) {
+                return Err(Error::UnknownDependency {
+                    member: member.clone(),
+                    dependency: dependency.clone(),
+                });
+            }
+            dependencies
+                .get_mut(member)
+                .ok_or_else(|| Error::UnknownMember(member.clone()))?
+                .insert(dependency.clone());
+        }
+
+        // members that remain after repeatedly removing those without dependencies form cycles
+        let mut remaining = dependencies.clone();
+        loop {
+            let ready: Vec<String> = remaining
+                .iter()
+                .filter(|(_, pending)| pending.is_empty())
+                .map(|(name, _)| name.clone())
+                .collect();
+            if ready.is_empty() {
+                break;
+            }
+            for name in ready {
+                remaining.remove(&name);
+                for pending in remaining.values_mut() {
+                    pending.remove(&name);
+                }
+            }
+        }
+        if !remaining.is_empty() {
+            return Err(Error::Cycle(remaining.into_keys().collect()));
+        }
+        Ok(dependencies)
+    }
+
+    /// Creates the network and starts all members in dependency order.
+    pub async fn start(self, client: &C) -> Result<RunningStack<C>, Error> {
+        let mut pending = self.dependencies()?;
+        let name = self.network.unwrap_or_else(|| {
+            format!(
+                "testcontainers-stack-{}-{}",
+                std::process::id(),
+                NEXT_NETWORK.fetch_add(1, Ordering::Relaxed)
+            )
+        });
+        let network = client
+            .network(&name)
+            .await
+            .map_err(|source| Error::Network {
+                name: name.clone(),
+                source: Box::new(crate::Error::new(Operation::Network, source)),
+            })?;
+        log::debug!("starting stack on network {}", name);
+
+        let mut images: BTreeMap<String, DockerImage<C>> = self
+            .members
+            .into_iter()
+            .map(|member| {
+                let image = member
+                    .image
+                    .with_network(&name)
+                    .with_network_alias(&name, &member.name);
+                (member.name, image)
+            })
+            .collect();
+
+        // dropping the stack on errors removes the started containers and the network
+        let mut stack = RunningStack {
+            containers: Vec::new(),
+            network: Some(network),
+        };
+        let mut starting = FuturesUnordered::new();
+        loop {
+            let ready: Vec<String> = pending
+                .iter()
+                .filter(|(_, dependencies)| dependencies.is_empty())
+                .map(|(name, _)| name.clone())
+                .collect();
+            for name in ready {
+                pending.remove(&name);
+                if let Some(image) = images.remove(&name) {
+                    starting.push(start_member(client, name, image));
+                }
+            }
+
+            match starting.next().await {
+                None => break,
+                Some((name, Ok(container))) => {
+                    log::debug!("stack member {} is ready", name);
+                    for dependencies in pending.values_mut() {
+                        dependencies.remove(&name);
+                    }
+                    stack.containers.push((name, container));
+                }
+                Some((name, Err(failure))) => {
+                    // cancelling a pending create would leak its container, so
+                    // in-flight members finish and are removed with the stack
+                    while let Some((other, result)) = starting.next().await {
+                        if let Ok(container) = result {
+                            stack.containers.push((other, container));
+                        }
+                    }
+                    let lines = diagnostics::lines();
+                    let mut reports = Vec::new();
+                    if let Some(container) = &failure.container {
+                        reports.push(container.report(lines).await);
+                    }
+                    reports.extend(stack.reports(lines).await);
+                    return Err(match failure.error {
+                        MemberError::Start(source) => Error::Start {
+                            member: name,
+                            source: Box::new(source),
+                            reports,
+                        },
+                        MemberError::NotReady(source) => Error::NotReady {
+                            member: name,
+                            source: Box::new(source),
+                            reports,
+                        },
+                    });
+                }
+            }
+        }
+        Ok(stack)
+    }
+}
+
+enum MemberError {
+    Start(crate::Error),
+    NotReady(crate::Error),
+}
+
+struct Failure<C>
+where
+    C: DockerClient,
+{
+    /// The container of the member, if it was created.
+    container: Option<Container<C>>,
+    error: MemberError,
+}
+
+async fn start_member<C: DockerClient>(
+    client: &C,
+    name: String,
+    image: DockerImage<C>,
+) -> (String, Result<Container<C>, Failure<C>>) {
+    let container = match client.create(image).await {
+        Ok(container) => container,
+        Err(err) => {
+            let failure = Failure {
+                container: None,
+                error: MemberError::Start(err),
+            };
+            return (name, Err(failure));
+        }
+    };
+    let error = match container.start().await {
+        Ok(()) => match container.wait_until_ready().await {
+            Ok(()) => return (name, Ok(container)),
+            Err(err) => MemberError::NotReady(err),
+        },
+        Err(err) => MemberError::Start(err),
+    };
+    let failure = Failure {
+        container: Some(container),
+        error,
+    };
+    (name, Err(failure))
+}
+
+/// The started containers of a [`Stack`], removed together with the network when dropped.
+pub struct RunningStack<C>
+where
+    C: DockerClient,
+{
+    /// Containers in the order they became ready.
+    containers: Vec<(String, Container<C>)>,
+    network: Option<Network<C>>,
+}
+
+impl<C> fmt::Debug for RunningStack<C>
+where
+    C: DockerClient,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RunningStack")
+            .field("network", &self.network)
+            .field("containers", &self.containers)
+            .finish()
+    }
+}
+
+impl<C> RunningStack<C>
+where
+    C: DockerClient,
+{
+    /// Returns the name of the network of the stack.
+    pub fn network(&self) -> &str {
+        self.network.as_ref().map(Network::name).unwrap_or_default()
+    }
+
+    /// Returns the container of the member `name`.
+    pub fn container(&self, name: &str) -> Option<&Container<C>> {
+        self.containers
+            .iter()
+            .find(|(member, _)| member == name)
+            .map(|(_, container)| container)
+    }
+
+    /// Returns the member names in the order the members became ready.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.containers.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Collects the diagnostics of all containers of the stack.
+    pub async fn reports(&self, lines: usize) -> Vec<Report> {
+        futures::future::join_all(
+            self.containers
+                .iter()
+                .map(|(_, container)| container.report(lines)),
+        )
+        .await
+    }
+
+    /// Removes all containers, then the network.
+    pub async fn rm(mut self) -> Result<(), crate::Error> {
+        let containers = std::mem::take(&mut self.containers);
+        let removed =
+            futures::future::join_all(containers.into_iter().map(|(_, container)| container.rm()))
+                .await;
+        removed.into_iter().collect::<Result<Vec<_>, _>>()?;
+        match self.network.take() {
+            Some(network) if !network.is_kept() => network.rm().await,
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<C> Drop for RunningStack<C>
+where
+    C: DockerClient,
+{
+    fn drop(&mut self) {
+        // dependents first, and the network only once no container uses it
+        while let Some(member) = self.containers.pop() {
+            drop(member);
+        }
+        self.network.take();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::{Error, Stack};
+    use crate::client::mock::{MockClient, Status};
+    use crate::hooks::ContainerHook;
+    use crate::{DockerImage, WaitFor};
+    use futures::FutureExt;
+    use pretty_assertions::assert_eq;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn starts_in_dependency_order() -> color_eyre::eyre::Result<()> {
+        let client = MockClient::new();
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let image = |name: &'static str| {
+            let started = started.clone();
+            DockerImage::new(name).with_hook::<MockClient, _>(ContainerHook::PostStart, move |_| {
+                started.lock().unwrap().push(name);
+                async { Ok(()) }.boxed()
+            })
+        };
+        let stack = Stack::new()
+            .with_network_name("shop")
+            .with_container("app", image("app"))
+            .with_container("db", image("postgres"))
+            .with_container("cache", image("redis"))
+            .with_depends_on("app", "db")
+            .with_depends_on("app", "cache")
+            .start(&client)
+            .await?;
+
+        assert_eq!(stack.network(), "shop");
+        assert_eq!(client.networks(), vec!["shop".to_string()]);
+        assert_eq!(started.lock().unwrap().last(), Some(&"app"));
+        assert_eq!(stack.names().last(), Some("app"));
+        for name in ["app", "db", "cache"] {
+            let container = stack.container(name).unwrap();
+            assert_eq!(client.status(container.id()), Some(Status::Running));
+            assert_eq!(client.container_networks(container.id()), vec!["shop"]);
+            assert_eq!(
+                container.image().network_aliases.get("shop"),
+                Some(&vec![name.to_string()])
+            );
+        }
+
+        stack.rm().await?;
+        assert!(client.container_ids().is_empty());
+        assert!(client.networks().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tears_down_when_a_member_is_not_ready() -> color_eyre::eyre::Result<()> {
+        let client = MockClient::new();
+        let err = Stack::new()
+            .with_container("db", DockerImage::new("postgres"))
+            .with_container(
+                "broken",
+                DockerImage::new("redis").with_wait_for(WaitFor::Healthcheck),
+            )
+            .with_container("app", DockerImage::new("app"))
+            .with_depends_on("app", "broken")
+            .with_depends_on("app", "db")
+            .start(&client)
+            .await
+            .unwrap_err();
+
+        match &err {
+            Error::NotReady {
+                member, reports, ..
+            } => {
+                assert_eq!(member, "broken");
+                assert!(reports.iter().any(|report| report.image == "redis:latest"));
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+        assert!(err.to_string().contains("==== container"));
+        assert!(client.container_ids().is_empty());
+        assert!(client.networks().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn removes_members_created_while_failing() -> color_eyre::eyre::Result<()> {
+        let client = MockClient::new()
+            .with_create_delay("slow", Duration::from_millis(200))
+            .with_create_delay("redis", Duration::from_millis(20));
+        let err = Stack::new()
+            .with_container("slow", DockerImage::new("slow"))
+            .with_container(
+                "broken",
+                DockerImage::new("redis").with_wait_for(WaitFor::Healthcheck),
+            )
+            .start(&client)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(&err, Error::NotReady { member, .. } if member == "broken"));
+        assert!(client.container_ids().is_empty());
+        assert!(client.networks().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_dependencies() {
+        let stack = || {
+            Stack::<MockClient>::new()
+                .with_container("a", DockerImage::new("a"))
+                .with_container("b", DockerImage::new("b"))
+        };
+        assert!(matches!(
+            stack().with_depends_on("a", "c").dependencies(),
+            Err(Error::UnknownDependency { .. })
+        ));
+        assert!(matches!(
+            stack().with_depends_on("c", "a").dependencies(),
+            Err(Error::UnknownMember(_))
+        ));
+        assert!(matches!(
+            stack()
+                .with_container("a", DockerImage::new("a"))
+                .dependencies(),
+            Err(Error::Duplicate(_))
+        ));
+        match stack()
+            .with_depends_on("a", "b")
+            .with_depends_on("b", "a")
+            .dependencies()
+        {
+            Err(Error::Cycle(members)) => assert_eq!(members, vec!["a", "b"]),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
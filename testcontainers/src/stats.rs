@@ -0,0 +1,313 @@
+//! Resource usage of containers.
+//!
+//! [`Sample`]s are built from the docker stats endpoint and computed the same
+//! way as by `docker stats`.
+
+use super::client::DockerClient;
+use super::logs::frames;
+use super::rt;
+use futures::channel::oneshot;
+use futures::future::{AbortHandle, Abortable};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// A stream of resource usage samples.
+pub type StatsStream<'s, E> = BoxStream<'s, Result<Sample, E>>;
+
+/// The resource usage of a container at one point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "record", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sample {
+    /// When the sample was taken.
+    pub read: Option<SystemTime>,
+    /// CPU usage in percent of a single CPU, i.e. up to 100 times the number of CPUs.
+    pub cpu_percent: f64,
+    /// Memory usage in bytes, excluding the inactive page cache.
+    pub memory_usage: u64,
+    /// Memory limit in bytes.
+    pub memory_limit: u64,
+    pub network_rx_bytes: u64,
+    pub network_tx_bytes: u64,
+    pub block_read_bytes: u64,
+    pub block_write_bytes: u64,
+    pub pids: u64,
+}
+
+impl Sample {
+    /// Memory usage in percent of the limit.
+    pub fn memory_percent(&self) -> f64 {
+        if self.memory_limit == 0 {
+            return 0.0;
+        }
+        self.memory_usage as f64 / self.memory_limit as f64 * 100.0
+    }
+}
+
+impl From<&bollard::container::Stats> for Sample {
+    fn from(stats: &bollard::container::Stats) -> Self {
+        use bollard::container::MemoryStatsStats;
+
+        let cpu = &stats.cpu_stats;
+        let precpu = &stats.precpu_stats;
+        let cpu_delta = cpu
+            .cpu_usage
+            .total_usage
+            .saturating_sub(precpu.cpu_usage.total_usage);
+        let system_delta = cpu
+            .system_cpu_usage
+            .unwrap_or_default()
+            .saturating_sub(precpu.system_cpu_usage.unwrap_or_default());
+        let online_cpus = cpu.online_cpus.unwrap_or_else(|| {
+            cpu.cpu_usage
+                .percpu_usage
+                .as_ref()
+                .map_or(1, |usage| usage.len() as u64)
+        });
+        let cpu_percent = if system_delta > 0 && cpu_delta > 0 {
+            cpu_delta as f64 / system_delta as f64 * online_cpus as f64 * 100.0
+        } else {
+            0.0
+        };
+
+        let memory = &stats.memory_stats;
+        let cache = match memory.stats {
+            Some(MemoryStatsStats::V1(stats)) => stats.total_inactive_file,
+            Some(MemoryStatsStats::V2(stats)) => stats.inactive_file,
+            None => 0,
+        };
+        let memory_usage = memory.usage.unwrap_or_default().saturating_sub(cache);
+
+        let (network_rx_bytes, network_tx_bytes) = stats
+            .networks
+            .iter()
+            .flat_map(|networks| networks.values())
+            .chain(stats.network.iter())
+            .fold((0, 0), |(rx, tx), network| {
+                (rx + network.rx_bytes, tx + network.tx_bytes)
+            });
+
+        let (block_read_bytes, block_write_bytes) = stats
+            .blkio_stats
+            .io_service_bytes_recursive
+            .iter()
+            .flatten()
+            .fold((0, 0), |(read, write), entry| {
+                match entry.op.to_ascii_lowercase().as_str() {
+                    "read" => (read + entry.value, write),
+                    "write" => (read, write + entry.value),
+                    _ => (read, write),
+                }
+            });
+
+        Self {
+            read: frames::parse_timestamp(&stats.read),
+            cpu_percent,
+            memory_usage,
+            memory_limit: memory.limit.unwrap_or_default(),
+            network_rx_bytes,
+            network_tx_bytes,
+            block_read_bytes,
+            block_write_bytes,
+            pids: stats.pids_stats.current.unwrap_or_default(),
+        }
+    }
+}
+
+/// The peak resource usage over a number of samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Peak {
+    pub cpu_percent: f64,
+    pub memory_usage: u64,
+    pub pids: u64,
+    /// The number of samples the peak was computed from.
+    pub samples: usize,
+}
+
+impl Peak {
+    pub fn update(&mut self, sample: &Sample) {
+        self.cpu_percent = self.cpu_percent.max(sample.cpu_percent);
+        self.memory_usage = self.memory_usage.max(sample.memory_usage);
+        self.pids = self.pids.max(sample.pids);
+        self.samples += 1;
+    }
+}
+
+/// A background task recording the peak resource usage of a container.
+///
+/// Recording ends when the container stops or [`PeakUsage::stop`] is called.
+#[derive(Debug)]
+pub struct PeakUsage {
+    peak: Arc<Mutex<Peak>>,
+    abort: AbortHandle,
+    done: oneshot::Receiver<()>,
+}
+
+impl PeakUsage {
+    /// Returns the peak usage recorded so far.
+    pub fn get(&self) -> Peak {
+        *self.peak.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Stops recording.
+    pub fn stop(&self) {
+        self.abort.abort();
+    }
+
+    /// Waits until recording ends and returns the peak usage.
+    pub async fn finished(self) -> Peak {
+        let _ = self.done.await;
+        *self.peak.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Spawns a task recording the peak usage of the container.
+pub(crate) fn track<C: DockerClient>(client: C, id: String) -> PeakUsage {
+    let peak = Arc::new(Mutex::new(Peak::default()));
+    let (abort, registration) = AbortHandle::new_pair();
+    let (done_tx, done) = oneshot::channel();
+    let record = {
+        let peak = peak.clone();
+        async move {
+            let mut stats = client.stats(&id);
+            while let Some(sample) = stats.next().await {
+                match sample {
+                    Ok(sample) => peak
+                        .lock()
+                        .unwrap_or_else(|err| err.into_inner())
+                        .update(&sample),
+                    Err(err) => {
+                        log::warn!("failed to read stats of container {}: {}", id, err);
+                        break;
+                    }
+                }
+            }
+        }
+    };
+    rt::spawn(async move {
+        let _ = Abortable::new(record, registration).await;
+        let _ = done_tx.send(());
+    });
+    PeakUsage { peak, abort, done }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sample;
+    use pretty_assertions::assert_eq;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[cfg(feature = "mock")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tracks_peak_usage() -> color_eyre::eyre::Result<()> {
+        use super::Peak;
+        use crate::client::mock::{MockClient, Operation};
+        use crate::client::DockerClient;
+        use crate::DockerImage;
+        use futures::TryStreamExt;
+
+        let client = MockClient::new();
+        let container = client.create(DockerImage::new("redis")).await?;
+        assert!(container.stats_snapshot().await.is_err());
+        container.start().await?;
+        assert_eq!(container.stats_snapshot().await?, Sample::default());
+
+        let sample = |cpu_percent, memory_usage, pids| Sample {
+            cpu_percent,
+            memory_usage,
+            pids,
+            ..Default::default()
+        };
+        client.push_stats(container.id(), sample(10.0, 300, 2))?;
+        client.push_stats(container.id(), sample(55.0, 200, 4))?;
+        client.push_stats(container.id(), sample(5.0, 100, 3))?;
+        assert_eq!(container.stats_snapshot().await?, sample(5.0, 100, 3));
+        assert_eq!(container.stats().try_collect::<Vec<_>>().await?.len(), 3);
+
+        let peak = container.track_peak_usage().finished().await;
+        assert_eq!(
+            peak,
+            Peak {
+                cpu_percent: 55.0,
+                memory_usage: 300,
+                pids: 4,
+                samples: 3,
+            }
+        );
+
+        client.inject_failure(Operation::Stats, "daemon gone");
+        assert_eq!(container.track_peak_usage().finished().await.samples, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn sample_from_docker_stats() -> color_eyre::eyre::Result<()> {
+        let stats: bollard::container::Stats = serde_json::from_value(serde_json::json!({
+            "read": "2023-01-30T12:34:56.5Z",
+            "preread": "2023-01-30T12:34:55.5Z",
+            "num_procs": 0,
+            "pids_stats": { "current": 7 },
+            "networks": {
+                "eth0": {
+                    "rx_bytes": 1000, "rx_packets": 0, "rx_errors": 0, "rx_dropped": 0,
+                    "tx_bytes": 500, "tx_packets": 0, "tx_errors": 0, "tx_dropped": 0
+                }
+            },
+            "memory_stats": {
+                "usage": 10_000,
+                "limit": 40_000,
+                "stats": {
+                    "active_anon": 0, "active_file": 0, "anon": 0, "anon_thp": 0, "file": 0,
+                    "file_dirty": 0, "file_mapped": 0, "file_writeback": 0,
+                    "inactive_anon": 0, "inactive_file": 2_000, "kernel_stack": 0,
+                    "pgactivate": 0, "pgdeactivate": 0, "pgfault": 0, "pglazyfree": 0,
+                    "pglazyfreed": 0, "pgmajfault": 0, "pgrefill": 0, "pgscan": 0,
+                    "pgsteal": 0, "shmem": 0, "slab": 0, "slab_reclaimable": 0,
+                    "slab_unreclaimable": 0, "sock": 0, "thp_collapse_alloc": 0,
+                    "thp_fault_alloc": 0, "unevictable": 0, "workingset_activate": 0,
+                    "workingset_nodereclaim": 0, "workingset_refault": 0
+                }
+            },
+            "blkio_stats": {
+                "io_service_bytes_recursive": [
+                    { "major": 8, "minor": 0, "op": "read", "value": 300 },
+                    { "major": 8, "minor": 0, "op": "write", "value": 200 }
+                ]
+            },
+            "cpu_stats": {
+                "cpu_usage": { "total_usage": 300, "usage_in_kernelmode": 0, "usage_in_usermode": 0 },
+                "system_cpu_usage": 2_000,
+                "online_cpus": 4,
+                "throttling_data": { "periods": 0, "throttled_periods": 0, "throttled_time": 0 }
+            },
+            "precpu_stats": {
+                "cpu_usage": { "total_usage": 100, "usage_in_kernelmode": 0, "usage_in_usermode": 0 },
+                "system_cpu_usage": 1_000,
+                "online_cpus": 4,
+                "throttling_data": { "periods": 0, "throttled_periods": 0, "throttled_time": 0 }
+            },
+            "storage_stats": {},
+            "name": "/redis",
+            "id": "c0ffee"
+        }))?;
+
+        let sample = Sample::from(&stats);
+        assert_eq!(
+            sample,
+            Sample {
+                read: Some(UNIX_EPOCH + Duration::new(1_675_082_096, 500_000_000)),
+                cpu_percent: 80.0,
+                memory_usage: 8_000,
+                memory_limit: 40_000,
+                network_rx_bytes: 1000,
+                network_tx_bytes: 500,
+                block_read_bytes: 300,
+                block_write_bytes: 200,
+                pids: 7,
+            }
+        );
+        assert_eq!(sample.memory_percent(), 20.0);
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+//! Runtime support for the [`test`](crate::test) attribute macro.
+//!
+//! ```no_run
+//! use testcontainers_rs::{client::bollard::Client, Container, DockerImage};
+//!
+//! #[testcontainers_rs::test]
+//! async fn ping(
+//!     #[image(DockerImage::new("redis").with_mapped_port(0, 6379))] redis: Container<Client>,
+//! ) {
+//!     let port = redis.mapped_port_ipv4(6379).await.unwrap();
+//!     assert!(port.is_some());
+//! }
+//! ```
+
+use super::client::DockerClient;
+use super::container::Container;
+use super::diagnostics::{self, Report};
+use super::image::IntoImage;
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Clients that can be connected to without any configuration.
+///
+/// The [`test`](crate::test) macro uses this to obtain the client of each injected container.
+#[async_trait]
+pub trait Connect: DockerClient {
+    async fn connect() -> Result<Self, Self::Error>;
+}
+
+#[async_trait]
+impl Connect for super::client::bollard::Client {
+    async fn connect() -> Result<Self, Self::Error> {
+        Self::new().await
+    }
+}
+
+#[cfg(feature = "mock")]
+#[async_trait]
+impl Connect for super::client::mock::MockClient {
+    async fn connect() -> Result<Self, Self::Error> {
+        Ok(Self::new())
+    }
+}
+
+/// The result of a test body.
+pub trait Outcome {
+    fn is_failure(&self) -> bool;
+}
+
+impl Outcome for () {
+    fn is_failure(&self) -> bool {
+        false
+    }
+}
+
+impl<T, E> Outcome for Result<T, E> {
+    fn is_failure(&self) -> bool {
+        self.is_err()
+    }
+}
+
+/// Runs a test on a new multi-threaded tokio runtime.
+#[doc(hidden)]
+pub fn block_on<F: Future>(test: F) -> F::Output {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("failed to build test runtime")
+        .block_on(test)
+}
+
+/// Creates, starts and waits for a container of the image.
+///
+/// Panics on failure, which fails the test.
+#[doc(hidden)]
+pub async fn start<C, I>(image: I) -> Container<C>
+where
+    C: Connect,
+    I: IntoImage<C> + Send,
+{
+    let image = image.into_image();
+    let descriptor = image.descriptor();
+    let client = C::connect()
+        .await
+        .unwrap_or_else(|err| panic!("failed to connect to docker: {}", err));
+    let container = client
+        .create(image)
+        .await
+        .unwrap_or_else(|err| panic!("failed to create container for {}: {}", descriptor, err));
+    container
+        .start()
+        .await
+        .unwrap_or_else(|err| panic!("failed to start container {}: {}", container.id(), err));
+    container.wait_until_ready().await.unwrap_or_else(|err| {
+        panic!(
+            "container {} ({}) did not become ready: {}",
+            container.id(),
+            descriptor,
+            err
+        )
+    });
+    container
+}
+
+type CollectReport = Box<dyn Fn() -> BoxFuture<'static, Report> + Send + Sync>;
+
+/// A container whose diagnostics are printed when the test fails.
+#[doc(hidden)]
+pub struct Watched {
+    report: CollectReport,
+}
+
+impl Watched {
+    pub fn new<C>(container: &Container<C>) -> Self
+    where
+        C: DockerClient + Clone + Send + Sync + 'static,
+    {
+        let client = container.client().clone();
+        let id = container.id().to_string();
+        let image = container.image().descriptor();
+        let report: CollectReport = Box::new(move || {
+            let client = client.clone();
+            let id = id.clone();
+            let image = image.clone();
+            async move { Report::collect(&client, &id, &image, diagnostics::lines()).await }.boxed()
+        });
+        Self { report }
+    }
+}
+
+/// Runs the test body, printing container diagnostics if it fails.
+///
+/// Panics are already reported by the panic hook of [`diagnostics`], unless it is disabled.
+/// The body owns the containers, so they are removed when it is dropped,
+/// which happens after the diagnostics are printed and before a panic is resumed.
+#[doc(hidden)]
+pub async fn run<F>(watched: Vec<Watched>, test: F) -> F::Output
+where
+    F: Future,
+    F::Output: Outcome,
+{
+    let mut test = Box::pin(test);
+    let result = AssertUnwindSafe(test.as_mut()).catch_unwind().await;
+    let report = match &result {
+        Ok(outcome) => outcome.is_failure(),
+        Err(_) => !diagnostics::on_panic(),
+    };
+    if report {
+        for container in &watched {
+            print!("{}", (container.report)().await);
+        }
+    }
+    drop(test);
+    match result {
+        Ok(outcome) => outcome,
+        Err(panic) => panic::resume_unwind(panic),
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::{run, Watched};
+    use crate::client::mock::MockClient;
+    use crate::client::DockerClient;
+    use crate::{Container, DockerImage, WaitFor};
+    use color_eyre::eyre;
+    use futures::FutureExt;
+    use pretty_assertions::assert_eq;
+    use std::panic::AssertUnwindSafe;
+
+    #[crate::test]
+    async fn injects_started_container(
+        #[image(DockerImage::new("redis").with_mapped_port(6379, 6379))] redis: Container<
+            MockClient,
+        >,
+    ) {
+        assert_eq!(redis.mapped_port_ipv4(6379).await.unwrap(), Some(6379));
+    }
+
+    #[crate::test]
+    async fn returns_result(
+        #[image(DockerImage::new("redis"))] redis: Container<MockClient>,
+        #[image(DockerImage::new("postgres"))] postgres: Container<MockClient>,
+    ) -> eyre::Result<()> {
+        assert!(redis.exec(["redis-cli", "ping"]).await?.success());
+        assert!(postgres.exec(["pg_isready"]).await?.success());
+        Ok(())
+    }
+
+    #[crate::test]
+    async fn injects_unstarted_image(
+        #[image(DockerImage::new("redis"))] redis: Container<MockClient>,
+        #[image(DockerImage::new("redis").with_tag("7"))] next: DockerImage,
+    ) {
+        assert_eq!(next.descriptor(), "redis:7");
+        assert!(redis.exec(["redis-cli", "ping"]).await.unwrap().success());
+    }
+
+    #[crate::test]
+    #[should_panic(expected = "did not become ready")]
+    async fn fails_when_not_ready(
+        #[image(DockerImage::new("redis").with_wait_for(WaitFor::message_on_stdout("Ready")))]
+        _redis: Container<MockClient>,
+    ) {
+    }
+
+    fn fail() {
+        panic!("test failed");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn removes_containers_on_panic() -> eyre::Result<()> {
+        let client = MockClient::new().with_stdout("redis", ["booting"]);
+        let container = client.create(DockerImage::new("redis")).await?;
+        container.start().await?;
+        let watched = vec![Watched::new(&container)];
+
+        let result = AssertUnwindSafe(run(watched, async move {
+            let _container = container;
+            fail();
+        }))
+        .catch_unwind()
+        .await;
+        assert!(result.is_err());
+        assert!(client.container_ids().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+//! Optional `tracing` instrumentation.
+//!
+//! With the `tracing` feature, every container gets a `testcontainers.container`
+//! span, and pull, create, start, wait, exec, stop, remove and the other
+//! lifecycle operations run in `testcontainers.<operation>` spans with the
+//! image descriptor, the container id and the elapsed milliseconds
+//! (`elapsed_ms`) as fields.
+//! Container log lines can be forwarded as events under the container span
+//! with [`Container::trace_logs`](crate::Container::trace_logs).
+//!
+//! Without the feature, the helpers in this module compile to nothing.
+
+use std::future::Future;
+
+/// A span, or nothing without the `tracing` feature.
+#[derive(Debug, Clone)]
+pub(crate) struct Span {
+    #[cfg(feature = "tracing")]
+    inner: tracing::Span,
+}
+
+impl Span {
+    #[cfg(not(feature = "tracing"))]
+    pub(crate) fn disabled() -> Self {
+        Self {}
+    }
+
+    #[cfg(feature = "tracing")]
+    pub(crate) fn new(inner: tracing::Span) -> Self {
+        Self { inner }
+    }
+
+    #[cfg(feature = "tracing")]
+    pub(crate) fn inner(&self) -> &tracing::Span {
+        &self.inner
+    }
+
+    /// Records the id of the container once it is known.
+    pub(crate) fn record_id(&self, id: &str) {
+        #[cfg(feature = "tracing")]
+        self.inner.record("container.id", id);
+        #[cfg(not(feature = "tracing"))]
+        let _ = id;
+    }
+
+    /// Runs `future` inside the span and records its duration.
+    pub(crate) async fn run<F: Future>(&self, future: F) -> F::Output {
+        #[cfg(feature = "tracing")]
+        {
+            let start = std::time::Instant::now();
+            let output = tracing::Instrument::instrument(future, self.inner.clone()).await;
+            self.inner
+                .record("elapsed_ms", start.elapsed().as_millis() as u64);
+            output
+        }
+        #[cfg(not(feature = "tracing"))]
+        {
+            future.await
+        }
+    }
+}
+
+/// Creates an info level [`Span`] with an `elapsed_ms` field recorded by [`Span::run`].
+///
+/// Accepts the name and fields of [`tracing::info_span`], optionally preceded
+/// by `parent: <trace::Span>`.
+macro_rules! span {
+    (parent: $parent:expr, $name:literal $(, $($fields:tt)*)?) => {{
+        #[cfg(feature = "tracing")]
+        let span = $crate::trace::Span::new(tracing::info_span!(
+            parent: $parent.inner(),
+            $name,
+            elapsed_ms = tracing::field::Empty
+            $(, $($fields)*)?
+        ));
+        #[cfg(not(feature = "tracing"))]
+        let span = {
+            let _ = &$parent;
+            $crate::trace::Span::disabled()
+        };
+        span
+    }};
+    ($name:literal $(, $($fields:tt)*)?) => {{
+        #[cfg(feature = "tracing")]
+        let span = $crate::trace::Span::new(tracing::info_span!(
+            $name,
+            elapsed_ms = tracing::field::Empty
+            $(, $($fields)*)?
+        ));
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::trace::Span::disabled();
+        span
+    }};
+}
+
+pub(crate) use span;
+
+#[cfg(all(test, feature = "tracing", feature = "mock"))]
+mod tests {
+    use crate::client::mock::MockClient;
+    use crate::client::DockerClient;
+    use crate::{DockerImage, WaitFor};
+    use color_eyre::eyre;
+    use pretty_assertions::assert_eq;
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /// Messages of events with the name of their parent span.
+    type Events = Vec<(Option<&'static str>, String)>;
+
+    /// Records the names of created spans and the messages of events with their parent span.
+    #[derive(Default, Clone)]
+    struct Recorder {
+        spans: Arc<Mutex<Vec<&'static str>>>,
+        events: Arc<Mutex<Events>>,
+    }
+
+    struct Message(String);
+
+    impl Visit for Message {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() == "message" {
+                self.0 = format!("{:?}", value);
+            }
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(span.metadata().name());
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let parent = event
+                .parent()
+                .map(|id| self.spans.lock().unwrap()[id.into_u64() as usize - 1]);
+            let mut message = Message(String::new());
+            event.record(&mut message);
+            self.events.lock().unwrap().push((parent, message.0));
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[tokio::test]
+    async fn instruments_container_operations() -> eyre::Result<()> {
+        let recorder = Recorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+
+        let client = MockClient::new().with_stdout("redis", ["Ready to accept connections"]);
+        let image = DockerImage::new("redis").with_wait_for(WaitFor::message_on_stdout("Ready"));
+        let container = client.create(image).await?;
+        container.start().await?;
+        container.wait_until_ready().await?;
+        container.trace_logs().finished().await;
+        container.exec(["redis-cli", "ping"]).await?;
+        container.stop().await?;
+        container.rm().await?;
+
+        assert_eq!(
+            *recorder.spans.lock().unwrap(),
+            vec![
+                "testcontainers.create",
+                "testcontainers.container",
+                "testcontainers.start",
+                "testcontainers.wait",
+                "testcontainers.exec",
+                "testcontainers.stop",
+                "testcontainers.remove",
+            ]
+        );
+        assert!(recorder.events.lock().unwrap().contains(&(
+            Some("testcontainers.container"),
+            "Ready to accept connections".to_string()
+        )));
+        Ok(())
+    }
+}
@@ -0,0 +1,352 @@
+//! Named docker volumes that outlive single containers.
+//!
+//! A [`Volume`] is created with [`DockerClient::volume`] and removed when the
+//! handle is dropped, unless it is [kept](Volume::keep) or already existed.
+//! It can be mounted into any number of containers with [`Volume::mount`],
+//! so data persists across container restarts within a test.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use testcontainers_rs::client::{bollard::Client, DockerClient};
+//! use testcontainers_rs::DockerImage;
+//!
+//! let client = Client::new().await?;
+//! let volume = client.volume("pgdata").await?;
+//! volume.populate_from_dir("tests/fixtures/pgdata").await?;
+//! let image = DockerImage::new("postgres").with_mount(volume.mount("/var/lib/postgresql/data"));
+//! let container = client.create(image).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use super::client::DockerClient;
+use super::error::Operation;
+use super::mounts::{Mount, VolumeMount};
+use super::rt;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+/// The label set on every volume created by this crate.
+pub const LABEL: &str = "org.testcontainers.volume";
+
+/// A host directory could not be archived for [`Volume::populate_from_dir`].
+#[derive(thiserror::Error, Debug)]
+#[error("failed to archive {}", path.display())]
+pub struct ArchiveError {
+    pub path: PathBuf,
+    pub source: io::Error,
+}
+
+/// The configuration of a volume.
+#[must_use]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "record", derive(serde::Serialize, serde::Deserialize))]
+pub struct VolumeConfig {
+    pub name: String,
+    /// The volume driver, `local` by default.
+    pub driver: Option<String>,
+    pub driver_options: BTreeMap<String, String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+impl VolumeConfig {
+    pub fn new(name: impl Into<String>) -> Self {
+        let labels = [(LABEL.to_string(), "true".to_string())].into();
+        Self {
+            name: name.into(),
+            labels,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_driver(self, driver: impl Into<String>) -> Self {
+        Self {
+            driver: Some(driver.into()),
+            ..self
+        }
+    }
+
+    pub fn with_driver_option(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        let mut driver_options = self.driver_options;
+        driver_options.insert(key.into(), value.into());
+        Self {
+            driver_options,
+            ..self
+        }
+    }
+
+    pub fn with_label(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        let mut labels = self.labels;
+        labels.insert(key.into(), value.into());
+        Self { labels, ..self }
+    }
+}
+
+impl From<&str> for VolumeConfig {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for VolumeConfig {
+    fn from(name: String) -> Self {
+        Self::new(name)
+    }
+}
+
+/// A handle to a docker volume that removes the volume when dropped.
+pub struct Volume<C>
+where
+    C: DockerClient,
+{
+    name: String,
+    client: C,
+    keep: bool,
+    removed: bool,
+}
+
+impl<C> fmt::Debug for Volume<C>
+where
+    C: DockerClient,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Volume")
+            .field("name", &self.name)
+            .field("keep", &self.keep)
+            .finish()
+    }
+}
+
+impl<C> Volume<C>
+where
+    C: DockerClient,
+{
+    pub(crate) fn new(name: String, client: C) -> Self {
+        Self {
+            name,
+            client,
+            keep: false,
+            removed: false,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns a mount of the volume at `target` for [`DockerImage::with_mount`](crate::DockerImage::with_mount).
+    pub fn mount(&self, target: impl Into<String>) -> VolumeMount {
+        Mount::volume(self.name.clone(), target)
+    }
+
+    /// Keeps the volume when the handle is dropped.
+    #[must_use]
+    pub fn keep(mut self) -> Self {
+        self.keep = true;
+        self
+    }
+
+    /// Extracts a tar archive into the root of the volume.
+    pub async fn populate_from_tar(&self, archive: Vec<u8>) -> Result<(), crate::Error> {
+        self.client
+            .populate_volume(&self.name, archive)
+            .await
+            .map_err(|err| crate::Error::new(Operation::Volume, err))
+    }
+
+    /// Copies the content of a host directory into the root of the volume.
+    ///
+    /// A directory that cannot be archived fails with an [`ArchiveError`].
+    pub async fn populate_from_dir(&self, dir: impl AsRef<Path>) -> Result<(), crate::Error> {
+        let dir = dir.as_ref();
+        let archive = archive(dir).map_err(|source| {
+            let err = ArchiveError {
+                path: dir.to_path_buf(),
+                source,
+            };
+            crate::Error::other(Operation::Volume, err)
+        })?;
+        self.populate_from_tar(archive).await
+    }
+
+    /// Removes the volume, which fails while a container uses it.
+    ///
+    /// If removing fails, the volume is removed again when the handle is dropped.
+    pub async fn rm(mut self) -> Result<(), crate::Error> {
+        self.client
+            .rm_volume(&self.name)
+            .await
+            .map_err(|err| crate::Error::new(Operation::Volume, err))?;
+        self.removed = true;
+        Ok(())
+    }
+}
+
+impl<C> Drop for Volume<C>
+where
+    C: DockerClient,
+{
+    fn drop(&mut self) {
+        if self.removed || self.keep {
+            return;
+        }
+        if let Err(err) = rt::block_on(self.client.rm_volume(&self.name)) {
+            log::error!("failed to remove docker volume {}: {}", self.name, err);
+        }
+    }
+}
+
+/// Builds an uncompressed tar archive of the content of `dir`.
+///
+/// Entries are sorted and modification times are zeroed, so that the archive
+/// only depends on the content.
+pub fn archive(dir: &Path) -> io::Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(Vec::new());
+    append_dir(&mut builder, dir, Path::new(""))?;
+    builder.into_inner()
+}
+
+fn append_dir(builder: &mut tar::Builder<Vec<u8>>, dir: &Path, prefix: &Path) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(fs::DirEntry::file_name);
+    for entry in entries {
+        let path = prefix.join(entry.file_name());
+        let metadata = fs::symlink_metadata(entry.path())?;
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(file_mode(&metadata));
+        header.set_mtime(0);
+        header.set_size(0);
+        if metadata.file_type().is_symlink() {
+            header.set_entry_type(tar::EntryType::Symlink);
+            builder.append_link(&mut header, &path, fs::read_link(entry.path())?)?;
+        } else if metadata.is_dir() {
+            header.set_entry_type(tar::EntryType::Directory);
+            builder.append_data(&mut header, &path, io::empty())?;
+            append_dir(builder, &entry.path(), &path)?;
+        } else {
+            let content = fs::read(entry.path())?;
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(content.len() as u64);
+            builder.append_data(&mut header, &path, content.as_slice())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.is_dir() {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::archive;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+    use std::io::Read;
+
+    /// Returns the paths and contents of the entries of a tar archive.
+    fn entries(archive: &[u8]) -> std::io::Result<Vec<(String, Vec<u8>)>> {
+        let mut entries = Vec::new();
+        for entry in tar::Archive::new(archive).entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.to_string_lossy().into_owned();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            entries.push((path, content));
+        }
+        Ok(entries)
+    }
+
+    #[test]
+    fn archives_directory() -> color_eyre::eyre::Result<()> {
+        let dir =
+            std::env::temp_dir().join(format!("testcontainers-archive-{}", std::process::id()));
+        let nested = dir.join("conf.d").join("a".repeat(60)).join("b".repeat(60));
+        fs::create_dir_all(&nested)?;
+        fs::write(dir.join("init.sql"), "CREATE TABLE t (id int);")?;
+        fs::write(nested.join("deep.conf"), "x")?;
+        let archive = archive(&dir);
+        fs::remove_dir_all(&dir)?;
+        let archive = archive?;
+
+        let long = format!("conf.d/{}/{}", "a".repeat(60), "b".repeat(60));
+        assert_eq!(
+            entries(&archive)?,
+            vec![
+                ("conf.d".to_string(), vec![]),
+                (format!("conf.d/{}", "a".repeat(60)), vec![]),
+                (long.clone(), vec![]),
+                (format!("{}/deep.conf", long), b"x".to_vec()),
+                ("init.sql".to_string(), b"CREATE TABLE t (id int);".to_vec()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn volume_lifecycle() -> color_eyre::eyre::Result<()> {
+        use super::{VolumeConfig, LABEL};
+        use crate::client::mock::MockClient;
+        use crate::client::DockerClient;
+        use crate::DockerImage;
+
+        let client = MockClient::new();
+        let volume = client
+            .volume(VolumeConfig::new("pgdata").with_label("suite", "db"))
+            .await?;
+        let config = client.volume_config("pgdata").expect("volume is created");
+        assert_eq!(config.labels.get(LABEL).map(String::as_str), Some("true"));
+        assert_eq!(config.labels.get("suite").map(String::as_str), Some("db"));
+
+        volume.populate_from_tar(vec![0; 1024]).await?;
+        assert_eq!(client.volume_archives("pgdata"), vec![vec![0; 1024]]);
+
+        let image = DockerImage::new("postgres").with_mount(volume.mount("/data"));
+        let container = client.create(image).await?;
+        assert!(container
+            .image()
+            .mounts
+            .contains(&crate::mounts::Mount::volume("pgdata", "/data").into()));
+        drop(container);
+
+        drop(volume);
+        assert_eq!(client.volume_config("pgdata"), None);
+
+        let kept = client.volume("cache").await?.keep();
+        drop(kept);
+        assert!(client.volume_config("cache").is_some());
+
+        // a volume that existed before is not removed with the handle
+        drop(client.volume("cache").await?);
+        assert!(client.volume_config("cache").is_some());
+        Ok(())
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failed_removal_is_retried_on_drop() -> color_eyre::eyre::Result<()> {
+        use crate::client::mock::{MockClient, Operation};
+        use crate::client::DockerClient;
+
+        let client = MockClient::new();
+        let volume = client.volume("pgdata").await?;
+        client.inject_failure(Operation::Volume, "volume is in use");
+        let err = volume.rm().await.unwrap_err();
+        assert_eq!(err.operation(), crate::error::Operation::Volume);
+        assert_eq!(client.volume_config("pgdata"), None);
+        Ok(())
+    }
+}
@@ -1,4 +1,28 @@
-use tokio::time::Duration;
+use super::hooks::HookError;
+use super::logs::WaitError;
+use std::time::Duration;
+
+/// Error while waiting for the ready conditions of a container.
+#[derive(thiserror::Error, Debug)]
+pub enum Error<E>
+where
+    E: std::error::Error + 'static,
+{
+    #[error("failed to wait for log message")]
+    Message(#[from] WaitError),
+
+    #[error("failed to inspect container")]
+    Inspect(#[source] E),
+
+    #[error("healthcheck is not configured for the container")]
+    HealthcheckNotConfigured,
+
+    #[error("healthcheck reports unhealthy")]
+    Unhealthy,
+
+    #[error(transparent)]
+    Hook(HookError),
+}
 
 /// Represents a condition that needs to be met before a container is considered ready.
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -0,0 +1,64 @@
+#![cfg(all(feature = "mock", feature = "record"))]
+
+use color_eyre::eyre;
+use futures::TryStreamExt;
+use pretty_assertions::assert_eq;
+use std::time::{Duration, SystemTime};
+use testcontainers_rs::client::mock::MockClient;
+use testcontainers_rs::client::record::{RecordingClient, ReplayClient};
+use testcontainers_rs::client::DockerClient;
+use testcontainers_rs::logs::LogOptions;
+use testcontainers_rs::DockerImage;
+
+/// Starts two differently configured containers of the same image and
+/// returns what the test observed.
+async fn scenario<C: DockerClient>(client: &C) -> eyre::Result<Vec<String>> {
+    let primary = client
+        .create(DockerImage::new("redis").with_mapped_port(0, 6379))
+        .await?;
+    let replica = client
+        .create(
+            DockerImage::new("redis")
+                .with_mapped_port(0, 6379)
+                .with_env_var("REDIS_REPLICATION_MODE", "replica"),
+        )
+        .await?;
+    let mut observed = Vec::new();
+    for container in [&primary, &replica] {
+        container.start().await?;
+        let port = container.mapped_port_ipv4(6379).await?;
+        observed.push(format!("{} {:?}", container.id(), port));
+        let since = SystemTime::now() - Duration::from_secs(60 * 60);
+        let frames: Vec<_> = container
+            .logs(LogOptions::new().with_since(since))
+            .try_collect()
+            .await?;
+        observed.extend(frames.iter().map(|frame| frame.line().to_string()));
+    }
+    Ok(observed)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replays_recorded_cassette() -> eyre::Result<()> {
+    let path = std::env::temp_dir()
+        .join(format!("testcontainers-cassette-{}", std::process::id()))
+        .join("redis.json");
+    let recorded = {
+        let client = RecordingClient::new(
+            MockClient::new().with_stdout("redis", ["Ready to accept connections\n"]),
+            &path,
+        );
+        let observed = scenario(&client).await?;
+        client.save()?;
+        observed
+    };
+
+    let client = ReplayClient::load(&path)?;
+    let replayed = scenario(&client).await?;
+    std::fs::remove_dir_all(path.parent().unwrap())?;
+
+    assert_eq!(replayed, recorded);
+    assert_ne!(recorded[0], recorded[2]);
+    assert_eq!(client.remaining(), vec![]);
+    Ok(())
+}